@@ -0,0 +1,14 @@
+@ECHO OFF
+
+FOR %%H IN (bucket-compare.txt bucket-write.txt) do ECHO "" > %%H
+
+FOR %%F IN ("c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago15.small.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago16Small.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago1610Mil.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago16.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\ny19A.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\ny19B.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\SJ14.small.txt") DO (
+	FOR %%B IN (4 8 16) DO (
+		FOR %%D IN (Cuckoo NitroCuckoo) DO (
+			cargo run --release -- --file-path %%F --ds-type %%D --compare --bucket-size %%B >> bucket-compare.txt
+			FOR /L %%G IN (1,1,13) DO (
+				cargo run --release -- --file-path %%F --ds-type %%D --time-type WRITETIME --bucket-size %%B >> bucket-write.txt
+			)
+		)
+	)
+)
@@ -0,0 +1,17 @@
+@ECHO OFF
+
+FOR %%H IN (facs-compare.txt facs-write.txt) do ECHO "" > %%H
+
+FOR %%F IN ("c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago15.small.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago16Small.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago1610Mil.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago16.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\ny19A.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\ny19B.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\SJ14.small.txt") DO (
+	FOR %%W IN (HASH Cuckoo NitroHash) DO (
+		FOR %%P IN (NitroCMS CountSketch SpaceSaving) DO (
+			cargo run --release -- --file-path %%F --ds-type FACS --facs-window %%W --facs-permanent %%P --compare >> facs-compare.txt
+			FOR /L %%G IN (1,1,13) DO (
+				cargo run --release -- --file-path %%F --ds-type FACS --facs-window %%W --facs-permanent %%P --time-type WRITETIME >> facs-write.txt
+			)
+		)
+		FOR /L %%G IN (1,1,13) DO (
+			cargo run --release -- --file-path %%F --ds-type FACS --facs-window %%W --facs-background --time-type WRITETIME >> facs-write.txt
+		)
+	)
+)
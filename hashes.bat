@@ -0,0 +1,14 @@
+@ECHO OFF
+
+FOR %%H IN (hash-compare.txt hash-write.txt) do ECHO "" > %%H
+
+FOR %%F IN ("c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago15.small.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago16Small.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago1610Mil.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago16.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\ny19A.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\ny19B.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\SJ14.small.txt") DO (
+	FOR %%S IN (SipHash XxHash64 Xxh3 FxHash Tabulation Weak) DO (
+		FOR %%D IN (HASH CMS NitroCMS SpaceSaving Cuckoo NitroCuckoo) DO (
+			cargo run --release -- --file-path %%F --ds-type %%D --compare --hash %%S >> hash-compare.txt
+			FOR /L %%G IN (1,1,13) DO (
+				cargo run --release -- --file-path %%F --ds-type %%D --time-type WRITETIME --hash %%S >> hash-write.txt
+			)
+		)
+	)
+)
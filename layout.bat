@@ -0,0 +1,30 @@
+@ECHO OFF
+
+FOR %%H IN (CMS-layout.txt NitroCMS-layout.txt CMS-nested-layout.txt NitroCMS-nested-layout.txt) do ECHO "" > %%H
+
+FOR %%F IN ("c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago15.small.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago16Small.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago1610Mil.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\Chicago16.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\ny19A.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\ny19B.txt" "c:\users\user\Dropbox (Technion Dropbox)\traces\dataCounters\SJ14.small.txt") DO (
+	FOR /L %%G IN (1,1,13) DO (
+		cargo run --release -- --file-path %%F --ds-type CMS --time-type WRITETIME >> CMS-layout.txt
+	)
+	FOR /L %%G IN (1,1,13) DO (
+		cargo run --release -- --file-path %%F --ds-type CMS --time-type WRITETIME --nested-layout >> CMS-nested-layout.txt
+	)
+	FOR /L %%G IN (1,1,13) DO (
+		cargo run --release -- --file-path %%F --ds-type NitroCMS --time-type WRITETIME >> NitroCMS-layout.txt
+	)
+	FOR /L %%G IN (1,1,13) DO (
+		cargo run --release -- --file-path %%F --ds-type NitroCMS --time-type WRITETIME --nested-layout >> NitroCMS-nested-layout.txt
+	)
+	FOR /L %%G IN (1,1,13) DO (
+		cargo run --release -- --file-path %%F --ds-type CMS --time-type READTIME >> CMS-layout.txt
+	)
+	FOR /L %%G IN (1,1,13) DO (
+		cargo run --release -- --file-path %%F --ds-type CMS --time-type READTIME --nested-layout >> CMS-nested-layout.txt
+	)
+	FOR /L %%G IN (1,1,13) DO (
+		cargo run --release -- --file-path %%F --ds-type NitroCMS --time-type READTIME >> NitroCMS-layout.txt
+	)
+	FOR /L %%G IN (1,1,13) DO (
+		cargo run --release -- --file-path %%F --ds-type NitroCMS --time-type READTIME --nested-layout >> NitroCMS-nested-layout.txt
+	)
+)
@@ -24,6 +24,10 @@ restricts['OPTS-FULL'] = ['CMS', 'NitroCMS', 'CMS-NOMI', 'Cuckoo', 'NitroCuckoo'
 restricts['OPTS'] = ['CMS', 'CMS-NOMI', 'NitroCuckoo', 'NitroCuckoo-SMALL']
 restricts['NOMI'] = ['CMS', 'CMS-NOMI']
 restricts['NITRO'] = ['Cuckoo', 'NitroCuckoo', 'NitroCuckoo-SMALL']
+restricts['LAYOUT'] = ['CMS', 'CMS-NESTED', 'NitroCMS', 'NitroCMS-NESTED']
+restricts['BUCKETS'] = ['Cuckoo', 'Cuckoo-B8', 'Cuckoo-B16', 'NitroCuckoo', 'NitroCuckoo-B8', 'NitroCuckoo-B16']
+restricts['THREADS'] = ['HASH-T4', 'CMS-NOMI-T4', 'CMS-T4-LOCAL', 'NitroCMS-NOMI-T4', 'NitroCMS-T4-LOCAL', 'Cuckoo-T4', 'NitroCuckoo-T4']
+restricts['FACS'] = ['FACS-HASH-NitroCMS-W10000', 'FACS-HASH-CountSketch-W10000', 'FACS-HASH-SpaceSaving-W10000', 'FACS-Cuckoo-NitroCMS-W10000', 'FACS-NitroHash-NitroCMS-W10000', 'NitroCMS']
 args = parser.parse_args()
 if not(args.restrict in restricts.keys()):
     print('Found an unknown restrict parameter ' + args.restrict + ' - using all algorithms.')
@@ -44,6 +48,15 @@ pmwavgrelerr_text = re.compile('PMW AVGRELERR .*')
 totalmemory_text = re.compile('Total memory: .*')
 items_text = re.compile('Number of items: .*')
 time_text = re.compile('TIMEms .*')
+hh_text = re.compile('HH .*')
+topk_text = re.compile('TOPK .*')
+flowerrq_text = re.compile('Flow (ABSERR|RELERR) .*')
+bound_text = re.compile('BOUND .*')
+checkpoint_text = re.compile('CHECKPOINT .*')
+decayflows_text = re.compile('DECAY FLOWS .*')
+epoch_text = re.compile('EPOCH .*')
+changers_text = re.compile('CHANGERS .*')
+cardinality_text = re.compile('CARDINALITY .*')
 end_text = re.compile('END .*')
 
 shortened = {}
@@ -63,6 +76,8 @@ algcolors["Cuckoo"] = 'grey'
 algcolors["NitroCuckoo"] = 'pink'
 algcolors["NitroCuckoo-SMALL"] = 'olive'
 algcolors["CMS-NOMI"] = 'brown'
+algcolors["CMS-NESTED"] = 'lime'
+algcolors["NitroCMS-NESTED"] = 'navy'
 
 altylegend={}
 altylegend["SPACE"]="Space (Bytes)"
@@ -159,6 +174,56 @@ def time_parser(line):
     generic_result_parser(line, "TIME(ms)")
 
 
+def hh_parser(line):
+    parts = line.split()
+    generic_result_parser(line, "HH-" + parts[1] + "-" + parts[2])
+
+
+def topk_parser(line):
+    parts = line.split()
+    generic_result_parser(line, "TOPK-" + parts[1] + "-" + parts[2])
+
+
+def flowerrq_parser(line):
+    parts = line.split()
+    generic_result_parser(line, "FLOW-" + parts[1] + "-" + parts[2])
+
+
+def bound_parser(line):
+    generic_result_parser(line, "BOUND-" + line.split()[1])
+
+
+def checkpoint_parser(line):
+    parts = line.split()
+    fields = {"ITEMS": parts[1]}
+    fields.update(zip(parts[2::2], parts[3::2]))
+    for name, value in fields.items():
+        res_name = "CHECKPOINT-" + name
+        logging.debug("%s:%s", res_name, value)
+        if res_name not in results[trace][operation][data_structure]:
+            results[trace][operation][data_structure][res_name] = [value]
+        else:
+            results[trace][operation][data_structure][res_name].append(value)
+
+
+def decayflows_parser(line):
+    generic_result_parser(line, "DECAY-FLOWS")
+
+
+def epoch_parser(line):
+    parts = line.split()
+    for name, value in zip(parts[2::2], parts[3::2]):
+        generic_result_parser(value, "EPOCH-" + name)
+
+
+def changers_parser(line):
+    generic_result_parser(line, "CHANGERS-" + line.split()[2])
+
+
+def cardinality_parser(line):
+    generic_result_parser(line, "CARDINALITY-" + line.split()[1])
+
+
 def items_parser(line):
     parts = line.split()
     items = parts[3]
@@ -193,7 +258,16 @@ my_parsers ={
     pmwavgrelerr_text: pmwavgrelerr_parser,
     totalmemory_text: totalmemory_parser,
     items_text: items_parser,
-    time_text: time_parser
+    time_text: time_parser,
+    hh_text: hh_parser,
+    topk_text: topk_parser,
+    flowerrq_text: flowerrq_parser,
+    bound_text: bound_parser,
+    checkpoint_text: checkpoint_parser,
+    decayflows_text: decayflows_parser,
+    epoch_text: epoch_parser,
+    changers_text: changers_parser,
+    cardinality_text: cardinality_parser
 }
 
 def process(line):
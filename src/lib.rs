@@ -1,26 +1,51 @@
 use std::fs;
+use std::fmt;
+use std::io::{BufWriter, Write};
 use std::time::{Instant, Duration};
+use std::hint::black_box;
+use std::thread;
+use dashmap::DashMap;
 //use std::env;
 use std::error::Error;
 use clap::Parser;
 use std::str::FromStr;
 use std::net::Ipv4Addr;
 //use amadeus_streaming::CountMinSketch;
-use std::collections::{HashMap,hash_map::DefaultHasher};
-use std::hash::{Hasher, Hash};
+use std::collections::{HashMap,HashSet,hash_map::DefaultHasher};
+use std::hash::{BuildHasherDefault, Hasher, Hash};
 use num_traits::abs;
-//use std::cmp::max;
+use twox_hash::XxHash64;
+use std::cmp::{max, Ordering};
 //use std::mem::size_of;
 
 pub mod more_streaming;
+pub mod pcap;
 
 use crate::more_streaming::nitro_cms::NitroCMS;
+use crate::more_streaming::nitro_cms_nested::NestedNitroCMS;
 use crate::more_streaming::space_saving::SpaceSaving;
 use crate::more_streaming::nitro_hash::NitroHash;
 use crate::more_streaming::cuckoo::CuckooCountingFilter;
 use crate::more_streaming::nitro_cuckoo::NitroCuckoo;
-use crate::more_streaming::facs::FACS;
-use crate::more_streaming::traits::{ItemIncrement,ItemQuery,PrintMemoryInfo};
+use crate::more_streaming::facs::{BackgroundFACS, CuckooWindow, FACS};
+use crate::more_streaming::count_sketch::CountSketch;
+use crate::more_streaming::wcss::WCSS;
+use crate::more_streaming::sliding_cms::SlidingNitroCMS;
+use crate::more_streaming::decay::{Decay, ExactDecay};
+use crate::more_streaming::decayed_cms::DecayedNitroCMS;
+use crate::more_streaming::decayed_space_saving::DecayedSpaceSaving;
+use crate::more_streaming::change_detection::EpochManager;
+use crate::more_streaming::reversible_sketch::ReversibleSketch;
+use crate::more_streaming::hyperloglog::HyperLogLog;
+use crate::more_streaming::adaptive::AdaptiveRate;
+use crate::more_streaming::latency::LatencyHistogram;
+use crate::more_streaming::hashing::{SketchHasher, Xxh3Hasher, FxHasher, TabulationHasher, WeakHasher};
+use crate::more_streaming::atomic_nitro_cms::AtomicNitroCMS;
+use crate::more_streaming::striped::Striped;
+use crate::more_streaming::thread_local_merge::ThreadLocalMerge;
+use crate::more_streaming::fp_dash::FingerprintDash;
+use crate::more_streaming::median;
+use crate::more_streaming::traits::{ItemIncrement,ItemQuery,ItemEstimate,PrintMemoryInfo,PrintSamplingInfo,DumpContents,ConcurrentIncrement,FacsWindow,FacsPermanent,TimedIncrement,LinearSketch,ChangeEstimate};
 //use crate::more_streaming::f64_to_usize;
 
 #[cfg(feature = "stats")]
@@ -32,8 +57,32 @@ use cap::Cap;
 #[global_allocator]
 static ALLOCATOR: Cap<alloc::System> = Cap::new(alloc::System, usize::max_value());
 
+/// The result of a measurement that may be a timing measurement or an accuracy comparison
+enum Measurement {
+    /// The elapsed time of a timing measurement
+    Time(Duration),
+    /// The number of flows beyond their error bound after an accuracy comparison (see `print_bounds`)
+    Accuracy(usize),
+}
+
+impl Measurement {
+    fn time(self) -> Duration {
+        match self {
+            Measurement::Time(elapsed) => elapsed,
+            Measurement::Accuracy(_) => panic!("timing measurements report their time"),
+        }
+    }
+
+    fn violations(self) -> usize {
+        match self {
+            Measurement::Accuracy(violations) => violations,
+            Measurement::Time(_) => panic!("accuracy comparisons report their bound violations"),
+        }
+    }
+}
+
 #[derive(Debug,Clone)]
-pub enum DsType { HASH, CMS, NitroCMS, FPDASH, SpaceSaving, NitroHash, Cuckoo, NitroCuckoo, FACS }
+pub enum DsType { HASH, CMS, NitroCMS, FPDASH, SpaceSaving, NitroHash, Cuckoo, NitroCuckoo, FACS, HyperLogLog }
 
 impl FromStr for DsType {
     type Err = String;
@@ -49,11 +98,111 @@ impl FromStr for DsType {
             "Cuckoo" => Ok(DsType::Cuckoo),
             "NitroCuckoo" => Ok(DsType::NitroCuckoo),
             "FACS" => Ok(DsType::FACS),
-            _ => Err(format!("Unrecognized DsType {s}: try HASH, CMS, NitroCMS, SpaceSaving, FDDASH, NitroHash, Cuckoo, NitroCuckoo or FACS"))
+            "HyperLogLog" => Ok(DsType::HyperLogLog),
+            _ => Err(format!("Unrecognized DsType {s}: try HASH, CMS, NitroCMS, SpaceSaving, FPDASH, NitroHash, Cuckoo, NitroCuckoo, FACS or HyperLogLog"))
+        }
+    }
+}
+
+
+impl DsType {
+    /// The hash function used when --hash is not given: the CMS based structures were written for xxh3,
+    /// while the hash tables and cuckoo filters used the std SipHash
+    pub fn default_hash(&self) -> HashType {
+        match self {
+            DsType::CMS | DsType::NitroCMS | DsType::FACS | DsType::HyperLogLog => HashType::Xxh3,
+            _ => HashType::SipHash,
+        }
+    }
+}
+
+#[derive(Debug,PartialEq,Clone)]
+pub enum HashType { SipHash, XxHash64, Xxh3, FxHash, Tabulation, Weak }
+
+impl FromStr for HashType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SipHash" => Ok(HashType::SipHash),
+            "XxHash64" => Ok(HashType::XxHash64),
+            "Xxh3" => Ok(HashType::Xxh3),
+            "FxHash" => Ok(HashType::FxHash),
+            "Tabulation" => Ok(HashType::Tabulation),
+            "Weak" => Ok(HashType::Weak),
+            _ => Err(format!("Unrecognized HashType {s}: try SipHash, XxHash64, Xxh3, FxHash, Tabulation or Weak"))
+        }
+    }
+}
+
+/// The structure counting the items of the current FACS window
+#[derive(Debug,PartialEq,Clone)]
+pub enum FacsWindowType { HASH, Cuckoo, NitroHash }
+
+impl FromStr for FacsWindowType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HASH" => Ok(FacsWindowType::HASH),
+            "Cuckoo" => Ok(FacsWindowType::Cuckoo),
+            "NitroHash" => Ok(FacsWindowType::NitroHash),
+            _ => Err(format!("Unrecognized FacsWindowType {s}: try HASH, Cuckoo or NitroHash"))
+        }
+    }
+}
+
+impl fmt::Display for FacsWindowType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// The structure accumulating the counts of the past FACS windows
+#[derive(Debug,PartialEq,Clone)]
+pub enum FacsPermanentType { NitroCMS, CountSketch, SpaceSaving }
+
+impl FromStr for FacsPermanentType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "NitroCMS" => Ok(FacsPermanentType::NitroCMS),
+            "CountSketch" => Ok(FacsPermanentType::CountSketch),
+            "SpaceSaving" => Ok(FacsPermanentType::SpaceSaving),
+            _ => Err(format!("Unrecognized FacsPermanentType {s}: try NitroCMS, CountSketch or SpaceSaving"))
+        }
+    }
+}
+
+impl fmt::Display for FacsPermanentType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// The linear sketch of the heavy changers detection
+#[derive(Debug,PartialEq,Clone)]
+pub enum ChangeSketchType { NitroCMS, CountSketch, Reversible }
+
+impl FromStr for ChangeSketchType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "NitroCMS" => Ok(ChangeSketchType::NitroCMS),
+            "CountSketch" => Ok(ChangeSketchType::CountSketch),
+            "Reversible" => Ok(ChangeSketchType::Reversible),
+            _ => Err(format!("Unrecognized ChangeSketchType {s}: try NitroCMS, CountSketch or Reversible"))
         }
     }
 }
 
+impl fmt::Display for ChangeSketchType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
 
 #[derive(Debug,PartialEq,Clone)]
 pub enum TimeType { READTIME, WRITETIME, RWTIME }
@@ -81,7 +230,7 @@ impl ToString for TimeType {
     }
 }
 
-#[derive(Parser,Debug)]
+#[derive(Parser,Debug,Clone)]
 #[clap(author="Roy Friedman", version, about="Banchmarking frequency sketches")]
 pub struct Config {
     #[clap(short, long)]
@@ -110,6 +259,336 @@ pub struct Config {
     pub compare : bool,
     #[clap(long, default_value_t = false)]
     pub compact : bool,
+    #[clap(long)]
+    pub dump : Option<String>,
+    #[clap(long, default_value_t = false)]
+    pub adaptive : bool,
+    #[clap(long, default_value_t = 65536)]
+    pub adaptive_volume : u64,
+    #[clap(long)]
+    pub time_budget_ns : Option<u64>,
+    #[clap(long, default_value_t = false)]
+    pub always_correct : bool,
+    #[clap(long, default_value_t = false)]
+    pub element_sampling : bool,
+    #[clap(long, default_value_t = false)]
+    pub nested_layout : bool,
+    #[clap(long)]
+    pub hash : Option<HashType>,
+    #[clap(long, default_value_t = 1)]
+    pub batch_size : usize,
+    #[clap(long)]
+    pub bucket_size : Option<usize>,
+    #[clap(long, default_value_t = 1)]
+    pub threads : usize,
+    #[clap(long, default_value_t = false)]
+    pub thread_local : bool,
+    #[clap(long, default_value_t = 65536)]
+    pub merge_interval : usize,
+    #[clap(long)]
+    pub facs_window : Option<FacsWindowType>,
+    #[clap(long)]
+    pub facs_permanent : Option<FacsPermanentType>,
+    #[clap(long)]
+    pub facs_window_size : Option<usize>,
+    #[clap(long, default_value_t = false)]
+    pub facs_background : bool,
+    #[clap(long, default_value_t = false)]
+    pub latency : bool,
+    #[clap(long, default_value_t = false)]
+    pub warmup : bool,
+    #[clap(long, default_value_t = 1)]
+    pub trials : usize,
+    // set for the discarded warm-up run, which prints nothing about its measurement
+    #[clap(skip)]
+    pub quiet : bool,
+    #[clap(long, value_delimiter = ',')]
+    pub hh_thresholds : Vec<f64>,
+    #[clap(long, value_delimiter = ',')]
+    pub top_k : Vec<usize>,
+    #[clap(long)]
+    pub errors_csv : Option<String>,
+    #[clap(long)]
+    pub checkpoint_interval : Option<usize>,
+    #[clap(long, default_value_t = false)]
+    pub log_checkpoints : bool,
+    #[clap(long)]
+    pub window : Option<usize>,
+    #[clap(long)]
+    pub window_panes : Option<usize>,
+    #[clap(long)]
+    pub half_life : Option<f64>,
+    #[clap(long)]
+    pub halving_period : Option<f64>,
+    #[clap(long, default_value_t = false)]
+    pub float_counters : bool,
+    #[clap(long, default_value_t = false)]
+    pub timestamps : bool,
+    #[clap(long)]
+    pub epoch : Option<f64>,
+    #[clap(long)]
+    pub heavy_changers : Option<f64>,
+    #[clap(long)]
+    pub change_sketch : Option<ChangeSketchType>,
+}
+
+impl Config {
+    /// Reject combinations of options that the chosen structure cannot honor, rather than silently ignoring them
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.sample > 0.0 && self.sample <= 1.0) {
+            return Err(format!("--sample must be in (0,1], got {}", self.sample));
+        }
+        let facs = matches!(self.ds_type, DsType::FACS);
+        if self.avoid_mi && !matches!(self.ds_type, DsType::CMS | DsType::NitroCMS)
+            && !(facs && self.facs_permanent() == FacsPermanentType::NitroCMS) {
+            return Err(format!("--avoid-mi only applies to CMS, NitroCMS and FACS with a NitroCMS permanent sketch, not {:?}", self.ds_type));
+        }
+        if self.rap && !matches!(self.ds_type, DsType::SpaceSaving)
+            && !(facs && self.facs_permanent() == FacsPermanentType::SpaceSaving) {
+            return Err(format!("--rap only applies to SpaceSaving and FACS with a SpaceSaving permanent sketch, not {:?}", self.ds_type));
+        }
+        if !facs && (self.facs_window.is_some() || self.facs_permanent.is_some() || self.facs_window_size.is_some()) {
+            return Err(format!("--facs-window, --facs-permanent and --facs-window-size only apply to FACS, not {:?}", self.ds_type));
+        }
+        if self.facs_background && !facs {
+            return Err(format!("--facs-background only applies to FACS, not {:?}", self.ds_type));
+        }
+        if self.facs_window_size == Some(0) {
+            return Err("--facs-window-size must be at least 1".to_string());
+        }
+        if self.compact && !matches!(self.ds_type, DsType::NitroCuckoo) {
+            return Err(format!("--compact only applies to NitroCuckoo, not {:?}", self.ds_type));
+        }
+        if self.adaptive && !matches!(self.ds_type, DsType::NitroCMS | DsType::NitroHash | DsType::NitroCuckoo) {
+            return Err(format!("--adaptive only applies to NitroCMS, NitroHash and NitroCuckoo, not {:?}", self.ds_type));
+        }
+        if self.always_correct && !matches!(self.ds_type, DsType::NitroCMS) {
+            return Err(format!("--always-correct only applies to NitroCMS, not {:?}", self.ds_type));
+        }
+        if self.adaptive && self.always_correct {
+            return Err("--adaptive and --always-correct are mutually exclusive sampling schedules".to_string());
+        }
+        if self.element_sampling && (!matches!(self.ds_type, DsType::NitroCMS) || self.avoid_mi) {
+            return Err("--element-sampling only applies to NitroCMS with conservative update (without --avoid-mi)".to_string());
+        }
+        if self.element_sampling && (self.window.is_some() || self.decay().is_some() || self.nested_layout || self.threads > 1) {
+            return Err("--element-sampling does not support --window, decayed counts, --nested-layout or --threads".to_string());
+        }
+        if self.nested_layout && !matches!(self.ds_type, DsType::CMS | DsType::NitroCMS) {
+            return Err(format!("--nested-layout only applies to CMS and NitroCMS, not {:?}", self.ds_type));
+        }
+        if self.batch_size == 0 {
+            return Err("--batch-size must be at least 1".to_string());
+        }
+        if self.batch_size > 1 && self.compare {
+            return Err("--batch-size only applies to timing measurements, not to --compare".to_string());
+        }
+        if self.batch_size > 1 && !self.batched_inserts() {
+            return Err(format!("--batch-size only applies to the structures that hash and prefetch a whole batch: Cuckoo, NitroCuckoo, CMS and NitroCMS (without --window, decayed counts, --nested-layout, --adaptive, --always-correct or a sampled conservative update), not {:?}", self.ds_type));
+        }
+        if let Some(bucket_size) = self.bucket_size {
+            if !matches!(bucket_size, 4 | 8 | 16) {
+                return Err(format!("--bucket-size must be 4, 8 or 16, got {}", bucket_size));
+            }
+            if !matches!(self.ds_type, DsType::Cuckoo | DsType::NitroCuckoo) {
+                return Err(format!("--bucket-size only applies to Cuckoo and NitroCuckoo, not {:?}", self.ds_type));
+            }
+        }
+        if !(1..=64).contains(&self.fp_size) {
+            return Err(format!("--fp-size must be between 1 and 64 bits, got {}", self.fp_size));
+        }
+        if self.max_size == 0 {
+            return Err("--max-size must be at least 1".to_string());
+        }
+        if self.threads == 0 {
+            return Err("--threads must be at least 1".to_string());
+        }
+        if self.threads > 1 && !matches!(self.ds_type, DsType::HASH | DsType::FPDASH | DsType::CMS | DsType::NitroCMS | DsType::Cuckoo | DsType::NitroCuckoo) {
+            return Err(format!("--threads only applies to HASH, FPDASH, CMS, NitroCMS, Cuckoo and NitroCuckoo, not {:?}", self.ds_type));
+        }
+        if self.threads > 1 && (self.adaptive || self.always_correct || self.nested_layout || self.batch_size > 1) {
+            return Err("--threads does not support --adaptive, --always-correct, --nested-layout or --batch-size".to_string());
+        }
+        if self.thread_local && (self.threads == 1 || !matches!(self.ds_type, DsType::CMS | DsType::NitroCMS)) {
+            return Err("--thread-local only applies to CMS and NitroCMS with --threads above 1".to_string());
+        }
+        if self.threads > 1 && !self.thread_local && matches!(self.ds_type, DsType::CMS | DsType::NitroCMS) && !self.avoid_mi {
+            return Err("the shared (atomic) CMS and NitroCMS do not support conservative update: use --avoid-mi or --thread-local".to_string());
+        }
+        if self.latency && (self.compare || self.threads > 1) {
+            return Err("--latency only applies to single threaded timing measurements, not to --compare or --threads".to_string());
+        }
+        if self.trials == 0 {
+            return Err("--trials must be at least 1".to_string());
+        }
+        if self.compare && (self.warmup || self.trials > 1) {
+            return Err("--warmup and --trials only apply to timing measurements, not to --compare".to_string());
+        }
+        if let Some(phi) = self.hh_thresholds.iter().find(|phi| !(**phi > 0.0 && **phi <= 1.0)) {
+            return Err(format!("--hh-thresholds must be in (0,1], got {}", phi));
+        }
+        if !self.hh_thresholds.is_empty() && !self.compare {
+            return Err("--hh-thresholds only applies to --compare".to_string());
+        }
+        if self.top_k.contains(&0) {
+            return Err("--top-k must be at least 1".to_string());
+        }
+        if !self.top_k.is_empty() && !self.compare {
+            return Err("--top-k only applies to --compare".to_string());
+        }
+        if self.errors_csv.is_some() && !self.compare {
+            return Err("--errors-csv only applies to --compare".to_string());
+        }
+        if self.checkpoint_interval == Some(0) {
+            return Err("--checkpoint-interval must be at least 1".to_string());
+        }
+        if self.checkpoint_interval.is_some() && self.log_checkpoints {
+            return Err("--checkpoint-interval and --log-checkpoints are exclusive".to_string());
+        }
+        if (self.checkpoint_interval.is_some() || self.log_checkpoints) && (!self.compare || self.threads > 1 || matches!(self.ds_type, DsType::HASH)) {
+            return Err("--checkpoint-interval and --log-checkpoints only apply to single threaded --compare of the approximate structures".to_string());
+        }
+        if let Some(window) = self.window {
+            let windowed = matches!(self.ds_type, DsType::SpaceSaving | DsType::CMS | DsType::NitroCMS)
+                || (facs && self.facs_permanent() == FacsPermanentType::NitroCMS);
+            if !windowed {
+                return Err(format!("--window only applies to SpaceSaving, CMS, NitroCMS and FACS with a NitroCMS permanent sketch, not {:?}", self.ds_type));
+            }
+            if self.rap || self.adaptive || self.always_correct || self.nested_layout || self.threads > 1 {
+                return Err("--window does not support --rap, --adaptive, --always-correct, --nested-layout or --threads".to_string());
+            }
+            if self.window_panes() == 0 || window < self.window_panes() {
+                return Err(format!("--window must be at least --window-panes ({}), which must be at least 1", self.window_panes()));
+            }
+        } else if self.window_panes.is_some() {
+            return Err("--window-panes only applies to --window".to_string());
+        }
+        if self.merge_interval == 0 {
+            return Err("--merge-interval must be at least 1".to_string());
+        }
+        if self.nested_layout && (self.adaptive || self.always_correct) {
+            return Err("--nested-layout only supports a fixed sampling probability".to_string());
+        }
+        if self.nested_layout && (self.compare || self.latency || self.dump.is_some()) {
+            return Err("--nested-layout only applies to timing measurements, without --latency or --dump".to_string());
+        }
+        if let Some(time) = self.half_life.iter().chain(&self.halving_period).find(|time| time.is_nan() || **time <= 0.0) {
+            return Err(format!("--half-life and --halving-period must be positive, got {}", time));
+        }
+        if self.half_life.is_some() && self.halving_period.is_some() {
+            return Err("--half-life and --halving-period are exclusive".to_string());
+        }
+        if self.decay().is_some() {
+            if !matches!(self.ds_type, DsType::CMS | DsType::NitroCMS | DsType::SpaceSaving) {
+                return Err(format!("--half-life and --halving-period only apply to CMS, NitroCMS and SpaceSaving, not {:?}", self.ds_type));
+            }
+            if self.window.is_some() || self.rap || self.adaptive || self.always_correct || self.nested_layout || self.threads > 1 {
+                return Err("decayed counts do not support --window, --rap, --adaptive, --always-correct, --nested-layout or --threads".to_string());
+            }
+            if !self.hh_thresholds.is_empty() || !self.top_k.is_empty() || self.errors_csv.is_some() || self.checkpoint_interval.is_some() || self.log_checkpoints {
+                return Err("decayed counts do not support --hh-thresholds, --top-k, --errors-csv or checkpoints".to_string());
+            }
+        }
+        if self.float_counters && (self.decay().is_none() || !matches!(self.ds_type, DsType::CMS | DsType::NitroCMS)) {
+            return Err("--float-counters only applies to decayed CMS and NitroCMS".to_string());
+        }
+        if self.timestamps && self.decay().is_some() && self.latency {
+            return Err("decayed counts over --timestamps do not support --latency".to_string());
+        }
+        if let Some(epoch) = self.epoch {
+            if !self.timestamps {
+                return Err("--epoch needs --timestamps".to_string());
+            }
+            if epoch.is_nan() || epoch <= 0.0 {
+                return Err(format!("--epoch must be positive, got {}", epoch));
+            }
+            if self.dump.is_some() || self.errors_csv.is_some() {
+                return Err("--epoch does not support --dump or --errors-csv".to_string());
+            }
+        }
+        if let Some(phi) = self.heavy_changers {
+            if phi.is_nan() || phi <= 0.0 || phi > 1.0 {
+                return Err(format!("--heavy-changers must be in (0,1], got {}", phi));
+            }
+            if !self.compare || self.epoch.is_none() {
+                return Err("--heavy-changers needs --compare and --epoch".to_string());
+            }
+            if !matches!(self.ds_type, DsType::CMS | DsType::NitroCMS) {
+                return Err(format!("--heavy-changers only applies to CMS and NitroCMS, not {:?}", self.ds_type));
+            }
+            if self.change_sketch() == ChangeSketchType::NitroCMS && !self.avoid_mi {
+                return Err("--heavy-changers needs a linear NitroCMS, without conservative update: add --avoid-mi".to_string());
+            }
+            if self.change_sketch() != ChangeSketchType::NitroCMS && !matches!(self.ds_type, DsType::CMS) {
+                return Err(format!("--change-sketch {} does not sample, use --ds-type CMS", self.change_sketch()));
+            }
+            if self.window.is_some() || self.decay().is_some() || self.adaptive || self.always_correct || self.nested_layout || self.threads > 1 {
+                return Err("--heavy-changers does not support --window, decayed counts, --adaptive, --always-correct, --nested-layout or --threads".to_string());
+            }
+            if !self.hh_thresholds.is_empty() || !self.top_k.is_empty() || self.checkpoint_interval.is_some() || self.log_checkpoints {
+                return Err("--heavy-changers does not support --hh-thresholds, --top-k or checkpoints".to_string());
+            }
+        } else if self.change_sketch.is_some() {
+            return Err("--change-sketch only applies to --heavy-changers".to_string());
+        }
+        if matches!(self.ds_type, DsType::HyperLogLog) {
+            if self.error.is_nan() || self.error <= 0.0 || self.error >= 1.0 {
+                return Err(format!("the --error of HyperLogLog (its relative standard error) must be in (0,1), got {}", self.error));
+            }
+            if self.time_type != TimeType::WRITETIME || self.latency || self.batch_size > 1 {
+                return Err("HyperLogLog is only timed by WRITETIME, without --latency or --batch-size".to_string());
+            }
+            if !self.hh_thresholds.is_empty() || !self.top_k.is_empty() || self.errors_csv.is_some() || self.dump.is_some() || self.checkpoint_interval.is_some() || self.log_checkpoints {
+                return Err("HyperLogLog does not count flows: --hh-thresholds, --top-k, --errors-csv, --dump and checkpoints do not apply".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the structure inserts a batch (--batch-size) by hashing it first and prefetching its counters or buckets,
+    /// rather than one item at a time
+    fn batched_inserts(&self) -> bool {
+        match self.ds_type {
+            DsType::Cuckoo | DsType::NitroCuckoo => true,
+            DsType::CMS | DsType::NitroCMS => self.window.is_none() && self.decay().is_none() && !self.nested_layout
+                && !self.adaptive && !self.always_correct
+                && (matches!(self.ds_type, DsType::CMS) || self.avoid_mi || self.sample == 1.0),
+            _ => false,
+        }
+    }
+
+    /// The structure of the FACS windows (--facs-window, HASH by default)
+    fn facs_window(&self) -> FacsWindowType {
+        self.facs_window.clone().unwrap_or(FacsWindowType::HASH)
+    }
+
+    /// The sketch of the past FACS windows (--facs-permanent, NitroCMS by default)
+    fn facs_permanent(&self) -> FacsPermanentType {
+        self.facs_permanent.clone().unwrap_or(FacsPermanentType::NitroCMS)
+    }
+
+    /// The number of items of a FACS window (--facs-window-size, 10000 by default)
+    fn facs_window_size(&self) -> usize {
+        self.facs_window_size.unwrap_or(10000)
+    }
+
+    /// The number of panes of a sliding window NitroCMS (--window-panes, 8 by default)
+    fn window_panes(&self) -> usize {
+        self.window_panes.unwrap_or(8)
+    }
+
+    /// The sketch of the heavy changers (--change-sketch, NitroCMS by default)
+    fn change_sketch(&self) -> ChangeSketchType {
+        self.change_sketch.clone().unwrap_or(ChangeSketchType::NitroCMS)
+    }
+
+    /// The decay of the counts requested by --half-life or --halving-period (in items)
+    fn decay(&self) -> Option<Decay> {
+        if let Some(half_life) = self.half_life {
+            return Some(Decay::Forward { half_life });
+        }
+        self.halving_period.map(|period| Decay::Halving { period })
+    }
 }
 
 #[derive(Hash,PartialEq,Eq,Debug,Clone,Copy)]
@@ -135,48 +614,68 @@ pub fn id_from_line(line: &str) -> Result<FlowId, Box<dyn Error>> {
     Ok(FlowId { srcip, dstip })
 }
 
-// TODO - fix fpdash - currently it is not interesting
-fn fpdash_run(_config: Config, _processed: Vec<FlowId>) -> Duration {
-//    let num : usize = 2_usize.pow(config.fp_size.into());
-//    let counts = DashMap::with_capacity(num);
-    let start = Instant::now();
-//    for id in &processed {
-//        // id.hash(&mut s);
-//        //let id : s.finish();
-//        let mut s = DefaultHasher::new();
-//        id.hash(&mut s);
-//        let id = s.finish();
-//        if let Some(mut count) = counts.get_mut(&id) {
-//            *count+=1;
-//        } else {
-//            counts.insert(id,1);
-//        }
-//        if config.time_type == TimeType::RWTIME {
-//            counts.get_mut(&id);
-//        }
-//    }
-//    if config.time_type == TimeType::READTIME {
-//        start = Instant::now();
-//        for id in &processed {
-//            let mut s = DefaultHasher::new();
-//            id.hash(&mut s);
-//            let id = s.finish();
-//            counts.get_mut(&id);
-//        }
-//    }
-//    if config.verbose {
-//        println!("COUNTS are {:#?}", counts);
-//    }
-    return start.elapsed();
+/// The timestamp (in seconds) of a trace line: the column following the addresses, possibly within brackets
+pub fn timestamp_from_line(line: &str) -> Option<f64> {
+    let column = line.split_whitespace().nth(8)?;
+    column.trim_start_matches('[').trim_end_matches(']').parse().ok().filter(|time: &f64| time.is_finite())
+}
+
+/// A flow as 64 bits, the source address in the high bits, for the structures of integer keys
+impl From<FlowId> for u64 {
+    fn from(id: FlowId) -> u64 {
+        (u64::from(u32::from(id.srcip)) << 32) | u64::from(u32::from(id.dstip))
+    }
+}
+
+impl From<u64> for FlowId {
+    fn from(key: u64) -> FlowId {
+        FlowId { srcip: Ipv4Addr::from((key >> 32) as u32), dstip: Ipv4Addr::from(key as u32) }
+    }
+}
+
+impl fmt::Display for FlowId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.srcip, self.dstip)
+    }
 }
 
-fn hash_run(config: Config, processed: Vec<FlowId>) -> Duration {
-    let counts = HashMap::new();
+/// Write the contents of a structure to a file, one entry per line, sorted by decreasing estimated count
+fn write_dump<Q: DumpContents>(counts: &Q, path: &str) -> Result<(), Box<dyn Error>> {
+    let mut entries = counts.dump_contents();
+    entries.sort_by(|(_, a), (_, b)| b.cmp(a));
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    for (label, val) in entries {
+        writeln!(out, "{} {}", label, val)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn dump_if_requested<Q: DumpContents>(config: &Config, counts: &Q) {
+    if let Some(path) = &config.dump {
+        if let Err(e) = write_dump(counts, path) {
+            eprintln!("Failed to dump contents to {path}: {e}");
+        }
+    }
+}
+
+fn fpdash_accuracy<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> usize {
+    let counts: FingerprintDash<H> = FingerprintDash::new(config.fp_size, config.max_size);
+    generic_accuracy(config, processed, counts, true)
+}
+
+fn fpdash_time<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> Duration {
+    let counts: FingerprintDash<H> = FingerprintDash::new(config.fp_size, config.max_size);
+    generic_time(config, processed, counts)
+}
+
+fn hash_run<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> Duration {
+    let counts: HashMap<FlowId,u32,BuildHasherDefault<H>> = HashMap::default();
     return generic_time(config, processed, counts);
 }
 
-fn hash_accuracy(_config: Config, processed: Vec<FlowId>) -> () {
-    let mut baseline = HashMap::new();
+fn hash_accuracy<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> usize {
+    let mut baseline: HashMap<FlowId,u32,BuildHasherDefault<H>> = HashMap::default();
     processed.iter().for_each(|id|
         if let Some(count) = baseline.get_mut(id) {
             *count+=1_u32;
@@ -186,19 +685,122 @@ fn hash_accuracy(_config: Config, processed: Vec<FlowId>) -> () {
     );
     println!("LENGTH {}", (&processed).len());
     baseline.print_memory_info();
+    let flows: HashMap<&FlowId, u32> = baseline.iter().map(|(id, val)| (id, *val)).collect();
+    print_error_distribution(&config, &flows, &baseline);
+    let violations = print_bounds(&config, processed.len(), &flows, &baseline);
+    print_heavy_hitters(&config, processed.len(), &flows, &baseline);
+    print_top_k(&config, &flows, &baseline);
+    print_cardinality(flows.len(), &baseline);
+    dump_if_requested(&config, &baseline);
+    violations
+}
+
+/// Estimates the number of distinct flows of the trace with a HyperLogLog whose relative standard error is --error
+fn hll_accuracy<H: SketchHasher>(config: Config, processed: Vec<FlowId>) {
+    let mut counts: HyperLogLog<FlowId,H> = HyperLogLog::new(config.error);
+    processed.iter().for_each(|id| counts.insert(id));
+    println!("LENGTH {}", processed.len());
+    counts.print_memory_info();
+    println!("HLL PRECISION {}", counts.precision());
+    let flows: HashSet<&FlowId> = processed.iter().collect();
+    print_cardinality_estimate(flows.len(), counts.cardinality());
+}
+
+fn hll_time<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> Duration {
+    let mut counts: HyperLogLog<FlowId,H> = HyperLogLog::new(config.error);
+    if !config.quiet {
+        println!("LENGTH {}", processed.len());
+    }
+    let start = Instant::now();
+    for id in &processed {
+        counts.insert(id);
+    }
+    let elapsed = start.elapsed();
+    print_write_phase(&config, processed.len(), elapsed);
+    black_box(counts.cardinality());
+    if config.verbose && !config.quiet {
+        println!("COUNTS are {:#?}", counts);
+    }
+    elapsed
+}
+
+/// The adaptive sampling rate requested by the configuration, starting at p = 1 and going down to --sample
+fn adaptive_rate(config: &Config) -> AdaptiveRate {
+    AdaptiveRate::new(config.sample, config.adaptive_volume, config.time_budget_ns.map(Duration::from_nanos))
+}
+
+fn nitrocms_new<H: SketchHasher>(config: &Config) -> NitroCMS<FlowId,u32,H> {
+    let counts = if config.adaptive {
+        NitroCMS::new_adaptive(config.confidence, config.error, !(config.avoid_mi), (), adaptive_rate(config))
+    } else if config.always_correct {
+        NitroCMS::new_always_correct(config.confidence, config.error, config.sample, !(config.avoid_mi), ())
+    } else {
+        NitroCMS::new(config.confidence, config.error, config.sample, !(config.avoid_mi), ())
+    };
+    if config.element_sampling {
+        counts.with_element_sampling()
+    } else {
+        counts
+    }
+}
+
+/// The sliding window NitroCMS of --window, sampling with probability `sample_prob`
+fn sliding_nitrocms_new<H: SketchHasher>(config: &Config, sample_prob: f64, window: usize) -> SlidingNitroCMS<FlowId,H> {
+    SlidingNitroCMS::new(config.confidence, config.error, sample_prob, !(config.avoid_mi), window, config.window_panes())
 }
 
-fn nitrocms_accuracy(config: Config, processed: Vec<FlowId>) -> () {
-    let counts: NitroCMS<FlowId,u32> = NitroCMS::new(config.confidence, config.error, if config.avoid_mi { 1.0 } else { config.sample }, !(config.avoid_mi), ());
+/// The decayed structure of --half-life or --halving-period: a decayed NitroCMS (sampling with probability 1 for CMS),
+/// with floating point counters with --float-counters, or a decayed SpaceSaving
+fn decayed_accuracy<H: SketchHasher>(config: Config, processed: Vec<FlowId>, times: Vec<f64>, decay: Decay) {
+    let sample_prob = if matches!(config.ds_type, DsType::CMS) { 1.0 } else { config.sample };
+    if matches!(config.ds_type, DsType::SpaceSaving) {
+        let counts: DecayedSpaceSaving<FlowId,BuildHasherDefault<H>> = DecayedSpaceSaving::new(config.error, decay);
+        return decay_accuracy(config, processed, times, counts, decay);
+    }
+    if config.float_counters {
+        let counts: DecayedNitroCMS<FlowId,f64,H> = DecayedNitroCMS::new(config.confidence, config.error, sample_prob, !(config.avoid_mi), decay);
+        return decay_accuracy(config, processed, times, counts, decay);
+    }
+    let counts: DecayedNitroCMS<FlowId,u64,H> = DecayedNitroCMS::new(config.confidence, config.error, sample_prob, !(config.avoid_mi), decay);
+    decay_accuracy(config, processed, times, counts, decay)
+}
+
+fn decayed_time<H: SketchHasher>(config: Config, processed: Vec<FlowId>, times: Vec<f64>, decay: Decay) -> Duration {
+    let sample_prob = if matches!(config.ds_type, DsType::CMS) { 1.0 } else { config.sample };
+    if matches!(config.ds_type, DsType::SpaceSaving) {
+        let counts: DecayedSpaceSaving<FlowId,BuildHasherDefault<H>> = DecayedSpaceSaving::new(config.error, decay);
+        return timed_time(config, processed, times, counts);
+    }
+    if config.float_counters {
+        let counts: DecayedNitroCMS<FlowId,f64,H> = DecayedNitroCMS::new(config.confidence, config.error, sample_prob, !(config.avoid_mi), decay);
+        return timed_time(config, processed, times, counts);
+    }
+    let counts: DecayedNitroCMS<FlowId,u64,H> = DecayedNitroCMS::new(config.confidence, config.error, sample_prob, !(config.avoid_mi), decay);
+    timed_time(config, processed, times, counts)
+}
+
+fn nitrocms_accuracy<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> usize {
+    if let Some(window) = config.window {
+        let counts = sliding_nitrocms_new::<H>(&config, config.sample, window);
+        return generic_accuracy(config, processed, counts, true);
+    }
+    let counts = nitrocms_new::<H>(&config);
     return generic_accuracy(config, processed, counts, true);
 }
 
-fn nitrocms_time(config: Config, processed: Vec<FlowId>) -> Duration {
-    let counts: NitroCMS<FlowId,u32> = NitroCMS::new(config.confidence, config.error, if config.avoid_mi { 1.0 } else { config.sample }, !(config.avoid_mi), ());
+fn nitrocms_time<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> Duration {
+    if let Some(window) = config.window {
+        let counts = sliding_nitrocms_new::<H>(&config, config.sample, window);
+        return generic_time(config, processed, counts);
+    }
+    if config.nested_layout {
+        return nested_time::<H>(config, processed);
+    }
+    let counts = nitrocms_new::<H>(&config);
     return generic_time(config, processed, counts);
 }
 
-fn cms_accuracy(config: Config, processed: Vec<FlowId>) -> () {
+fn cms_accuracy<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> usize {
     //// below is a hack because the corresponding function in the Amadeus CMS implementation is commented out
 	//let mut width = f64_to_usize((2.0 / config.error).round());
 	//width = max(2, width)
@@ -212,88 +814,223 @@ fn cms_accuracy(config: Config, processed: Vec<FlowId>) -> () {
     //// end of hack
     //let counts: CountMinSketch<FlowId,u32> = amadeus_streaming::CountMinSketch::new(config.confidence, config.error, ());
     // The code from Amadeus gave much worse accuracy even though it looks the same, so I am not using the local version
-    let counts: NitroCMS<FlowId,u32> = NitroCMS::new(config.confidence, config.error, 1.0 , !(config.avoid_mi), ());
-    generic_accuracy(config, processed, counts, true);
+    if let Some(window) = config.window {
+        let counts = sliding_nitrocms_new::<H>(&config, 1.0, window);
+        return generic_accuracy(config, processed, counts, true);
+    }
+    let counts: NitroCMS<FlowId,u32,H> = NitroCMS::new(config.confidence, config.error, 1.0 , !(config.avoid_mi), ());
+    generic_accuracy(config, processed, counts, true)
 }
 
-fn cms_time(config: Config, processed: Vec<FlowId>) -> Duration {
+fn cms_time<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> Duration {
     //let counts: CountMinSketch<FlowId,u32> = amadeus_streaming::CountMinSketch::new(config.confidence, config.error, ());
-    let counts: NitroCMS<FlowId,u32> = NitroCMS::new(config.confidence, config.error, 1.0 , !(config.avoid_mi), ());
+    if let Some(window) = config.window {
+        let counts = sliding_nitrocms_new::<H>(&config, 1.0, window);
+        return generic_time(config, processed, counts);
+    }
+    if config.nested_layout {
+        return nested_time::<H>(config, processed);
+    }
+    let counts: NitroCMS<FlowId,u32,H> = NitroCMS::new(config.confidence, config.error, 1.0 , !(config.avoid_mi), ());
     return generic_time(config, processed, counts);
 }
 
-fn space_accuracy(config: Config, processed: Vec<FlowId>) -> () {
-    let counts: SpaceSaving<FlowId,u32> = SpaceSaving::new(config.error, config.rap);
+fn space_accuracy<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> usize {
+    if let Some(window) = config.window {
+        let counts: WCSS<FlowId,BuildHasherDefault<H>> = WCSS::new(config.error, window);
+        return generic_accuracy(config, processed, counts, true);
+    }
+    let counts: SpaceSaving<FlowId,u32,BuildHasherDefault<H>> = SpaceSaving::new(config.error, config.rap);
     return generic_accuracy(config, processed, counts, true);
 }
 
-fn space_time(config: Config, processed: Vec<FlowId>) -> Duration {
-    let counts: SpaceSaving<FlowId,u32> = SpaceSaving::new(config.error, config.rap);
+fn space_time<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> Duration {
+    if let Some(window) = config.window {
+        let counts: WCSS<FlowId,BuildHasherDefault<H>> = WCSS::new(config.error, window);
+        return generic_time(config, processed, counts);
+    }
+    let counts: SpaceSaving<FlowId,u32,BuildHasherDefault<H>> = SpaceSaving::new(config.error, config.rap);
     return generic_time(config, processed, counts);
 }
 
-fn nitrohash_accuracy(config: Config, processed: Vec<FlowId>) -> () {
-    let counts: NitroHash<FlowId,u32> = NitroHash::new(config.sample);
+fn nitrohash_new<H: SketchHasher>(config: &Config) -> NitroHash<FlowId,u32,BuildHasherDefault<H>> {
+    if config.adaptive {
+        NitroHash::new_adaptive(adaptive_rate(config))
+    } else {
+        NitroHash::new(config.sample)
+    }
+}
+
+fn nitrohash_accuracy<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> usize {
+    let counts = nitrohash_new::<H>(&config);
     return generic_accuracy(config, processed, counts, true);
 }
 
-fn nitrohash_time(config: Config, processed: Vec<FlowId>) -> Duration {
-    let counts: NitroHash<FlowId,u32> = NitroHash::new(config.sample);
+fn nitrohash_time<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> Duration {
+    let counts = nitrohash_new::<H>(&config);
     return generic_time(config, processed, counts);
 }
 
-fn cuckoo_accuracy(config: Config, processed: Vec<FlowId>) -> () {
-    let counts= CuckooCountingFilter::<DefaultHasher>::with_capacity(processed.len());
-    return generic_accuracy(config, processed, counts, true);
+fn cuckoo_accuracy<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> usize {
+    let len = processed.len();
+    match config.bucket_size {
+        Some(8) => generic_accuracy(config, processed, CuckooCountingFilter::<H, 8>::with_capacity(len), true),
+        Some(16) => generic_accuracy(config, processed, CuckooCountingFilter::<H, 16>::with_capacity(len), true),
+        _ => generic_accuracy(config, processed, CuckooCountingFilter::<H, 4>::with_capacity(len), true),
+    }
 }
 
-fn cuckoo_time(config: Config, processed: Vec<FlowId>) -> Duration {
-    let counts= CuckooCountingFilter::<DefaultHasher>::with_capacity(processed.len());
-    return generic_time(config, processed, counts);
+fn cuckoo_time<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> Duration {
+    let len = processed.len();
+    match config.bucket_size {
+        Some(8) => generic_time(config, processed, CuckooCountingFilter::<H, 8>::with_capacity(len)),
+        Some(16) => generic_time(config, processed, CuckooCountingFilter::<H, 16>::with_capacity(len)),
+        _ => generic_time(config, processed, CuckooCountingFilter::<H, 4>::with_capacity(len)),
+    }
 }
 
-fn nitrocuckoo_accuracy(config: Config, processed: Vec<FlowId>) -> () {
-    let counts= if config.compact {
-        NitroCuckoo::<DefaultHasher>::with_capacity(processed.len()/((1.0/config.sample).ceil() as usize), config.sample)
+fn nitrocuckoo_new<H: SketchHasher, const B: usize>(config: &Config, len: usize) -> NitroCuckoo<H, B> {
+    let capacity = if config.compact {
+        len/((1.0/config.sample).ceil() as usize)
     } else {
-        NitroCuckoo::<DefaultHasher>::with_capacity(processed.len(), config.sample)
+        len
     };
-    return generic_accuracy(config, processed, counts, true);
+    if config.adaptive {
+        NitroCuckoo::<H, B>::with_capacity_adaptive(capacity, adaptive_rate(config))
+    } else {
+        NitroCuckoo::<H, B>::with_capacity(capacity, config.sample)
+    }
 }
 
-fn nitrocuckoo_time(config: Config, processed: Vec<FlowId>) -> Duration {
-    let counts= if config.compact {
-        NitroCuckoo::<DefaultHasher>::with_capacity(processed.len()/((1.0/config.sample).ceil() as usize), config.sample)
-    } else {
-        NitroCuckoo::<DefaultHasher>::with_capacity(processed.len(), config.sample)
-    };
-    return generic_time(config, processed, counts);
+fn nitrocuckoo_accuracy<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> usize {
+    let len = processed.len();
+    match config.bucket_size {
+        Some(8) => {
+            let counts = nitrocuckoo_new::<H, 8>(&config, len);
+            generic_accuracy(config, processed, counts, true)
+        }
+        Some(16) => {
+            let counts = nitrocuckoo_new::<H, 16>(&config, len);
+            generic_accuracy(config, processed, counts, true)
+        }
+        _ => {
+            let counts = nitrocuckoo_new::<H, 4>(&config, len);
+            generic_accuracy(config, processed, counts, true)
+        }
+    }
 }
 
-fn facs_accuracy(config: Config, processed: Vec<FlowId>) -> () {
-    let counts: FACS = FACS::new(config.sample);
-    return generic_accuracy(config, processed, counts, true);
+fn nitrocuckoo_time<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> Duration {
+    let len = processed.len();
+    match config.bucket_size {
+        Some(8) => {
+            let counts = nitrocuckoo_new::<H, 8>(&config, len);
+            generic_time(config, processed, counts)
+        }
+        Some(16) => {
+            let counts = nitrocuckoo_new::<H, 16>(&config, len);
+            generic_time(config, processed, counts)
+        }
+        _ => {
+            let counts = nitrocuckoo_new::<H, 4>(&config, len);
+            generic_time(config, processed, counts)
+        }
+    }
 }
 
-fn facs_time(config: Config, processed: Vec<FlowId>) -> Duration {
-    let counts: FACS = FACS::new(config.sample);
-    return generic_time(config, processed, counts);
+/// Measures the accuracy or the time of FACS with the window and permanent sketches of the configuration
+fn facs_run<H: SketchHasher + Send + Sync + 'static>(config: Config, processed: Vec<FlowId>, accuracy: bool) -> Measurement {
+    let window_size = config.facs_window_size();
+    match config.facs_window() {
+        FacsWindowType::HASH => {
+            let new_window = HashMap::<FlowId,u32,BuildHasherDefault<H>>::default;
+            facs_run_with_window::<H, _>(config, processed, accuracy, new_window)
+        }
+        FacsWindowType::Cuckoo => {
+            let new_window = || CuckooWindow::<H>::with_capacity(window_size);
+            facs_run_with_window::<H, _>(config, processed, accuracy, new_window)
+        }
+        FacsWindowType::NitroHash => {
+            let sample = config.sample;
+            let new_window = || NitroHash::<FlowId,u32,BuildHasherDefault<H>>::new(sample);
+            facs_run_with_window::<H, _>(config, processed, accuracy, new_window)
+        }
+    }
 }
 
-fn generic_accuracy<Q: Sized>(config: Config, processed: Vec<FlowId>, mut counts: Q, memory_info: bool) -> () 
+fn facs_run_with_window<H: SketchHasher + Send + Sync + 'static, W>(config: Config, processed: Vec<FlowId>, accuracy: bool, new_window: impl Fn() -> W) -> Measurement
 where
-Q: ItemIncrement + ItemQuery<Item=u32> + PrintMemoryInfo + std::fmt::Debug, <Q as ItemQuery>::Item: std::fmt::Display, f64: From<<Q as ItemQuery>::Item>
+W: FacsWindow + std::fmt::Debug + Send + Sync + 'static
 {
-    let mut msre_on_arrival = 0.0;
-    let mut avgerr_on_arrival = 0.0;
-    let mut avgrelerr_on_arrival = 0.0;
-    let mut baseline = HashMap::new();
-    for id in &processed {
+    if let Some(window) = config.window {
+        let permanent_config = config.clone();
+        let new_permanent = move || sliding_nitrocms_new::<H>(&permanent_config, 1.0, window);
+        return facs_measure(config, processed, accuracy, new_window, new_permanent);
+    }
+    let (confidence, error, avoid_mi, rap) = (config.confidence, config.error, config.avoid_mi, config.rap);
+    match config.facs_permanent() {
+        FacsPermanentType::NitroCMS => {
+            let new_permanent = || NitroCMS::<FlowId,u32,H>::new(confidence, error, 1.0, !avoid_mi, ());
+            facs_measure(config, processed, accuracy, new_window, new_permanent)
+        }
+        FacsPermanentType::CountSketch => {
+            let new_permanent = || CountSketch::<FlowId,H>::new(confidence, error);
+            facs_measure(config, processed, accuracy, new_window, new_permanent)
+        }
+        FacsPermanentType::SpaceSaving => {
+            let new_permanent = || SpaceSaving::<FlowId,u32,BuildHasherDefault<H>>::new(error, rap);
+            facs_measure(config, processed, accuracy, new_window, new_permanent)
+        }
+    }
+}
+
+/// Flushes the windows inline, or in a background thread with --facs-background (into a second, staged permanent
+/// sketch)
+fn facs_measure<W, P>(config: Config, processed: Vec<FlowId>, accuracy: bool, new_window: impl Fn() -> W, new_permanent: impl Fn() -> P) -> Measurement
+where
+W: FacsWindow + std::fmt::Debug + Send + Sync + 'static,
+P: FacsPermanent + std::fmt::Debug + Send + 'static
+{
+    let window_size = config.facs_window_size();
+    if config.facs_background {
+        let counts = BackgroundFACS::new(new_window(), new_window(), new_permanent(), new_permanent(), window_size);
+        if accuracy {
+            return Measurement::Accuracy(generic_accuracy(config, processed, counts, true));
+        }
+        return Measurement::Time(generic_time(config, processed, counts));
+    }
+    let counts = FACS::new(new_window(), new_permanent(), window_size);
+    if accuracy {
+        return Measurement::Accuracy(generic_accuracy(config, processed, counts, true));
+    }
+    Measurement::Time(generic_time(config, processed, counts))
+}
+
+fn facs_accuracy<H: SketchHasher + Send + Sync + 'static>(config: Config, processed: Vec<FlowId>) -> usize {
+    facs_run::<H>(config, processed, true).violations()
+}
+
+fn facs_time<H: SketchHasher + Send + Sync + 'static>(config: Config, processed: Vec<FlowId>) -> Duration {
+    facs_run::<H>(config, processed, false).time()
+}
+
+fn generic_accuracy<Q>(config: Config, processed: Vec<FlowId>, mut counts: Q, memory_info: bool) -> usize
+where
+Q: ItemIncrement + ItemQuery<Item=u32> + ItemEstimate + PrintMemoryInfo + PrintSamplingInfo + DumpContents + std::fmt::Debug, <Q as ItemQuery>::Item: std::fmt::Display, f64: From<<Q as ItemQuery>::Item>
+{
+    let mut msre_on_arrival = 0.0;
+    let mut avgerr_on_arrival = 0.0;
+    let mut avgrelerr_on_arrival = 0.0;
+    let mut baseline: HashMap<&FlowId, u32> = HashMap::new();
+    for (position, id) in processed.iter().enumerate() {
         if let Some(count) = baseline.get_mut(&id) {
             *count+=1;
         } else {
             baseline.insert(id,1);
         }
+        if let Some(expired) = config.window.and_then(|window| position.checked_sub(window)) {
+            expire(&mut baseline, &processed[expired]);
+        }
         counts.item_increment(*id);
         if let Some(count) = baseline.get(&id) {
             if config.verbose {
@@ -304,14 +1041,111 @@ Q: ItemIncrement + ItemQuery<Item=u32> + PrintMemoryInfo + std::fmt::Debug, <Q a
             avgerr_on_arrival += abs(item_estimate - f64::from(*count));
             avgrelerr_on_arrival += abs((item_estimate - f64::from(*count))/f64::from(*count));
         }
+        if is_checkpoint(&config, position + 1, processed.len()) {
+            print_checkpoint(position + 1, &baseline, &counts, avgerr_on_arrival, avgrelerr_on_arrival);
+        }
     }
     println!("LENGTH {}", (&processed).len());
     if memory_info {
         counts.print_memory_info();
     }
+    counts.print_sampling_info();
     println!("On-Arrival MSRE {}", msre_on_arrival.sqrt()/f64::try_from(i32::try_from((&processed).len()).unwrap()).unwrap());
     println!("On-Arrival AVGERR {}", avgerr_on_arrival / f64::try_from(i32::try_from((&processed).len()).unwrap()).unwrap());
     println!("On-Arrival AVGRELERR {}", avgrelerr_on_arrival / f64::try_from(i32::try_from((&processed).len()).unwrap()).unwrap());
+    let window_start = config.window.map_or(0, |window| processed.len().saturating_sub(window));
+    let violations = print_final_accuracy(&config, &processed[window_start..], &baseline, &counts);
+    dump_if_requested(&config, &counts);
+    violations
+}
+
+/// Measures the accuracy of decayed counts against the exactly decayed count of each flow (time being the timestamp of
+/// each item in `times`, or its position in the trace without --timestamps). The final errors are over the live flows,
+/// whose decayed count is at least 1.
+fn decay_accuracy<Q>(config: Config, processed: Vec<FlowId>, times: Vec<f64>, mut counts: Q, decay: Decay)
+where
+Q: TimedIncrement + ItemEstimate + PrintMemoryInfo + PrintSamplingInfo + DumpContents
+{
+    let mut exact: ExactDecay<FlowId> = ExactDecay::new(decay);
+    let mut msre_on_arrival = 0.0;
+    let mut avgerr_on_arrival = 0.0;
+    let mut avgrelerr_on_arrival = 0.0;
+    for (position, id) in processed.iter().enumerate() {
+        let time = if times.is_empty() { position as f64 } else { times[position] };
+        exact.insert(*id, time);
+        counts.timed_increment(*id, time);
+        let item_real = exact.get(id);
+        let item_estimate = counts.item_estimate(*id);
+        msre_on_arrival += (item_estimate - item_real).powi(2);
+        avgerr_on_arrival += abs(item_estimate - item_real);
+        avgrelerr_on_arrival += abs((item_estimate - item_real) / item_real);
+    }
+    println!("LENGTH {}", processed.len());
+    counts.print_memory_info();
+    counts.print_sampling_info();
+    println!("On-Arrival MSRE {}", msre_on_arrival.sqrt() / processed.len() as f64);
+    println!("On-Arrival AVGERR {}", avgerr_on_arrival / processed.len() as f64);
+    println!("On-Arrival AVGRELERR {}", avgrelerr_on_arrival / processed.len() as f64);
+    let flows: Vec<(f64, f64)> = exact.iter()
+        .filter(|(_, item_real)| *item_real >= 1.0)
+        .map(|(id, item_real)| (item_real, counts.item_estimate(*id)))
+        .collect();
+    let mut abs_errors: Vec<f64> = flows.iter().map(|(item_real, item_estimate)| abs(item_estimate - item_real)).collect();
+    let mut rel_errors: Vec<f64> = flows.iter().map(|(item_real, item_estimate)| abs(item_estimate - item_real) / item_real).collect();
+    println!("DECAY FLOWS {}", flows.len());
+    println!("Flow MSRE {}", abs_errors.iter().map(|error| error.powi(2)).sum::<f64>().sqrt() / flows.len() as f64);
+    println!("Flow AVGERR {}", abs_errors.iter().sum::<f64>() / flows.len() as f64);
+    println!("Flow AVGRELERR {}", rel_errors.iter().sum::<f64>() / flows.len() as f64);
+    abs_errors.sort_by(f64::total_cmp);
+    rel_errors.sort_by(f64::total_cmp);
+    for (name, q) in ERROR_QUANTILES {
+        println!("Flow ABSERR {} {}", name, sorted_quantile(&abs_errors, q));
+    }
+    for (name, q) in ERROR_QUANTILES {
+        println!("Flow RELERR {} {}", name, sorted_quantile(&rel_errors, q));
+    }
+    dump_if_requested(&config, &counts);
+}
+
+/// Remove an arrival of `id` that left the window from the ground truth
+fn expire(baseline: &mut HashMap<&FlowId, u32>, id: &FlowId) {
+    if let Some(count) = baseline.get_mut(id) {
+        *count -= 1;
+        if *count == 0 {
+            baseline.remove(id);
+        }
+    }
+}
+
+/// Whether the accuracy is sampled after the `items`-th arrival: every --checkpoint-interval items, or at each power of two
+/// with --log-checkpoints, and at the end of a trace of `len` items
+fn is_checkpoint(config: &Config, items: usize, len: usize) -> bool {
+    if let Some(interval) = config.checkpoint_interval {
+        return items.is_multiple_of(interval) || items == len;
+    }
+    config.log_checkpoints && (items.is_power_of_two() || items == len)
+}
+
+/// Print a record of the accuracy after the first `items` arrivals: the errors of the current estimates of the flows
+/// seen so far, and the on-arrival errors accumulated so far
+fn print_checkpoint<Q: ItemQuery<Item=u32>>(items: usize, baseline: &HashMap<&FlowId, u32>, counts: &Q, avgerr_on_arrival: f64, avgrelerr_on_arrival: f64) {
+    let mut avgerr = 0.0;
+    let mut avgrelerr = 0.0;
+    for (id, val) in baseline.iter() {
+        let error = abs(f64::from(counts.item_query(**id)) - f64::from(*val));
+        avgerr += error;
+        avgrelerr += error / f64::from(*val);
+    }
+    println!("CHECKPOINT {} FLOWS {} AVGERR {} AVGRELERR {} On-Arrival-AVGERR {} On-Arrival-AVGRELERR {}", items, baseline.len(),
+        avgerr / baseline.len() as f64, avgrelerr / baseline.len() as f64, avgerr_on_arrival / items as f64, avgrelerr_on_arrival / items as f64);
+}
+
+/// Print the errors of the final estimates of `counts`, per flow and per packet (PMW), and the coverage of their confidence intervals.
+/// Returns the number of flows beyond their error bound (see `print_bounds`)
+fn print_final_accuracy<Q>(config: &Config, processed: &[FlowId], baseline: &HashMap<&FlowId, u32>, counts: &Q) -> usize
+where
+Q: ItemQuery<Item=u32> + ItemEstimate
+{
     let mut msre_flow = 0.0;
     let mut avgerr_flow = 0.0;
     let mut avgrelerr_flow = 0.0;
@@ -325,10 +1159,26 @@ Q: ItemIncrement + ItemQuery<Item=u32> + PrintMemoryInfo + std::fmt::Debug, <Q a
     println!("Flow MSRE {}", msre_flow.sqrt()/f64::try_from(i32::try_from((baseline).len()).unwrap()).unwrap());
     println!("Flow AVGERR {}", avgerr_flow / f64::try_from(i32::try_from((baseline).len()).unwrap()).unwrap());
     println!("Flow AVGRELERR {}", avgrelerr_flow / f64::try_from(i32::try_from((baseline).len()).unwrap()).unwrap());
+    let mut ci_flows = 0_usize;
+    let mut ci_covered = 0_usize;
+    let mut ci_width = 0.0;
+    for (id,val) in baseline.iter() {
+        if let Some((low, high)) = counts.item_confidence_interval(**id, config.confidence) {
+            ci_flows += 1;
+            if low <= f64::from(*val) && f64::from(*val) <= high {
+                ci_covered += 1;
+            }
+            ci_width += high - low;
+        }
+    }
+    if ci_flows > 0 {
+        println!("Flow CI COVERAGE {}", ci_covered as f64 / ci_flows as f64);
+        println!("Flow CI AVGWIDTH {}", ci_width / ci_flows as f64);
+    }
     let mut msre_pmw = 0.0;
     let mut avgerr_pmw = 0.0;
     let mut avgrelerr_pmw = 0.0;
-    for id in &processed {
+    for id in processed {
         if let Some(count) = baseline.get(&id) {
             let item_real = f64::from(*count);
             let item_estimate = f64::from(counts.item_query(*id));
@@ -337,49 +1187,736 @@ Q: ItemIncrement + ItemQuery<Item=u32> + PrintMemoryInfo + std::fmt::Debug, <Q a
             avgrelerr_pmw += abs((item_estimate - item_real)/item_real);
         }
     }  
-    println!("PMW MSRE is {}", msre_pmw.sqrt()/f64::try_from(i32::try_from((&processed).len()).unwrap()).unwrap());
-    println!("PMW AVGERR is {}", avgerr_pmw / f64::try_from(i32::try_from((&processed).len()).unwrap()).unwrap()); 
-    println!("PMW AVGRELERR is {}", avgrelerr_pmw / f64::try_from(i32::try_from((&processed).len()).unwrap()).unwrap()); 
+    println!("PMW MSRE is {}", msre_pmw.sqrt()/f64::from(i32::try_from(processed.len()).unwrap()));
+    println!("PMW AVGERR is {}", avgerr_pmw / f64::from(i32::try_from(processed.len()).unwrap())); 
+    println!("PMW AVGRELERR is {}", avgrelerr_pmw / f64::from(i32::try_from(processed.len()).unwrap())); 
+    print_error_distribution(config, baseline, counts);
+    let violations = print_bounds(config, processed.len(), baseline, counts);
+    print_heavy_hitters(config, processed.len(), baseline, counts);
+    print_top_k(config, baseline, counts);
+    print_cardinality(baseline.len(), counts);
+    violations
+}
+
+/// Compares the number of distinct flows estimated by the structure, if it estimates it, with the true number
+fn print_cardinality<Q: ItemQuery>(flows: usize, counts: &Q) {
+    if let Some(estimate) = counts.cardinality() {
+        print_cardinality_estimate(flows, estimate);
+    }
+}
+
+fn print_cardinality_estimate(flows: usize, estimate: f64) {
+    println!("CARDINALITY TRUE {}", flows);
+    println!("CARDINALITY ESTIMATE {}", estimate);
+    println!("CARDINALITY RELERR {}", (estimate - flows as f64) / flows as f64);
+}
+
+/// The value at quantile `q` of the sorted `values`
+fn sorted_quantile(values: &[f64], q: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let rank = ((q * values.len() as f64).ceil() as usize).max(1);
+    values[rank - 1]
+}
+
+/// The quantiles reported for the per-flow errors
+const ERROR_QUANTILES: [(&str, f64); 4] = [("P50", 0.5), ("P90", 0.9), ("P99", 0.99), ("MAX", 1.0)];
+
+/// Print the quantiles of the per-flow absolute and relative errors, and the errors of the flows of each size class
+/// (1-9, 10-99, ... packets), and write the (true, estimated) count of each flow to --errors-csv
+fn print_error_distribution<Q: ItemQuery<Item=u32>>(config: &Config, baseline: &HashMap<&FlowId, u32>, counts: &Q) {
+    let flows: Vec<(&FlowId, u32, u32)> = baseline.iter().map(|(id, val)| (*id, *val, counts.item_query(**id))).collect();
+    let abs_error = |val: u32, estimate: u32| abs(f64::from(estimate) - f64::from(val));
+    let mut abs_errors: Vec<f64> = flows.iter().map(|(_, val, estimate)| abs_error(*val, *estimate)).collect();
+    let mut rel_errors: Vec<f64> = flows.iter().map(|(_, val, estimate)| abs_error(*val, *estimate) / f64::from(*val)).collect();
+    abs_errors.sort_by(f64::total_cmp);
+    rel_errors.sort_by(f64::total_cmp);
+    for (name, q) in ERROR_QUANTILES {
+        println!("Flow ABSERR {} {}", name, sorted_quantile(&abs_errors, q));
+    }
+    for (name, q) in ERROR_QUANTILES {
+        println!("Flow RELERR {} {}", name, sorted_quantile(&rel_errors, q));
+    }
+    let mut classes: Vec<Vec<(f64, f64)>> = Vec::new();
+    for (_, val, estimate) in &flows {
+        let class = val.ilog10() as usize;
+        if classes.len() <= class {
+            classes.resize(class + 1, Vec::new());
+        }
+        classes[class].push((abs_error(*val, *estimate), abs_error(*val, *estimate) / f64::from(*val)));
+    }
+    for (class, errors) in classes.iter_mut().enumerate().filter(|(_, errors)| !errors.is_empty()) {
+        let low = 10_u64.pow(class as u32);
+        let avgerr = errors.iter().map(|(abs_error, _)| abs_error).sum::<f64>() / errors.len() as f64;
+        let avgrelerr = errors.iter().map(|(_, rel_error)| rel_error).sum::<f64>() / errors.len() as f64;
+        let mut rel: Vec<f64> = errors.iter().map(|(_, rel_error)| *rel_error).collect();
+        rel.sort_by(f64::total_cmp);
+        println!("Flow SIZE {}-{} FLOWS {} AVGERR {} AVGRELERR {} P99RELERR {} MAXRELERR {}", low, low * 10 - 1, errors.len(),
+            avgerr, avgrelerr, sorted_quantile(&rel, 0.99), sorted_quantile(&rel, 1.0));
+    }
+    if let Some(path) = &config.errors_csv {
+        if let Err(e) = write_errors_csv(&flows, path) {
+            eprintln!("Failed to write the per-flow errors to {path}: {e}");
+        }
+    }
+}
+
+fn write_errors_csv(flows: &[(&FlowId, u32, u32)], path: &str) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    writeln!(out, "src_ip,dst_ip,true,estimate")?;
+    for (id, val, estimate) in flows {
+        writeln!(out, "{},{},{},{}", id.srcip, id.dstip, val, estimate)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Print the fraction of flows whose error exceeds the bound promised by the structure for --error and --confidence.
+/// Returns the number of these flows when they are more than the fraction of failures the structure promises (failing
+/// the run), and 0 otherwise. Structures without a bound print nothing.
+fn print_bounds<Q: ItemEstimate>(config: &Config, volume: usize, baseline: &HashMap<&FlowId, u32>, counts: &Q) -> usize {
+    let mut bounded = 0_usize;
+    let mut violations = 0_usize;
+    let mut bound_sum = 0.0;
+    for (id, val) in baseline.iter() {
+        if let Some(bound) = counts.item_error_bound(**id, volume as u64, config.error, config.confidence) {
+            bounded += 1;
+            bound_sum += bound;
+            if abs(counts.item_estimate(**id) - f64::from(*val)) > bound {
+                violations += 1;
+            }
+        }
+    }
+    if bounded == 0 {
+        return 0;
+    }
+    let violation_rate = violations as f64 / bounded as f64;
+    let promised = counts.item_bound_failure(config.confidence);
+    println!("BOUND FLOWS {}", bounded);
+    println!("BOUND AVGBOUND {}", bound_sum / bounded as f64);
+    println!("BOUND PROMISED {}", promised);
+    println!("BOUND VIOLATIONS {}", violation_rate);
+    if violation_rate > promised {
+        eprintln!("BOUND VIOLATED: {} of the flows exceed their error bound, more than the promised {}", violation_rate, promised);
+        return violations;
+    }
+    0
+}
+
+/// Print the accuracy of the top-k flows for each --top-k k: the estimated top-k are the k flows of highest estimates among
+/// the items stored by the structure, or among the flows of the trace for sketches that do not store their keys.
+/// Reports the fraction of the true top-k flows found (RECALL) and the number missed (MISSED), and the Kendall rank
+/// correlation (tau-b, which accounts for ties) of the estimates of the true top-k flows with their true counts (KENDALL).
+/// Traces of fewer than k flows are evaluated on all their flows.
+fn print_top_k<Q: ItemQuery<Item=u32>>(config: &Config, baseline: &HashMap<&FlowId, u32>, counts: &Q) {
+    if config.top_k.is_empty() {
+        return;
+    }
+    let mut true_ranking: Vec<(&FlowId, u32)> = baseline.iter().map(|(id, val)| (*id, *val)).collect();
+    true_ranking.sort_by(|(_, a), (_, b)| b.cmp(a));
+    let candidates: Vec<FlowId> = counts.stored_items().unwrap_or_else(|| baseline.keys().map(|id| **id).collect());
+    let mut estimated_ranking: Vec<(FlowId, u32)> = candidates.into_iter().map(|id| (id, counts.item_query(id))).collect();
+    estimated_ranking.sort_by(|(_, a), (_, b)| b.cmp(a));
+    for requested_k in &config.top_k {
+        let k = (*requested_k).min(true_ranking.len());
+        let estimated_top: HashSet<&FlowId> = estimated_ranking.iter().take(k).map(|(id, _)| id).collect();
+        let top = &true_ranking[..k];
+        let found = top.iter().filter(|(id, _)| estimated_top.contains(id)).count();
+        let estimates: Vec<u32> = top.iter().map(|(id, _)| counts.item_query(**id)).collect();
+        let mut concordant = 0_i64;
+        let mut discordant = 0_i64;
+        let mut real_untied = 0_i64;
+        let mut estimated_untied = 0_i64;
+        for i in 0..k {
+            for j in i + 1..k {
+                let real = top[i].1.cmp(&top[j].1);
+                let estimated = estimates[i].cmp(&estimates[j]);
+                real_untied += i64::from(real != Ordering::Equal);
+                estimated_untied += i64::from(estimated != Ordering::Equal);
+                if real != Ordering::Equal && estimated != Ordering::Equal {
+                    if real == estimated { concordant += 1 } else { discordant += 1 }
+                }
+            }
+        }
+        let untied = (real_untied as f64 * estimated_untied as f64).sqrt();
+        println!("TOPK {} RECALL {}", requested_k, if k > 0 { found as f64 / k as f64 } else { 1.0 });
+        println!("TOPK {} MISSED {}", requested_k, k - found);
+        println!("TOPK {} KENDALL {}", requested_k, if untied > 0.0 { (concordant - discordant) as f64 / untied } else { 1.0 });
+    }
+}
+
+/// Print the heavy hitter detection accuracy at each --hh-thresholds phi. The heavy hitters are the flows of at least
+/// phi*N packets (N being the trace length), and a flow is reported when its estimate reaches phi*N; the average relative
+/// error (ARE) is over the true heavy hitters.
+fn print_heavy_hitters<Q: ItemQuery<Item=u32>>(config: &Config, len: usize, baseline: &HashMap<&FlowId, u32>, counts: &Q) {
+    if config.hh_thresholds.is_empty() {
+        return;
+    }
+    let estimates: Vec<(u32, u32)> = baseline.iter().map(|(id, val)| (*val, counts.item_query(**id))).collect();
+    for phi in &config.hh_thresholds {
+        let threshold = phi * len as f64;
+        let mut heavy = 0_usize;
+        let mut reported = 0_usize;
+        let mut detected = 0_usize;
+        let mut relerr = 0.0;
+        for (val, estimate) in &estimates {
+            let is_heavy = f64::from(*val) >= threshold;
+            let is_reported = f64::from(*estimate) >= threshold;
+            if is_heavy {
+                heavy += 1;
+                relerr += abs((f64::from(*estimate) - f64::from(*val)) / f64::from(*val));
+            }
+            if is_reported {
+                reported += 1;
+            }
+            if is_heavy && is_reported {
+                detected += 1;
+            }
+        }
+        // no reported (true) heavy hitter means no false positive (negative)
+        let precision = if reported > 0 { detected as f64 / reported as f64 } else { 1.0 };
+        let recall = if heavy > 0 { detected as f64 / heavy as f64 } else { 1.0 };
+        let f1 = if precision + recall > 0.0 { 2.0 * precision * recall / (precision + recall) } else { 0.0 };
+        println!("HH {} TRUE {}", phi, heavy);
+        println!("HH {} REPORTED {}", phi, reported);
+        println!("HH {} PRECISION {}", phi, precision);
+        println!("HH {} RECALL {}", phi, recall);
+        println!("HH {} F1 {}", phi, f1);
+        println!("HH {} ARE {}", phi, if heavy > 0 { relerr / heavy as f64 } else { 0.0 });
+    }
 }
 
 fn generic_time<Q: Sized>(config: Config, processed: Vec<FlowId>, mut counts: Q) -> Duration
 where
-Q: ItemIncrement + ItemQuery + PrintMemoryInfo + std::fmt::Debug,
+Q: ItemIncrement + ItemQuery + PrintMemoryInfo + PrintSamplingInfo + DumpContents + std::fmt::Debug,
 {
-    println!("LENGTH {}", (&processed).len());
-    let mut start = Instant::now();
-    for id in &processed {
-        counts.item_increment(*id);
-        if config.time_type == TimeType::RWTIME {
-            counts.item_query(*id);
+    if !config.quiet {
+        println!("LENGTH {}", (&processed).len());
+    }
+    if config.latency {
+        return latency_time(config, processed, counts);
+    }
+    let elapsed = insert_and_read(&config, &processed, &mut counts);
+    print_counts(&config, &counts);
+    dump_if_requested(&config, &counts);
+    return elapsed;
+}
+
+/// Times the nested counter layout of CMS and NitroCMS (--nested-layout), for comparison with the flat layout
+fn nested_time<H: SketchHasher>(config: Config, processed: Vec<FlowId>) -> Duration {
+    let sample_prob = if matches!(config.ds_type, DsType::CMS) { 1.0 } else { config.sample };
+    let mut counts: NestedNitroCMS<FlowId,u32,H> = NestedNitroCMS::new(config.confidence, config.error, sample_prob, !(config.avoid_mi), ());
+    if !config.quiet {
+        println!("LENGTH {}", processed.len());
+    }
+    insert_and_read(&config, &processed, &mut counts)
+}
+
+/// The insertion (and reading) loops of `generic_time`, returning the time of the measured phase
+fn insert_and_read<Q: ItemIncrement + ItemQuery>(config: &Config, processed: &[FlowId], counts: &mut Q) -> Duration {
+    let start = Instant::now();
+    if config.batch_size > 1 {
+        for batch in processed.chunks(config.batch_size) {
+            counts.item_increment_batch(batch);
+            if config.time_type == TimeType::RWTIME {
+                batch.iter().for_each(|id| {black_box(counts.item_query(*id));});
+            }
+        }
+    } else {
+        for id in processed {
+            counts.item_increment(*id);
+            if config.time_type == TimeType::RWTIME {
+                black_box(counts.item_query(*id));
+            }
         }
     }
+    let mut elapsed = start.elapsed();
+    print_write_phase(config, processed.len(), elapsed);
     if config.time_type == TimeType::READTIME {
-        start = Instant::now();
+        let start = Instant::now();
         //for id in &processed {
         processed.iter().for_each(|id|
-            {counts.item_query(*id);});
+            {black_box(counts.item_query(*id));});
         //}
+        elapsed = start.elapsed();
+        print_phase(config, "READ", processed.len(), elapsed);
     }
+    elapsed
+}
+
+/// Like `generic_time` for the structures whose counts depend on time, inserting each item with its timestamp in `times`
+/// (without --timestamps, the structures count time in items and `generic_time` is used)
+fn timed_time<Q>(config: Config, processed: Vec<FlowId>, times: Vec<f64>, mut counts: Q) -> Duration
+where
+Q: TimedIncrement + ItemIncrement + ItemQuery + PrintMemoryInfo + PrintSamplingInfo + DumpContents + std::fmt::Debug,
+{
+    if times.is_empty() {
+        return generic_time(config, processed, counts);
+    }
+    if !config.quiet {
+        println!("LENGTH {}", processed.len());
+    }
+    let start = Instant::now();
+    for (id, time) in processed.iter().zip(&times) {
+        counts.timed_increment(*id, *time);
+        if config.time_type == TimeType::RWTIME {
+            black_box(counts.item_query(*id));
+        }
+    }
+    let mut elapsed = start.elapsed();
+    print_write_phase(&config, processed.len(), elapsed);
+    if config.time_type == TimeType::READTIME {
+        let start = Instant::now();
+        processed.iter().for_each(|id| {black_box(counts.item_query(*id));});
+        elapsed = start.elapsed();
+        print_phase(&config, "READ", processed.len(), elapsed);
+    }
+    print_counts(&config, &counts);
+    dump_if_requested(&config, &counts);
+    elapsed
+}
+
+/// Millions of items per second
+fn mpps(len: usize, elapsed: Duration) -> f64 {
+    len as f64 / elapsed.as_secs_f64() / 1e6
+}
+
+fn print_phase(config: &Config, phase: &str, len: usize, elapsed: Duration) {
+    if !config.quiet {
+        println!("PHASE {} Mpps {}", phase, mpps(len, elapsed));
+    }
+}
+
+/// The insertion phase, which also reads each item after inserting it with RWTIME
+fn print_write_phase(config: &Config, len: usize, elapsed: Duration) {
+    print_phase(config, if config.time_type == TimeType::RWTIME { "RW" } else { "WRITE" }, len, elapsed);
+}
+
+/// The sampling state of a timed structure, and its counts with --verbose
+fn print_counts<Q: PrintSamplingInfo + std::fmt::Debug>(config: &Config, counts: &Q) {
+    if config.quiet {
+        return;
+    }
+    counts.print_sampling_info();
     if config.verbose {
         println!("COUNTS are {:#?}", counts);
     }
-    return start.elapsed();
 }
 
-fn preprocess_contents(contents: String) -> Vec<FlowId> {
+/// The quantiles reported for latency histograms
+const LATENCY_QUANTILES: [(&str, f64); 4] = [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("p99.9", 0.999)];
+
+fn print_latency(operation: &str, histogram: &LatencyHistogram) {
+    let quantiles: Vec<String> = LATENCY_QUANTILES.iter().map(|(name, q)| format!("{} {}", name, histogram.quantile(*q))).collect();
+    println!("{}-LATENCYns {} max {} count {}", operation, quantiles.join(" "), histogram.max(), histogram.len());
+}
+
+/// Like `generic_time`, additionally timing each insertion (each batch with --batch-size) and each query, and
+/// reporting their latency quantiles. Reading the clock around every operation adds its own overhead to the total time.
+fn latency_time<Q>(config: Config, processed: Vec<FlowId>, mut counts: Q) -> Duration
+where
+Q: ItemIncrement + ItemQuery + PrintMemoryInfo + PrintSamplingInfo + DumpContents + std::fmt::Debug,
+{
+    let mut inserts = LatencyHistogram::new();
+    let mut queries = LatencyHistogram::new();
+    let query = |counts: &Q, id: &FlowId, queries: &mut LatencyHistogram| {
+        let op_start = Instant::now();
+        black_box(counts.item_query(*id));
+        queries.record_duration(op_start.elapsed());
+    };
+    let start = Instant::now();
+    for batch in processed.chunks(config.batch_size) {
+        let op_start = Instant::now();
+        if config.batch_size > 1 {
+            counts.item_increment_batch(batch);
+        } else {
+            counts.item_increment(batch[0]);
+        }
+        inserts.record_duration(op_start.elapsed());
+        if config.time_type == TimeType::RWTIME {
+            batch.iter().for_each(|id| query(&counts, id, &mut queries));
+        }
+    }
+    let mut elapsed = start.elapsed();
+    print_write_phase(&config, processed.len(), elapsed);
+    if config.time_type == TimeType::READTIME {
+        let start = Instant::now();
+        processed.iter().for_each(|id| query(&counts, id, &mut queries));
+        elapsed = start.elapsed();
+        print_phase(&config, "READ", processed.len(), elapsed);
+    }
+    if config.time_type != TimeType::READTIME && !config.quiet {
+        print_latency(if config.batch_size > 1 { "BATCH" } else { "INSERT" }, &inserts);
+    }
+    if !queries.is_empty() && !config.quiet {
+        print_latency("QUERY", &queries);
+    }
+    print_counts(&config, &counts);
+    dump_if_requested(&config, &counts);
+    elapsed
+}
+
+/// Number of stripes of a striped structure per thread
+const STRIPES_PER_THREAD: usize = 16;
+
+/// Run `work` on `config.threads` threads, each on a contiguous part of the trace, and return the wall clock time
+fn parallel_for<F: Fn(&[FlowId]) + Sync>(config: &Config, processed: &[FlowId], work: F) -> Duration {
+    let part_len = max(1, processed.len().div_ceil(config.threads));
+    let work = &work;
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for part in processed.chunks(part_len) {
+            scope.spawn(move || work(part));
+        }
+    });
+    start.elapsed()
+}
+
+fn parallel_accuracy<Q>(config: Config, processed: Vec<FlowId>, counts: Q) -> usize
+where
+Q: ConcurrentIncrement + ItemQuery<Item=u32> + ItemEstimate + PrintMemoryInfo + PrintSamplingInfo + DumpContents
+{
+    parallel_for(&config, &processed, |part| counts.concurrent_insert_part(part, false));
+    let mut baseline: HashMap<&FlowId, u32> = HashMap::new();
+    for id in &processed {
+        *baseline.entry(id).or_insert(0) += 1;
+    }
+    println!("LENGTH {}", processed.len());
+    println!("THREADS {}", config.threads);
+    counts.print_memory_info();
+    counts.print_sampling_info();
+    let violations = print_final_accuracy(&config, &processed, &baseline, &counts);
+    dump_if_requested(&config, &counts);
+    violations
+}
+
+/// Time the insertion (and reading) of the trace by all threads together. The aggregate throughput is the trace length
+/// over the wall clock time.
+fn parallel_time<Q>(config: Config, processed: Vec<FlowId>, counts: Q) -> Duration
+where
+Q: ConcurrentIncrement + PrintSamplingInfo + DumpContents
+{
+    if !config.quiet {
+        println!("LENGTH {}", processed.len());
+        println!("THREADS {}", config.threads);
+    }
+    let rwtime = config.time_type == TimeType::RWTIME;
+    let mut elapsed = parallel_for(&config, &processed, |part| counts.concurrent_insert_part(part, rwtime));
+    print_write_phase(&config, processed.len(), elapsed);
+    if config.time_type == TimeType::READTIME {
+        elapsed = parallel_for(&config, &processed, |part| part.iter().for_each(|id| {black_box(counts.concurrent_query(*id));}));
+        print_phase(&config, "READ", processed.len(), elapsed);
+    }
+    if !config.quiet {
+        counts.print_sampling_info();
+    }
+    dump_if_requested(&config, &counts);
+    elapsed
+}
+
+/// A timing measurement, or an accuracy comparison with --compare
+fn parallel_run<Q>(config: Config, processed: Vec<FlowId>, counts: Q) -> Measurement
+where
+Q: ConcurrentIncrement + ItemQuery<Item=u32> + ItemEstimate + PrintMemoryInfo + PrintSamplingInfo + DumpContents
+{
+    if config.compare {
+        return Measurement::Accuracy(parallel_accuracy(config, processed, counts));
+    }
+    Measurement::Time(parallel_time(config, processed, counts))
+}
+
+/// Run the concurrent variant of the chosen structure with --threads threads: a DashMap for HASH, the (already concurrent)
+/// fingerprint table for FPDASH, per-thread
+/// sketches merged periodically (--thread-local) or an atomic sketch for CMS and NitroCMS, and lock-striped
+/// filters for Cuckoo and NitroCuckoo
+fn run_parallel<H: SketchHasher + Send>(config: Config, processed: Vec<FlowId>) -> Measurement {
+    let len = processed.len();
+    let stripes = config.threads * STRIPES_PER_THREAD;
+    let sample = if matches!(config.ds_type, DsType::CMS) { 1.0 } else { config.sample };
+    match (&config.ds_type, config.bucket_size) {
+        (DsType::FPDASH, _) => {
+            let counts: FingerprintDash<H> = FingerprintDash::new(config.fp_size, config.max_size);
+            parallel_run(config, processed, counts)
+        }
+        (DsType::HASH, _) => {
+            let counts: DashMap<FlowId,u32,BuildHasherDefault<H>> = DashMap::default();
+            parallel_run(config, processed, counts)
+        }
+        (DsType::CMS | DsType::NitroCMS, _) if config.thread_local => {
+            let empty: NitroCMS<FlowId,u32,H> = NitroCMS::new(config.confidence, config.error, sample, !(config.avoid_mi), ());
+            let counts = ThreadLocalMerge::new(empty, config.merge_interval);
+            parallel_run(config, processed, counts)
+        }
+        (DsType::CMS | DsType::NitroCMS, _) => {
+            let counts: AtomicNitroCMS<FlowId,H> = AtomicNitroCMS::new(config.confidence, config.error, sample);
+            parallel_run(config, processed, counts)
+        }
+        (DsType::Cuckoo, Some(8)) => {
+            let counts: Striped<_, H> = Striped::new(stripes, || CuckooCountingFilter::<H, 8>::with_capacity(len / stripes));
+            parallel_run(config, processed, counts)
+        }
+        (DsType::Cuckoo, Some(16)) => {
+            let counts: Striped<_, H> = Striped::new(stripes, || CuckooCountingFilter::<H, 16>::with_capacity(len / stripes));
+            parallel_run(config, processed, counts)
+        }
+        (DsType::Cuckoo, _) => {
+            let counts: Striped<_, H> = Striped::new(stripes, || CuckooCountingFilter::<H, 4>::with_capacity(len / stripes));
+            parallel_run(config, processed, counts)
+        }
+        (DsType::NitroCuckoo, Some(8)) => {
+            let counts: Striped<_, H> = Striped::new(stripes, || nitrocuckoo_new::<H, 8>(&config, len / stripes));
+            parallel_run(config, processed, counts)
+        }
+        (DsType::NitroCuckoo, Some(16)) => {
+            let counts: Striped<_, H> = Striped::new(stripes, || nitrocuckoo_new::<H, 16>(&config, len / stripes));
+            parallel_run(config, processed, counts)
+        }
+        (DsType::NitroCuckoo, _) => {
+            let counts: Striped<_, H> = Striped::new(stripes, || nitrocuckoo_new::<H, 4>(&config, len / stripes));
+            parallel_run(config, processed, counts)
+        }
+        _ => unreachable!("--threads is validated against the structure"),
+    }
+}
+
+/// Parse a text trace, and the timestamp of each line with `timestamps`
+fn preprocess_contents(contents: String, timestamps: bool) -> Result<(Vec<FlowId>, Vec<f64>), String> {
     let mut result = Vec::new();
-    for line in contents.lines() {
+    let mut times = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
         if let Ok(id) = id_from_line(line) {
             result.push(id);
+            if timestamps {
+                times.push(timestamp_from_line(line).ok_or_else(|| format!("line {} has no timestamp", number + 1))?);
+            }
         }
     }
-    result
+    Ok((result, relative_times(times)))
+}
+
+/// Parse the IPv4 packets of a pcap trace, and their timestamps with `timestamps`
+fn preprocess_pcap(contents: &[u8], timestamps: bool) -> Result<(Vec<FlowId>, Vec<f64>), String> {
+    let packets = pcap::read_pcap(contents)?;
+    let times = if timestamps {
+        relative_times(packets.iter().map(|(_, _, time)| *time).collect())
+    } else {
+        Vec::new()
+    };
+    Ok((packets.into_iter().map(|(srcip, dstip, _)| FlowId { srcip, dstip }).collect(), times))
+}
+
+/// The timestamps in seconds since the first one, a timestamp going back in time being clamped to the previous one
+fn relative_times(mut times: Vec<f64>) -> Vec<f64> {
+    let first = times.first().copied().unwrap_or(0.0);
+    let mut last: f64 = 0.0;
+    for time in &mut times {
+        last = last.max(*time - first);
+        *time = last;
+    }
+    times
+}
+
+/// Run the measurements on the whole trace or, with --epoch, on each epoch of the trace (the items whose timestamps
+/// fall in the same interval of --epoch seconds), with a fresh structure per epoch. Returns the number of flows beyond
+/// their error bound in compare mode, over all epochs
+fn run_trace<H: SketchHasher + Send + Sync + 'static>(config: Config, processed: Vec<FlowId>, timestamps: Vec<f64>) -> usize {
+    let Some(epoch) = config.epoch else {
+        return run_with_hasher::<H>(config, processed, timestamps);
+    };
+    if config.heavy_changers.is_some() {
+        changers_accuracy::<H>(config, processed, timestamps);
+        return 0;
+    }
+    let mut epochs = 0;
+    let mut violations = 0;
+    let mut first = 0;
+    while first < processed.len() {
+        let index = (timestamps[first] / epoch).floor();
+        let end = (index + 1.0) * epoch;
+        let last = first + max(1, timestamps[first..].partition_point(|time| *time < end));
+        println!("EPOCH {} START {} ITEMS {}", index, index * epoch, last - first);
+        violations += run_with_hasher::<H>(config.clone(), processed[first..last].to_vec(), timestamps[first..last].to_vec());
+        epochs += 1;
+        first = last;
+    }
+    println!("EPOCHS {}", epochs);
+    violations
+}
+
+/// Detect the heavy changers between consecutive epochs with the sketch of --change-sketch: a NitroCMS without
+/// conservative update (sampling with --sample for NitroCMS), a Count Sketch, or a reversible sketch
+fn changers_accuracy<H: SketchHasher>(config: Config, processed: Vec<FlowId>, timestamps: Vec<f64>) {
+    let (confidence, error) = (config.confidence, config.error);
+    match config.change_sketch() {
+        ChangeSketchType::NitroCMS => {
+            let sample_prob = if matches!(config.ds_type, DsType::CMS) { 1.0 } else { config.sample };
+            let epochs = EpochManager::new(|| NitroCMS::<FlowId,u32,H>::new(confidence, error, sample_prob, false, ()));
+            changers_run(config, processed, timestamps, epochs);
+        }
+        ChangeSketchType::CountSketch => {
+            let epochs = EpochManager::new(|| CountSketch::<FlowId,H>::new(confidence, error));
+            changers_run(config, processed, timestamps, epochs);
+        }
+        ChangeSketchType::Reversible => {
+            let epochs = EpochManager::new(|| ReversibleSketch::<H>::new(confidence, error));
+            changers_run(config, processed, timestamps, epochs);
+        }
+    }
+}
+
+/// Count each epoch with the current sketch of `epochs`, and report each epoch, with its heavy changers for the epochs
+/// after the first. The sketches are rotated at each epoch boundary, including the boundaries of empty epochs.
+fn changers_run<S: LinearSketch + ItemIncrement>(config: Config, processed: Vec<FlowId>, timestamps: Vec<f64>, mut epochs: EpochManager<S>) {
+    let epoch = config.epoch.expect("--heavy-changers is validated to need --epoch");
+    let mut previous: HashMap<&FlowId, u32> = HashMap::new();
+    let mut current: HashMap<&FlowId, u32> = HashMap::new();
+    let mut index = timestamps.first().map_or(0.0, |time| (time / epoch).floor());
+    for (id, time) in processed.iter().zip(&timestamps) {
+        while (time / epoch).floor() > index {
+            print_changers(&config, index, &epochs, &previous, &current);
+            previous = std::mem::take(&mut current);
+            epochs.rotate();
+            index += 1.0;
+        }
+        epochs.current().item_increment(*id);
+        *current.entry(id).or_insert(0) += 1;
+    }
+    print_changers(&config, index, &epochs, &previous, &current);
+    println!("EPOCHS {}", epochs.epochs() + 1);
+}
+
+/// Report an epoch and, when it has a previous epoch, the detection of the heavy changers: the flows whose counts changed
+/// by at least --heavy-changers times the volume of the two epochs. The candidates are recovered from the sketch of the
+/// changes by the reversible sketch, and are the flows of the two epochs (a key store) for the other sketches.
+fn print_changers<S: LinearSketch>(config: &Config, index: f64, epochs: &EpochManager<S>, previous: &HashMap<&FlowId, u32>, current: &HashMap<&FlowId, u32>) {
+    let epoch = config.epoch.expect("--heavy-changers is validated to need --epoch");
+    let current_len: u32 = current.values().sum();
+    println!("EPOCH {} START {} ITEMS {}", index, index * epoch, current_len);
+    if epochs.epochs() == 0 {
+        return;
+    }
+    let phi = config.heavy_changers.expect("only called with --heavy-changers");
+    let threshold = phi * f64::from(previous.values().sum::<u32>() + current_len);
+    let change = |id: &FlowId| f64::from(*current.get(id).unwrap_or(&0)) - f64::from(*previous.get(id).unwrap_or(&0));
+    let flows: HashSet<&FlowId> = previous.keys().chain(current.keys()).copied().collect();
+    let difference = epochs.difference();
+    let reported: HashSet<FlowId> = match difference.changed_items(threshold) {
+        Some(items) => items.into_iter().collect(),
+        None => flows.iter().map(|id| **id).filter(|id| abs(difference.change_estimate(*id)) >= threshold).collect(),
+    };
+    let changers: Vec<&FlowId> = flows.iter().copied().filter(|id| abs(change(id)) >= threshold).collect();
+    let detected = changers.iter().filter(|id| reported.contains(**id)).count();
+    let avgerr = flows.iter().map(|id| abs(difference.change_estimate(**id) - change(id))).sum::<f64>() / flows.len() as f64;
+    let relerr: f64 = changers.iter().map(|id| abs((difference.change_estimate(**id) - change(id)) / change(id))).sum();
+    // no reported (true) heavy changer means no false positive (negative)
+    let precision = if reported.is_empty() { 1.0 } else { detected as f64 / reported.len() as f64 };
+    let recall = if changers.is_empty() { 1.0 } else { detected as f64 / changers.len() as f64 };
+    let f1 = if precision + recall > 0.0 { 2.0 * precision * recall / (precision + recall) } else { 0.0 };
+    println!("CHANGERS {} TRUE {}", index, changers.len());
+    println!("CHANGERS {} REPORTED {}", index, reported.len());
+    println!("CHANGERS {} PRECISION {}", index, precision);
+    println!("CHANGERS {} RECALL {}", index, recall);
+    println!("CHANGERS {} F1 {}", index, f1);
+    println!("CHANGERS {} AVGERR {}", index, avgerr);
+    println!("CHANGERS {} ARE {}", index, if changers.is_empty() { 0.0 } else { relerr / changers.len() as f64 });
+}
+
+/// Run the accuracy comparison or the timing measurement of the chosen structure, hashing with `H`.
+/// Timing measurements are preceded by a discarded (and silent) warm-up run with --warmup, and repeated --trials times on
+/// fresh structures, dumping the structure of the last trial with --dump.
+fn run_with_hasher<H: SketchHasher + Send + Sync + 'static>(config: Config, processed: Vec<FlowId>, timestamps: Vec<f64>) -> usize {
+    if config.compare {
+        if config.threads > 1 {
+            return run_parallel::<H>(config, processed).violations();
+        }
+        if let Some(decay) = config.decay() {
+            decayed_accuracy::<H>(config, processed, timestamps, decay);
+            return 0;
+        }
+        return match config.ds_type {
+            DsType::HASH => hash_accuracy::<H>(config, processed),
+            DsType::CMS => cms_accuracy::<H>(config, processed),
+            DsType::NitroCMS => nitrocms_accuracy::<H>(config, processed),
+            DsType::FPDASH => fpdash_accuracy::<H>(config, processed),
+            DsType::SpaceSaving => space_accuracy::<H>(config, processed),
+            DsType::NitroHash => nitrohash_accuracy::<H>(config, processed),
+            DsType::Cuckoo => cuckoo_accuracy::<H>(config, processed),
+            DsType::NitroCuckoo => nitrocuckoo_accuracy::<H>(config, processed),
+            DsType::FACS => facs_accuracy::<H>(config, processed),
+            DsType::HyperLogLog => {
+                hll_accuracy::<H>(config, processed);
+                0
+            }
+            //_ => (),
+        };
+    }
+    let len = processed.len();
+    let (warmup, trials) = (config.warmup, config.trials);
+    let runs = usize::from(warmup) + trials;
+    let mut times = Vec::with_capacity(trials);
+    let mut input = Some((config, processed, timestamps));
+    for run in 0..runs {
+        // the last run takes the trace, the others work on copies (and do not dump their structure)
+        let (config, processed, timestamps) = if run + 1 < runs {
+            let (config, processed, timestamps) = input.as_ref().unwrap();
+            let config = Config { dump: None, quiet: warmup && run == 0, ..config.clone() };
+            (config, processed.clone(), timestamps.clone())
+        } else {
+            input.take().unwrap()
+        };
+        if config.quiet {
+            println!("WARMUP");
+            time_with_hasher::<H>(config, processed, timestamps);
+            continue;
+        }
+        if trials > 1 {
+            println!("TRIAL {}", times.len() + 1);
+        }
+        let elapsed_time = time_with_hasher::<H>(config, processed, timestamps);
+        println!("TIMEms = {}", elapsed_time.as_micros());
+        println!("THROUGHPUT Mpps {}", mpps(len, elapsed_time));
+        times.push(elapsed_time);
+    }
+    if trials > 1 {
+        let mut micros: Vec<f64> = times.iter().map(|time| time.as_micros() as f64).collect();
+        let mut throughputs: Vec<f64> = times.iter().map(|time| mpps(len, *time)).collect();
+        let fastest = *times.iter().min().unwrap();
+        println!("TIME-MEDIANus = {}", median(&mut micros));
+        println!("TIME-MINus = {}", fastest.as_micros());
+        println!("THROUGHPUT-MEDIAN Mpps {}", median(&mut throughputs));
+        println!("THROUGHPUT-MAX Mpps {}", mpps(len, fastest));
+    }
+    0
+}
+
+/// A single timing measurement of a fresh structure
+fn time_with_hasher<H: SketchHasher + Send + Sync + 'static>(config: Config, processed: Vec<FlowId>, timestamps: Vec<f64>) -> Duration {
+    if config.threads > 1 {
+        return run_parallel::<H>(config, processed).time();
+    }
+    if let Some(decay) = config.decay() {
+        return decayed_time::<H>(config, processed, timestamps, decay);
+    }
+    match config.ds_type {
+        DsType::HASH => hash_run::<H>(config, processed),
+        DsType::CMS => cms_time::<H>(config, processed),
+        DsType::NitroCMS => nitrocms_time::<H>(config, processed),
+        DsType::FPDASH => fpdash_time::<H>(config, processed),
+        DsType::SpaceSaving => space_time::<H>(config, processed),
+        DsType::NitroHash => nitrohash_time::<H>(config, processed),
+        DsType::Cuckoo => cuckoo_time::<H>(config, processed),
+        DsType::NitroCuckoo => nitrocuckoo_time::<H>(config, processed),
+        DsType::FACS => facs_time::<H>(config, processed),
+        DsType::HyperLogLog => hll_time::<H>(config, processed),
+        //_ => (),
+    }
 }
 
 /// Perform measurements according to the specified parameters.
 /// Most importanly, timing measurements OR accuracy comparisson and memory usage
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    config.validate()?;
     //println!("{:#?}({}) {:#?} for FILE: {}", config.ds_type, config.rap, config.time_type, config.file_path);
     println!("TRACE {}", config.file_path);
     if config.compare {
@@ -387,20 +1924,102 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     } else {
         println!("TEST {:#?}", config.time_type);
     }
-    if config.rap {
-        println!("DSTYPE {:#?}-RAP", config.ds_type);
+    let dstype = if config.rap {
+        format!("{:#?}-RAP", config.ds_type)
     } else if config.compact {
-        println!("DSTYPE {:#?}-SMALL", config.ds_type);
+        format!("{:#?}-SMALL", config.ds_type)
+    } else if config.nested_layout && config.avoid_mi {
+        format!("{:#?}-NOMI-NESTED", config.ds_type)
+    } else if config.nested_layout {
+        format!("{:#?}-NESTED", config.ds_type)
     } else if config.avoid_mi {
-        println!("DSTYPE CMS-NOMI");
+        format!("{:#?}-NOMI", config.ds_type)
+    } else if config.adaptive {
+        format!("{:#?}-ADAPTIVE", config.ds_type)
+    } else if config.always_correct {
+        format!("{:#?}-ALWAYSCORRECT", config.ds_type)
+    } else {
+        format!("{:#?}", config.ds_type)
+    };
+    let dstype = if config.element_sampling {
+        format!("{}-ELEMENTS", dstype)
+    } else {
+        dstype
+    };
+    let dstype = if matches!(config.ds_type, DsType::FPDASH) {
+        format!("{}-FP{}", dstype, config.fp_size)
     } else {
-        println!("DSTYPE {:#?}", config.ds_type);
+        dstype
+    };
+    let dstype = if matches!(config.ds_type, DsType::FACS) {
+        format!("{}-{}-{}-W{}", dstype, config.facs_window(), config.facs_permanent(), config.facs_window_size())
+    } else {
+        dstype
+    };
+    let dstype = match config.window {
+        Some(window) if config.window_panes() != 8 => format!("{}-WINDOW{}-P{}", dstype, window, config.window_panes()),
+        Some(window) => format!("{}-WINDOW{}", dstype, window),
+        None => dstype,
+    };
+    let dstype = match (config.half_life, config.halving_period) {
+        (Some(half_life), _) => format!("{}-HALFLIFE{}", dstype, half_life),
+        (_, Some(period)) => format!("{}-HALVING{}", dstype, period),
+        _ => dstype,
+    };
+    let dstype = if config.float_counters {
+        format!("{}-FLOAT", dstype)
+    } else {
+        dstype
+    };
+    let dstype = if config.timestamps {
+        format!("{}-TIME", dstype)
+    } else {
+        dstype
+    };
+    let dstype = match config.epoch {
+        Some(epoch) => format!("{}-EPOCH{}", dstype, epoch),
+        None => dstype,
+    };
+    let dstype = match config.heavy_changers {
+        Some(phi) => format!("{}-CHANGERS{}-{}", dstype, phi, config.change_sketch()),
+        None => dstype,
+    };
+    let dstype = if config.facs_background {
+        format!("{}-BG", dstype)
+    } else {
+        dstype
+    };
+    let dstype = match config.bucket_size {
+        Some(bucket_size) if bucket_size != 4 => format!("{}-B{}", dstype, bucket_size),
+        _ => dstype,
+    };
+    let dstype = if config.thread_local {
+        format!("{}-T{}-LOCAL", dstype, config.threads)
+    } else if config.threads > 1 {
+        format!("{}-T{}", dstype, config.threads)
+    } else {
+        dstype
+    };
+    let dstype = match &config.hash {
+        Some(hash) => format!("{}-{:?}", dstype, hash),
+        None => dstype,
+    };
+    if config.batch_size > 1 {
+        println!("DSTYPE {}-BATCH{}", dstype, config.batch_size);
+    } else {
+        println!("DSTYPE {}", dstype);
     }
-    let contents = fs::read_to_string(config.file_path.clone())?;
+    let parse_start = Instant::now();
+    let contents = fs::read(config.file_path.clone())?;
     if config.verbose {
         println!("PREPROCESSING DONE");
     }
-    let processed = preprocess_contents(contents);
+    let (processed, timestamps) = if pcap::is_pcap(&contents) {
+        preprocess_pcap(&contents, config.timestamps)?
+    } else {
+        preprocess_contents(String::from_utf8(contents)?, config.timestamps)?
+    };
+    println!("PARSEus = {}", parse_start.elapsed().as_micros());
     #[cfg(feature = "stats")]
     let mem_allocated:usize;
     #[cfg(feature = "stats")]
@@ -416,38 +2035,14 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
         max_allocated = ALLOCATOR.max_allocated();
         println!("{}", max_allocated);
     }
-    if config.compare {
-        match config.ds_type {
-            DsType::HASH => hash_accuracy(config, processed),
-            DsType::CMS => if config.avoid_mi {
-                                nitrocms_accuracy(config, processed)
-                            } else {
-                                cms_accuracy(config, processed)
-                            },
-            DsType::NitroCMS => nitrocms_accuracy(config, processed),
-            DsType::FPDASH => (),
-            DsType::SpaceSaving => space_accuracy(config, processed),
-            DsType::NitroHash => nitrohash_accuracy(config, processed),
-            DsType::Cuckoo => cuckoo_accuracy(config, processed),
-            DsType::NitroCuckoo => nitrocuckoo_accuracy(config, processed),
-            DsType::FACS => facs_accuracy(config, processed),
-            //_ => (),
-        };
-    } else {
-        let elapsed_time  = match config.ds_type {
-            DsType::HASH => hash_run(config, processed),
-            DsType::CMS => cms_time(config, processed),
-            DsType::NitroCMS => nitrocms_time(config, processed),
-            DsType::FPDASH => fpdash_run(config, processed),
-            DsType::SpaceSaving => space_time(config, processed),
-            DsType::NitroHash => nitrohash_time(config, processed),
-            DsType::Cuckoo => cuckoo_time(config, processed),
-            DsType::NitroCuckoo => nitrocuckoo_time(config, processed),
-            DsType::FACS => facs_time(config, processed),
-            //_ => (),
-        };
-        println!("TIMEms = {}", elapsed_time.as_micros());
-    }
+    let violations = match config.hash.clone().unwrap_or_else(|| config.ds_type.default_hash()) {
+        HashType::SipHash => run_trace::<DefaultHasher>(config, processed, timestamps),
+        HashType::XxHash64 => run_trace::<XxHash64>(config, processed, timestamps),
+        HashType::Xxh3 => run_trace::<Xxh3Hasher>(config, processed, timestamps),
+        HashType::FxHash => run_trace::<FxHasher>(config, processed, timestamps),
+        HashType::Tabulation => run_trace::<TabulationHasher>(config, processed, timestamps),
+        HashType::Weak => run_trace::<WeakHasher>(config, processed, timestamps),
+    };
     #[cfg(feature = "stats")]
     {
         println!("{}", ALLOCATOR.allocated());
@@ -456,6 +2051,9 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
         println!("{}", ALLOCATOR.max_allocated() - mem_allocated);
     }
     println!("END");
+    if violations > 0 {
+        return Err(format!("the error bound was violated for {} flows, more than a fraction delta of the flows", violations).into());
+    }
     Ok(())
 }
 
@@ -478,6 +2076,27 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
 //        .collect()
 //}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // most flows are single packets, whose sampled estimates are 0 or 1/p: their interval must still cover them
+    #[test]
+    fn test_print_bounds_sampled_mice() {
+        let config = Config::parse_from(["test", "-f", "trace", "-d", "NitroCMS", "-e", "0.00001", "-s", "0.1", "--element-sampling", "--compare"]);
+        let mice = (0..30_000_u32).map(|i| FlowId { srcip: Ipv4Addr::from(i), dstip: Ipv4Addr::from(1) });
+        let elephants = (0..10_u32).flat_map(|i| std::iter::repeat_n(FlowId { srcip: Ipv4Addr::from(i), dstip: Ipv4Addr::from(2) }, 1000));
+        let processed: Vec<FlowId> = mice.chain(elephants).collect();
+        let mut counts = nitrocms_new::<Xxh3Hasher>(&config);
+        let mut baseline: HashMap<&FlowId, u32> = HashMap::new();
+        for id in &processed {
+            counts.item_increment(*id);
+            *baseline.entry(id).or_insert(0) += 1;
+        }
+        assert_eq!(print_bounds(&config, processed.len(), &baseline, &counts), 0);
+    }
+}
+
 //#[cfg(test)]
 //mod tests {
 //    use super::*;
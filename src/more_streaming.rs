@@ -1,10 +1,27 @@
 pub mod nitro_cms;
+pub mod nitro_cms_nested;
 pub mod space_saving;
 pub mod nitro_hash;
 pub mod cuckoo;
 pub mod nitro_cuckoo;
 pub mod facs;
 pub mod traits;
+pub mod adaptive;
+pub mod hashing;
+pub mod atomic_nitro_cms;
+pub mod striped;
+pub mod thread_local_merge;
+pub mod fp_dash;
+pub mod count_sketch;
+pub mod latency;
+pub mod wcss;
+pub mod sliding_cms;
+pub mod decay;
+pub mod decayed_cms;
+pub mod decayed_space_saving;
+pub mod change_detection;
+pub mod reversible_sketch;
+pub mod hyperloglog;
 
 #[allow(
     clippy::cast_possible_truncation,
@@ -16,3 +33,257 @@ pub fn f64_to_usize(a: f64) -> usize {
     assert!(a.is_sign_positive() && a <= usize::max_value() as f64 && a.fract() == 0.0);
     a as usize
 }
+
+/// Hint the CPU to bring the cache line holding `value` into the cache ahead of its use
+/// (a no-op on architectures other than x86_64)
+#[inline(always)]
+pub fn prefetch<T>(value: &T) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        std::arch::x86_64::_mm_prefetch(value as *const T as *const i8, std::arch::x86_64::_MM_HINT_T0);
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = value;
+}
+
+/// Quantile function (inverse CDF) of the standard normal distribution,
+/// using the rational approximation of Acklam (relative error below 1.2e-9)
+#[allow(clippy::excessive_precision)]
+pub fn normal_quantile(p: f64) -> f64 {
+    assert!(p > 0.0 && p < 1.0);
+    const A: [f64; 6] = [-3.969683028665376e+01, 2.209460984245205e+02, -2.759285104469687e+02, 1.383577518672690e+02, -3.066479806614716e+01, 2.506628277459239e+00];
+    const B: [f64; 5] = [-5.447609879822406e+01, 1.615858368580409e+02, -1.556989798598866e+02, 6.680131188771972e+01, -1.328068155288572e+01];
+    const C: [f64; 6] = [-7.784894002430293e-03, -3.223964580411365e-01, -2.400758277161838e+00, -2.549732539343734e+00, 4.374664141464968e+00, 2.938163982698783e+00];
+    const D: [f64; 4] = [7.784695709041462e-03, 3.224671290700398e-01, 2.445134137142996e+00, 3.754408661907416e+00];
+    const P_LOW: f64 = 0.02425;
+    if p < P_LOW {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5]) / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else if p <= 1.0 - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -normal_quantile(1.0 - p)
+    }
+}
+
+/// Natural logarithm of the gamma function, for x > 0 (Lanczos approximation, g = 7, relative error below 1e-15)
+#[allow(clippy::excessive_precision)]
+fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const C: [f64; 9] = [0.99999999999980993, 676.5203681218851, -1259.1392167224028, 771.32342877765313, -176.61502916214059,
+        12.507343278686905, -0.13857109526572012, 9.9843695780195716e-6, 1.5056327351493116e-7];
+    if x < 0.5 {
+        // reflection formula
+        return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + G + 0.5;
+    let series = C[1..].iter().enumerate().fold(C[0], |sum, (i, c)| sum + c / (x + i as f64 + 1.0));
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+/// Regularized incomplete beta function I_x(a, b), for a, b > 0 and x in [0,1]: the probability that a Beta(a, b)
+/// variable is at most x, so that I_p(k, n-k+1) = P(Binomial(n, p) >= k). Evaluated by the continued fraction of
+/// Numerical Recipes (modified Lentz), on the side of the symmetry I_x(a, b) = 1 - I_{1-x}(b, a) where it converges.
+fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 100_000;
+    const EPSILON: f64 = 1e-15;
+    const TINY: f64 = 1e-300;
+    let clamp = |value: f64| if value.abs() < TINY { TINY } else { value };
+    let mut c = 1.0;
+    let mut d = 1.0 / clamp(1.0 - (a + b) * x / (a + 1.0));
+    let mut fraction = d;
+    for m in 1..MAX_ITERATIONS {
+        let m = m as f64;
+        // even step, then odd step of the continued fraction
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / clamp(1.0 + even * d);
+        c = clamp(1.0 + even / c);
+        fraction *= d * c;
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / clamp(1.0 + odd * d);
+        c = clamp(1.0 + odd / c);
+        let step = d * c;
+        fraction *= step;
+        if (step - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    fraction
+}
+
+/// The `n` in [low, high] where the monotone `f` crosses `target`, `f(low)` and `f(high)` being on either side of it
+fn bisect(f: impl Fn(f64) -> f64, target: f64, mut low: f64, mut high: f64) -> f64 {
+    let below_at_low = f(low) < target;
+    for _ in 0..200 {
+        let middle = (low + high) / 2.0;
+        if high - low <= 1e-9 * high.max(1.0) {
+            break;
+        }
+        if (f(middle) < target) == below_at_low {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// Two-sided confidence interval, with confidence 1-delta, around an estimate `n/p` obtained by sampling
+/// each of `n` arrivals independently with probability `p`. The sampled count `x` is binomial, so we
+/// return the exact (Clopper–Pearson) set of counts `n` under which neither `P(X >= x)` nor `P(X <= x)` is below
+/// delta/2: the interval covers the count with probability at least 1-delta whatever the count, including the small
+/// counts of the mice flows, for which normal approximations undercover. Its lower end is never below `x`, and the
+/// interval of a flow that was never sampled is [0, ln(delta/2)/ln(1-p)]. The interval bounds `n` as a real number,
+/// extending the binomial distribution by the incomplete beta function.
+pub fn sampling_confidence_interval(estimate: f64, sample_prob: f64, delta: f64) -> (f64, f64) {
+    if sample_prob >= 1.0 {
+        return (estimate, estimate);
+    }
+    let alpha = delta / 2.0;
+    let x = estimate * sample_prob;
+    // the Wilson score interval, an approximation of the exact one, seeds the searches
+    let z = normal_quantile(1.0 - alpha);
+    let b = 2.0 * x + z * z * (1.0 - sample_prob);
+    let root = (b * b - 4.0 * x * x).max(0.0).sqrt();
+    let (wilson_low, wilson_high) = (((b - root) / 2.0) / sample_prob, ((b + root) / 2.0) / sample_prob);
+    // P(X >= x) grows with n, from p^x at n = x
+    let low = if x <= 0.0 || sample_prob.powf(x) >= alpha {
+        x.max(0.0)
+    } else {
+        let at_least = |n: f64| regularized_incomplete_beta(sample_prob, x, n - x + 1.0);
+        let mut start = wilson_low.max(x);
+        while at_least(start) >= alpha && start > x {
+            start = x + (start - x) / 2.0;
+        }
+        let mut end = estimate.max(start + 1.0);
+        while at_least(end) < alpha {
+            end = x + 2.0 * (end - x);
+        }
+        bisect(at_least, alpha, start, end)
+    };
+    // P(X <= x) decreases with n, from 1 at n = x
+    let at_most = |n: f64| regularized_incomplete_beta(1.0 - sample_prob, n - x, x + 1.0);
+    let start = estimate.max(x + 1e-9);
+    let mut end = wilson_high.max(start + 1.0);
+    while at_most(end) >= alpha {
+        end = x + 2.0 * (end - x);
+    }
+    let high = if at_most(start) < alpha { start } else { bisect(at_most, alpha, start, end) };
+    (low, high)
+}
+
+/// Error bound ε·volume of a count-min sketch with tolerance `error` after `volume` arrivals. Its rows have at
+/// least 2/ε counters, so each row overestimates an item by more than ε·volume with probability at most 1/2
+/// (Markov's inequality), and the minimum of the k = floor(ln 1/δ) rows with probability at most 2^-k: the bound
+/// fails for about 6% of the items at δ = 0.01, not for a fraction δ of them
+pub fn sketch_error_bound(error: f64, volume: u64) -> f64 {
+    error * volume as f64
+}
+
+/// The probability 2^-k that the minimum of the k rows of a count-min sketch exceeds `sketch_error_bound`
+pub fn sketch_bound_failure(k_num: usize) -> f64 {
+    0.5_f64.powi(i32::try_from(k_num).unwrap_or(i32::MAX))
+}
+
+/// The largest distance between `estimate` and the ends of its confidence `interval`
+pub fn interval_error_bound(estimate: f64, interval: (f64, f64)) -> f64 {
+    (estimate - interval.0).max(interval.1 - estimate)
+}
+
+/// The median of `values` (the mean of the two middle values for an even number of values), reordering them
+pub fn median(values: &mut [f64]) -> f64 {
+    assert!(!values.is_empty());
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[middle]
+    } else {
+        (values[middle - 1] + values[middle]) / 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_normal_quantile() {
+        assert!((super::normal_quantile(0.975) - 1.959964).abs() < 1e-6);
+        assert!((super::normal_quantile(0.5)).abs() < 1e-9);
+        assert!((super::normal_quantile(0.001) + 3.090232).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sampling_confidence_interval() {
+        let (low, high) = super::sampling_confidence_interval(1000.0, 0.1, 0.05);
+        // the smallest and largest integer counts in the exact interval are 823 and 1205
+        assert!((822.0..=823.0).contains(&low) && (1205.0..=1206.0).contains(&high), "({}, {})", low, high);
+        let (low, high) = super::sampling_confidence_interval(0.0, 0.1, 0.05);
+        assert!(low == 0.0 && (high - 0.025_f64.ln() / 0.9_f64.ln()).abs() < 1e-6);
+        let (low, _) = super::sampling_confidence_interval(20.0, 0.1, 0.05);
+        assert!(low >= 2.0);
+        assert_eq!(super::sampling_confidence_interval(10.0, 1.0, 0.05), (10.0, 10.0));
+    }
+
+    #[test]
+    fn test_incomplete_beta() {
+        // P(Binomial(10, 0.3) >= 3) = 0.6172172
+        assert!((super::regularized_incomplete_beta(0.3, 3.0, 8.0) - 0.6172172).abs() < 1e-6);
+        assert!((super::regularized_incomplete_beta(0.5, 2.0, 2.0) - 0.5).abs() < 1e-12);
+        assert!((super::ln_gamma(10.0) - 362880.0_f64.ln()).abs() < 1e-10);
+    }
+
+    /// The probability that `n` arrivals, sampled with probability `p`, give an interval covering `n`
+    fn coverage(n: u64, p: f64, delta: f64) -> f64 {
+        let mut probability = (1.0 - p).powi(n as i32);
+        let mut covered = 0.0;
+        for x in 0..=n {
+            let (low, high) = super::sampling_confidence_interval(x as f64 / p, p, delta);
+            if low <= n as f64 && n as f64 <= high {
+                covered += probability;
+            }
+            // P(X = x+1) from P(X = x)
+            probability *= (n - x) as f64 / (x + 1) as f64 * p / (1.0 - p);
+        }
+        covered
+    }
+
+    #[test]
+    fn test_small_count_coverage() {
+        for p in [0.01, 0.1, 0.5] {
+            for n in 1..=60 {
+                let covered = coverage(n, p, 0.05);
+                assert!(covered >= 0.95 - 1e-9, "count {} at p = {}: coverage {}", n, p, covered);
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_bounds() {
+        assert_eq!(super::sketch_error_bound(0.01, 1_000_000), 10000.0);
+        assert_eq!(super::interval_error_bound(100.0, (80.0, 130.0)), 30.0);
+        assert_eq!(super::interval_error_bound(10.0, (10.0, 10.0)), 0.0);
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(super::median(&mut [3.0, -1.0, 2.0]), 2.0);
+        assert_eq!(super::median(&mut [4.0, 1.0, -2.0, 3.0]), 2.0);
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+/// Number of packets between two consecutive checks of the time budget
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// Adaptive sampling rate for the Nitro variants, following the idea of NitroSketch
+/// (https://dl.acm.org/doi/10.1145/3341302.3342076) to adapt the sampling probability to the line rate.
+/// The rate starts at p = 1 and is halved (p = 2^-level) each time the counted volume doubles past
+/// `volume_threshold`, or whenever the average time per packet exceeds `time_budget`, but never below `min_prob`.
+/// Structures using an adaptive rate add 1/p = 2^level to a counter on each sampled update, so their counters
+/// already hold (unbiased) estimates, consistent across rate changes.
+#[derive(Debug, Clone)]
+pub struct AdaptiveRate {
+    level: u32,
+    max_level: u32,
+    volume: u64,
+    next_volume: u64,
+    time_budget: Option<Duration>,
+    window_start: Instant,
+    window_packets: u64,
+    changes: usize,
+}
+
+impl AdaptiveRate {
+    /// `min_prob` is rounded up to a power of two, so that p stops at the smallest power of two not below it;
+    /// a `volume_threshold` of 0 disables volume based adaptation
+    pub fn new(min_prob: f64, volume_threshold: u64, time_budget: Option<Duration>) -> Self {
+        assert!(min_prob > 0.0 && min_prob <= 1.0);
+        let max_level = (1.0 / min_prob).log2().floor() as u32;
+        Self {
+            level: 0,
+            max_level,
+            volume: 0,
+            next_volume: if volume_threshold == 0 { u64::MAX } else { volume_threshold },
+            time_budget,
+            window_start: Instant::now(),
+            window_packets: 0,
+            changes: 0,
+        }
+    }
+
+    /// Account for one arriving packet. Returns the new sampling probability if it has just changed.
+    #[inline]
+    pub fn observe(&mut self) -> Option<f64> {
+        self.volume += 1;
+        if self.level >= self.max_level {
+            return None;
+        }
+        let mut lower = false;
+        if self.volume >= self.next_volume {
+            self.next_volume = self.next_volume.saturating_mul(2);
+            lower = true;
+        }
+        if let Some(budget) = self.time_budget {
+            self.window_packets += 1;
+            if self.window_packets == TIME_CHECK_INTERVAL {
+                let now = Instant::now();
+                if now.duration_since(self.window_start) > budget * (TIME_CHECK_INTERVAL as u32) {
+                    lower = true;
+                }
+                self.window_start = now;
+                self.window_packets = 0;
+            }
+        }
+        if lower {
+            self.level += 1;
+            self.changes += 1;
+            Some(self.sample_prob())
+        } else {
+            None
+        }
+    }
+
+    /// The current sampling probability
+    pub fn sample_prob(&self) -> f64 {
+        1.0 / self.weight() as f64
+    }
+
+    /// The amount added to a counter on a sampled update, i.e., 1/p
+    pub fn weight(&self) -> usize {
+        1_usize << self.level
+    }
+
+    /// The number of times the sampling probability was lowered
+    pub fn changes(&self) -> usize {
+        self.changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_volume_adaptation() {
+        let mut rate = super::AdaptiveRate::new(0.1, 100, None);
+        let changes: Vec<u64> = (1..=10_000_u64).filter(|_| rate.observe().is_some()).collect();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(rate.sample_prob(), 1.0 / 8.0);
+        assert_eq!(rate.weight(), 8);
+    }
+
+    #[test]
+    fn test_min_prob() {
+        for (min_prob, expected) in [(0.3, 0.5), (0.25, 0.25), (0.01, 1.0 / 64.0), (1.0, 1.0)] {
+            let mut rate = super::AdaptiveRate::new(min_prob, 1, None);
+            (0..1_000_000).for_each(|_| { rate.observe(); });
+            assert_eq!(rate.sample_prob(), expected, "min_prob = {}", min_prob);
+        }
+    }
+}
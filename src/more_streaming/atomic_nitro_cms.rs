@@ -0,0 +1,191 @@
+use std::{
+	borrow::Borrow, cell::Cell, fmt, hash::Hash, marker::PhantomData, sync::atomic::{AtomicU32, Ordering}
+};
+use super::{f64_to_usize, sampling_confidence_interval};
+use super::nitro_cms::{key_hash, row_hash, NitroCMS};
+use super::hashing::{SketchHasher, Xxh3Hasher};
+use rand;
+use rand_distr::{Geometric, Distribution};
+
+thread_local! {
+	// the position of the next sampled counter of the calling thread, relative to its current packet
+	static NEXT_ROW: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A NitroCMS whose counters are atomic, so that several threads can update and query it at once.
+/// Each thread samples its own counters: the counters to skip are counted per thread, which does not change their
+/// (memoryless, geometric) distribution. Sampled counters are incremented with a relaxed `fetch_add`.
+/// Conservative update is not supported, as two racing updates of a key could read the same minimum and count once.
+pub struct AtomicNitroCMS<K: ?Sized, H = Xxh3Hasher> {
+	counters: Vec<AtomicU32>, // k_num rows of width counters, stored contiguously
+	width: usize,
+	shift: u32,
+	k_num: usize,
+	geo: Geometric,
+	sample_prob: f64,
+	marker: PhantomData<fn(K) -> H>,
+}
+
+impl<K: ?Sized, H> AtomicNitroCMS<K, H>
+where
+	K: Hash,
+	H: SketchHasher,
+{
+	/// Create an empty `AtomicNitroCMS` with the same dimensions as a `NitroCMS` with the specified error tolerance.
+	pub fn new(probability: f64, tolerance: f64, sample_prob: f64) -> Self {
+		let width = NitroCMS::<K, u32, H>::optimal_width(tolerance);
+		let k_num = NitroCMS::<K, u32, H>::optimal_k_num(probability);
+		Self {
+			counters: (0..k_num * width).map(|_| AtomicU32::new(0)).collect(),
+			width,
+			shift: NitroCMS::<K, u32, H>::shift(width),
+			k_num,
+			geo: Geometric::new(sample_prob).unwrap(),
+			sample_prob,
+			marker: PhantomData,
+		}
+	}
+
+	/// "Visit" an element, from any thread
+	pub fn push<Q>(&self, key: &Q)
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		if self.sample_prob == 1.0 {
+			let hash = key_hash::<Q, H>(key);
+			for row in 0..self.k_num {
+				self.counters[self.single_offset(hash, row)].fetch_add(1, Ordering::Relaxed);
+			}
+			return;
+		}
+		// as in `NitroCMS`, counters are numbered consecutively across the packets of the thread,
+		// and the key is only hashed if one of its rows is sampled
+		NEXT_ROW.with(|next_row| {
+			let mut row = next_row.get();
+			let mut hash = None;
+			while row < self.k_num {
+				let hash = *hash.get_or_insert_with(|| key_hash::<Q, H>(key));
+				self.counters[self.single_offset(hash, row)].fetch_add(1, Ordering::Relaxed);
+				row += 1 + self.geo.sample(&mut rand::thread_rng()) as usize;
+			}
+			next_row.set(row - self.k_num);
+		});
+	}
+
+	/// Retrieve an estimate of the count of `key`: `estimate_f64` rounded to the nearest integer.
+	pub fn get<Q>(&self, key: &Q) -> u32
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		u32::try_from(f64_to_usize(self.estimate_f64(key).round())).unwrap_or(u32::MAX)
+	}
+
+	/// Retrieve an unbiased estimate of the count of `key`, scaling the sampled counters by 1/p.
+	pub fn estimate_f64<Q>(&self, key: &Q) -> f64
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		let hash = key_hash::<Q, H>(key);
+		let min = (0..self.k_num)
+			.map(|row| self.counters[self.single_offset(hash, row)].load(Ordering::Relaxed))
+			.min()
+			.unwrap();
+		f64::from(min) / self.sample_prob
+	}
+
+	/// Retrieve a confidence interval, holding with probability 1-delta, for the count of `key` (see `NitroCMS`).
+	pub fn confidence_interval<Q>(&self, key: &Q, delta: f64) -> (f64, f64)
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		sampling_confidence_interval(self.estimate_f64(key), self.sample_prob, delta)
+	}
+
+	/// Iterate over the non-zero counters as (row, column, counter).
+	pub fn iter(&self) -> impl Iterator<Item = (usize, usize, u32)> + '_ {
+		let width = self.width;
+		self.counters
+			.iter()
+			.map(|counter| counter.load(Ordering::Relaxed))
+			.enumerate()
+			.filter(|(_, counter)| *counter != 0)
+			.map(move |(offset, counter)| (offset / width, offset % width, counter))
+	}
+
+	/// returns an estimation of the memory used
+	pub fn estimate_memory_size(&self) -> usize {
+		self.counters.len() * std::mem::size_of::<AtomicU32>()
+	}
+
+	/// The number of counters in each row
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	/// The number of rows
+	pub fn k_num(&self) -> usize {
+		self.k_num
+	}
+
+	/// The current sampling probability
+	pub fn sample_prob(&self) -> f64 {
+		self.sample_prob
+	}
+
+	/// The position of the counter of a hashed key in `row`
+	fn single_offset(&self, hash: (u64, u64), row: usize) -> usize {
+		row * self.width + usize::try_from(row_hash(hash, row) >> self.shift).unwrap()
+	}
+}
+
+impl<K: ?Sized, H> fmt::Debug for AtomicNitroCMS<K, H> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("AtomicNitroCMS")
+			.field("width", &self.width)
+			.field("k_num", &self.k_num)
+			.field("sample_prob", &self.sample_prob)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::thread;
+	type AtomicNitroCMS<K> = super::AtomicNitroCMS<K>;
+
+	#[test]
+	fn test_concurrent_increment() {
+		let cms = AtomicNitroCMS::<&str>::new(0.01, 2.0 / 100.0, 1.0);
+		thread::scope(|scope| {
+			for _ in 0..4 {
+				scope.spawn(|| {
+					for _ in 0..100_000 {
+						cms.push("key");
+					}
+				});
+			}
+		});
+		assert_eq!(cms.get("key"), 400_000);
+	}
+
+	#[test]
+	fn test_concurrent_sampled_increment() {
+		let cms = AtomicNitroCMS::<&str>::new(0.01, 2.0 / 100.0, 0.1);
+		thread::scope(|scope| {
+			for _ in 0..4 {
+				scope.spawn(|| {
+					for _ in 0..100_000 {
+						cms.push("key");
+					}
+				});
+			}
+		});
+		// every row of the key counts its ~40000 sampled updates, and the minimum is within a few standard deviations
+		let estimate = cms.estimate_f64("key");
+		assert!((estimate - 400_000.0).abs() < 0.03 * 400_000.0, "estimate {estimate}");
+	}
+}
@@ -0,0 +1,159 @@
+use std::{
+	borrow::Borrow, fmt, hash::Hash, marker::PhantomData, mem
+};
+use super::nitro_cms::{key_hash, NitroCMS};
+use super::count_sketch::offset_and_sign;
+use super::hashing::{SketchHasher, Xxh3Hasher};
+use super::median;
+use super::traits::LinearSketch;
+
+/// The sketch of the changes of the counts between two epochs: the difference of the counters of two linear sketches
+/// with the same dimensions and hashes (see `NitroCMS::difference` and `CountSketch::difference`).
+/// The change of a key is estimated by the median over the rows of its counter, signed for the Count Sketch; for the
+/// count-min sketch, as in the k-ary sketch of Krishnamurthy, Sen, Zhang and Chen, the average counter of the row
+/// (the expected contribution of the colliding keys) is subtracted from the counter, scaled by w/(w-1).
+pub struct DifferenceSketch<K: ?Sized, H = Xxh3Hasher> {
+	counters: Vec<f64>, // k_num rows of width counters, stored contiguously
+	row_sums: Vec<f64>,
+	width: usize,
+	shift: u32,
+	k_num: usize,
+	signed: bool,
+	marker: PhantomData<fn(K) -> H>,
+}
+
+impl<K: ?Sized, H> DifferenceSketch<K, H>
+where
+	K: Hash,
+	H: SketchHasher,
+{
+	pub(super) fn new(counters: Vec<f64>, width: usize, k_num: usize, signed: bool) -> Self {
+		assert_eq!(counters.len(), width * k_num);
+		Self {
+			row_sums: counters.chunks(width).map(|row| row.iter().sum()).collect(),
+			counters,
+			width,
+			shift: NitroCMS::<K, u32, H>::shift(width),
+			k_num,
+			signed,
+			marker: PhantomData,
+		}
+	}
+
+	/// Retrieve an estimate of the change of the count of `key`.
+	pub fn estimate<Q>(&self, key: &Q) -> f64
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		let hash = key_hash::<Q, H>(key);
+		let width = self.width as f64;
+		let mut estimates: Vec<f64> = (0..self.k_num)
+			.map(|row| {
+				let (offset, sign) = offset_and_sign(hash, row, self.width, self.shift);
+				if self.signed {
+					sign as f64 * self.counters[offset]
+				} else {
+					(self.counters[offset] - self.row_sums[row] / width) / (1.0 - 1.0 / width)
+				}
+			})
+			.collect();
+		median(&mut estimates)
+	}
+
+	/// returns an estimation of the memory used
+	pub fn estimate_memory_size(&self) -> usize {
+		self.counters.len() * std::mem::size_of::<f64>()
+	}
+}
+
+impl<K: ?Sized, H> fmt::Debug for DifferenceSketch<K, H> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("DifferenceSketch")
+			.field("width", &self.width)
+			.field("k_num", &self.k_num)
+			.field("signed", &self.signed)
+			.finish()
+	}
+}
+
+/// Keeps the sketches of the current and of the previous epoch, for detecting the changes between consecutive epochs.
+/// The sketch of the epoch that ends is kept as the previous one, and the sketch of the epoch before it is cleared and
+/// reused for the next epoch.
+#[derive(Debug)]
+pub struct EpochManager<S> {
+	current: S,
+	previous: S,
+	epochs: usize,
+}
+
+impl<S: LinearSketch> EpochManager<S> {
+	/// Start the first epoch, with two empty sketches of the same dimensions and hashes made by `new_sketch`
+	pub fn new(new_sketch: impl Fn() -> S) -> Self {
+		Self { current: new_sketch(), previous: new_sketch(), epochs: 0 }
+	}
+
+	/// The sketch of the current epoch
+	pub fn current(&mut self) -> &mut S {
+		&mut self.current
+	}
+
+	/// The sketch of the previous epoch (empty during the first epoch)
+	pub fn previous(&self) -> &S {
+		&self.previous
+	}
+
+	/// The number of epochs that ended
+	pub fn epochs(&self) -> usize {
+		self.epochs
+	}
+
+	/// End the current epoch and start the next one
+	pub fn rotate(&mut self) {
+		mem::swap(&mut self.current, &mut self.previous);
+		self.current.clear();
+		self.epochs += 1;
+	}
+
+	/// The sketch of the changes from the previous epoch to the current one
+	pub fn difference(&self) -> S::Difference {
+		self.current.difference(&self.previous)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::FlowId;
+	use super::super::count_sketch::CountSketch;
+	use super::super::nitro_cms::NitroCMS;
+	use super::EpochManager;
+
+	#[test]
+	fn test_difference() {
+		let mut cms = EpochManager::new(|| NitroCMS::<FlowId, u32>::new(0.01, 0.01, 1.0, false, ()));
+		let mut cs = EpochManager::new(|| CountSketch::<FlowId>::new(0.01, 0.01));
+		// flow 0 grows by 1000 and flow 1 shrinks by 1000 against a background of stable flows
+		for epoch in 0..3 {
+			for key in 2..2000_u64 {
+				cms.current().push(&FlowId::from(key), &5);
+				cs.current().push(&FlowId::from(key), 5);
+			}
+			let (grown, shrunk) = if epoch == 2 { (1500, 500) } else { (500, 1500) };
+			cms.current().push(&FlowId::from(0), &grown);
+			cms.current().push(&FlowId::from(1), &shrunk);
+			cs.current().push(&FlowId::from(0), grown.into());
+			cs.current().push(&FlowId::from(1), shrunk.into());
+			if epoch < 2 {
+				cms.rotate();
+				cs.rotate();
+			}
+		}
+		assert_eq!(cms.epochs(), 2);
+		let (cms, cs) = (cms.difference(), cs.difference());
+		for (key, change) in [(0, 1000.0), (1, -1000.0), (2, 0.0)] {
+			let id = FlowId::from(key);
+			assert!((cms.estimate(&id) - change).abs() < 100.0, "{} {}", key, cms.estimate(&id));
+			assert!((cs.estimate(&id) - change).abs() < 100.0, "{} {}", key, cs.estimate(&id));
+		}
+	}
+}
@@ -0,0 +1,129 @@
+use std::{
+	borrow::Borrow, fmt, hash::Hash, marker::PhantomData
+};
+use super::nitro_cms::{key_hash, row_hash, NitroCMS};
+use super::hashing::{SketchHasher, Xxh3Hasher};
+use super::change_detection::DifferenceSketch;
+
+/// The Count Sketch of Charikar, Chen and Farach-Colton: each row adds the value of a key, with a random sign,
+/// to one counter, and the estimate is the median of the signed counters of the key over the rows.
+/// Unlike the count-min sketch the estimates are unbiased, and can be too high or too low.
+/// It has the dimensions of a `NitroCMS` with the same parameters, and derives its row hashes the same way;
+/// the sign of a row is the hash bit below the bits of the column.
+pub struct CountSketch<K: ?Sized, H = Xxh3Hasher> {
+	counters: Vec<i64>, // k_num rows of width counters, stored contiguously
+	width: usize,
+	shift: u32,
+	k_num: usize,
+	marker: PhantomData<fn(K) -> H>,
+}
+
+impl<K: ?Sized, H> CountSketch<K, H>
+where
+	K: Hash,
+	H: SketchHasher,
+{
+	/// Create an empty `CountSketch` with the specified error tolerance (and an odd number of rows, for the median).
+	pub fn new(probability: f64, tolerance: f64) -> Self {
+		let width = NitroCMS::<K, u32, H>::optimal_width(tolerance);
+		let k_num = NitroCMS::<K, u32, H>::optimal_k_num(probability) | 1;
+		Self {
+			counters: vec![0; k_num * width],
+			width,
+			shift: NitroCMS::<K, u32, H>::shift(width),
+			k_num,
+			marker: PhantomData,
+		}
+	}
+
+	/// Add `value` to the count of `key`
+	pub fn push<Q>(&mut self, key: &Q, value: i64)
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		let hash = key_hash::<Q, H>(key);
+		for row in 0..self.k_num {
+			let (offset, sign) = self.offset_and_sign(hash, row);
+			self.counters[offset] += sign * value;
+		}
+	}
+
+	/// Retrieve an estimate of the count of `key`: the median of its signed counters
+	pub fn estimate<Q>(&self, key: &Q) -> i64
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		let hash = key_hash::<Q, H>(key);
+		let mut estimates: Vec<i64> = (0..self.k_num)
+			.map(|row| {
+				let (offset, sign) = self.offset_and_sign(hash, row);
+				sign * self.counters[offset]
+			})
+			.collect();
+		let middle = self.k_num / 2;
+		*estimates.select_nth_unstable(middle).1
+	}
+
+	/// The sketch of the changes of the counts from `previous`, a Count Sketch with the same dimensions, to this one
+	pub fn difference(&self, previous: &Self) -> DifferenceSketch<K, H> {
+		assert_eq!(self.counters.len(), previous.counters.len());
+		let counters = self.counters
+			.iter()
+			.zip(&previous.counters)
+			.map(|(counter, previous)| (counter - previous) as f64)
+			.collect();
+		DifferenceSketch::new(counters, self.width, self.k_num, true)
+	}
+
+	/// Clears the `CountSketch`, as if it was new.
+	pub fn clear(&mut self) {
+		self.counters.fill(0);
+	}
+
+	/// returns an estimation of the memory used
+	pub fn estimate_memory_size(&self) -> usize {
+		self.counters.len() * std::mem::size_of::<i64>()
+	}
+
+	fn offset_and_sign(&self, hash: (u64, u64), row: usize) -> (usize, i64) {
+		offset_and_sign(hash, row, self.width, self.shift)
+	}
+}
+
+/// The position of the counter of a hashed key in `row` of a sketch with rows of `width` counters, and the sign of the
+/// key in that row
+pub(super) fn offset_and_sign(hash: (u64, u64), row: usize, width: usize, shift: u32) -> (usize, i64) {
+	let row_hash = row_hash(hash, row);
+	let offset = row * width + usize::try_from(row_hash >> shift).unwrap();
+	let sign = if (row_hash >> (shift - 1)) & 1 == 0 { 1 } else { -1 };
+	(offset, sign)
+}
+
+impl<K: ?Sized, H> fmt::Debug for CountSketch<K, H> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("CountSketch")
+			.field("width", &self.width)
+			.field("k_num", &self.k_num)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	type CountSketch<K> = super::CountSketch<K>;
+
+	#[test]
+	fn test_increment() {
+		let mut cs = CountSketch::<u32>::new(0.01, 1.0 / 100.0);
+		for key in 0..1000_u32 {
+			cs.push(&key, i64::from(key % 10));
+		}
+		cs.push(&5000, 100_000);
+		assert!((cs.estimate(&5000) - 100_000).abs() < 100);
+		// the errors of the other keys cancel out on average
+		let bias: i64 = (0..1000_u32).map(|key| cs.estimate(&key) - i64::from(key % 10)).sum();
+		assert!(bias.abs() < 1000, "bias {bias}");
+	}
+}
@@ -22,6 +22,7 @@
 //SOFTWARE.
 
 pub const FINGERPRINT_SIZE: usize = 1;
+/// The default number of fingerprints per bucket
 pub const BUCKET_SIZE: usize = 4;
 const EMPTY_FINGERPRINT_DATA: [u8; FINGERPRINT_SIZE] = [100; FINGERPRINT_SIZE];
 
@@ -55,56 +56,45 @@ impl Fingerprint {
     pub fn is_empty(&self) -> bool {
         self.data == EMPTY_FINGERPRINT_DATA
     }
-
-    /// Sets the fingerprint value to a previously exported one via an in-memory copy.
-    fn slice_copy(&mut self, fingerprint: &[u8]) {
-        self.data.copy_from_slice(fingerprint);
-    }
 }
 
 #[derive(PartialEq)]
 pub enum BucketPutStatus { NEWITEM, EXISTING, FAILED }
 
-/// Manages `BUCKET_SIZE` fingerprints at most.
+/// Manages `N` fingerprints at most (`N` is 4, 8 or 16).
+/// The one byte fingerprints are packed in a single array, so finding a fingerprint, or an empty slot,
+/// compares all the slots at once: with SSE2 on x86_64, and with SWAR (SIMD within a register) elsewhere.
 #[derive(Clone,Debug)]
-pub struct Bucket {
-    pub buffer: [Fingerprint; BUCKET_SIZE],
-    pub values: [u32; BUCKET_SIZE],
+pub struct Bucket<const N: usize> {
+    fingerprints: [u8; N],
+    pub values: [u32; N],
 }
 
-impl Bucket {
+impl<const N: usize> Bucket<N> {
     /// Creates a new bucket with a pre-allocated buffer.
     pub fn new() -> Self {
+        assert!(N == 4 || N == 8 || N == 16, "unsupported bucket size {}", N);
         Self {
-            buffer: [Fingerprint::empty(); BUCKET_SIZE],
-            values: [0_u32; BUCKET_SIZE],
+            fingerprints: [EMPTY_FINGERPRINT_DATA[0]; N],
+            values: [0_u32; N],
         }
     }
 
     /// Inserts the fingerprint with values val into the buffer if the buffer is not full.
     /// If the fingerprint is already there, add val to its value
-    /// This operation is O(1).
+    /// This operation is O(1), and looks for the fingerprint and for an empty slot in the same pass.
     pub fn insert(&mut self, fp: Fingerprint, val: u32) -> BucketPutStatus {
-        match self.get_fingerprint_index(fp) {
-            Some(index) => {
-                self.values[index] += val;
-                BucketPutStatus::EXISTING
-            }
-            None => {
-                match self.get_fingerprint_index(Fingerprint::empty()) {
-                    Some(index) => {
-                        self.buffer[index] = fp;
-                        self.values[index] = val;
-                        BucketPutStatus::NEWITEM
-                    }
-                    None => BucketPutStatus::FAILED,
-                }
-            }
-                //for entry in &mut self.buffer {
-                //    if entry.is_empty() {
-                //        *entry = fp;
-                //        return true;
-                //    }        
+        let (found, empty) = self.slot_masks(fp);
+        if found != 0 {
+            self.values[found.trailing_zeros() as usize] += val;
+            BucketPutStatus::EXISTING
+        } else if empty != 0 {
+            let index = empty.trailing_zeros() as usize;
+            self.fingerprints[index] = fp.data[0];
+            self.values[index] = val;
+            BucketPutStatus::NEWITEM
+        } else {
+            BucketPutStatus::FAILED
         }
     }
 
@@ -112,7 +102,7 @@ impl Bucket {
     pub fn delete(&mut self, fp: Fingerprint) -> bool {
         match self.get_fingerprint_index(fp) {
             Some(index) => {
-                self.buffer[index] = Fingerprint::empty();
+                self.fingerprints[index] = EMPTY_FINGERPRINT_DATA[0];
                 self.values[index] = 0_u32;
                 true
             }
@@ -122,16 +112,30 @@ impl Bucket {
 
     /// Returns the index of the given fingerprint, if its found. O(1)
     pub fn get_fingerprint_index(&self, fp: Fingerprint) -> Option<usize> {
-        self.buffer.iter().position(|e| *e == fp)
+        let (found, _) = self.slot_masks(fp);
+        if found != 0 {
+            Some(found.trailing_zeros() as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the fingerprint stored in slot `index` (possibly the empty fingerprint).
+    pub fn fingerprint(&self, index: usize) -> Fingerprint {
+        Fingerprint { data: [self.fingerprints[index]] }
+    }
+
+    /// Replaces the fingerprint and value in slot `index`, returning the previous ones.
+    pub fn swap(&mut self, index: usize, fp: Fingerprint, val: u32) -> (Fingerprint, u32) {
+        let other = (self.fingerprint(index), self.values[index]);
+        self.fingerprints[index] = fp.data[0];
+        self.values[index] = val;
+        other
     }
 
     /// Returns all current fingerprint data of the current buffer for storage.
     pub fn get_fingerprint_data(&self) -> Vec<u8> {
-        self.buffer
-            .iter()
-            .flat_map(|f| f.data.iter())
-            .cloned()
-            .collect()
+        self.fingerprints.to_vec()
     }
     // TODO - do we need something similar for the values?
 
@@ -140,18 +144,122 @@ impl Bucket {
     pub fn clear(&mut self) {
         *self = Self::new()
     }
+
+    /// Bit masks (bit i for slot i) of the slots holding `fp` and of the empty slots
+    #[inline(always)]
+    fn slot_masks(&self, fp: Fingerprint) -> (u32, u32) {
+        #[cfg(target_arch = "x86_64")]
+        return self.slot_masks_sse2(fp);
+        #[cfg(not(target_arch = "x86_64"))]
+        return self.slot_masks_swar(fp);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[inline(always)]
+    fn slot_masks_sse2(&self, fp: Fingerprint) -> (u32, u32) {
+        use std::arch::x86_64::*;
+        // SSE2 is part of the x86_64 baseline; the slots beyond N are zero and masked out
+        let valid = (1_u32 << N) - 1;
+        // The loads read exactly N bytes (N is 4, 8 or 16, see `new`)
+        unsafe {
+            let slots = match N {
+                16 => _mm_loadu_si128(self.fingerprints.as_ptr() as *const __m128i),
+                8 => _mm_loadl_epi64(self.fingerprints.as_ptr() as *const __m128i),
+                _ => _mm_cvtsi32_si128(i32::from_le_bytes(self.fingerprints[..4].try_into().unwrap())),
+            };
+            let found = _mm_movemask_epi8(_mm_cmpeq_epi8(slots, _mm_set1_epi8(fp.data[0] as i8))) as u32;
+            let empty = _mm_movemask_epi8(_mm_cmpeq_epi8(slots, _mm_set1_epi8(EMPTY_FINGERPRINT_DATA[0] as i8))) as u32;
+            (found & valid, empty & valid)
+        }
+    }
+
+    /// The portable version of `slot_masks`, comparing 8 slots at a time
+    #[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+    #[inline(always)]
+    fn slot_masks_swar(&self, fp: Fingerprint) -> (u32, u32) {
+        let mut found = 0;
+        let mut empty = 0;
+        for (i, chunk) in self.fingerprints.chunks(8).enumerate() {
+            let mut word = [EMPTY_FINGERPRINT_DATA[0]; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            let word = u64::from_le_bytes(word);
+            found |= swar_matches(word, fp.data[0]) << (8 * i);
+            empty |= swar_matches(word, EMPTY_FINGERPRINT_DATA[0]) << (8 * i);
+        }
+        let valid = (1_u32 << N) - 1;
+        (found & valid, empty & valid)
+    }
 }
 
-impl From<&[u8]> for Bucket {
+/// SWAR comparison of the 8 bytes of `word` with `byte`: returns a mask whose bit i is set iff byte i is equal
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+fn swar_matches(word: u64, byte: u8) -> u32 {
+    const LOW7: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+    let x = word ^ (u64::from(byte) * 0x0101_0101_0101_0101);
+    // the high bit of each byte is set iff the byte of x is zero (exactly, without borrows between bytes)
+    let zero = !(((x & LOW7) + LOW7) | x | LOW7);
+    // gather the high bits of the 8 bytes into the low 8 bits
+    (zero.wrapping_mul(0x0002_0408_1020_4081) >> 56) as u32
+}
+
+impl<const N: usize> From<&[u8]> for Bucket<N> {
     /// Constructs a buffer of fingerprints from a set of previously exported fingerprints.
     fn from(fingerprints: &[u8]) -> Self {
-        let mut buffer = [Fingerprint::empty(); BUCKET_SIZE];
+        let mut bucket = Self::new();
         for (idx, value) in fingerprints.chunks(FINGERPRINT_SIZE).enumerate() {
-            buffer[idx].slice_copy(value);
+            bucket.fingerprints[idx] = value[0];
         }
-        let values = [0_u32; BUCKET_SIZE];
-        Self { buffer, values }
+        bucket
     }
 }
 
-// TODO - do we need something similar for the values?
\ No newline at end of file
+// TODO - do we need something similar for the values?
+
+#[cfg(test)]
+mod tests {
+    use super::{swar_matches, Bucket, BucketPutStatus, Fingerprint};
+
+    #[test]
+    fn test_swar_matches() {
+        let word = u64::from_le_bytes([7, 100, 7, 0, 255, 7, 1, 100]);
+        assert_eq!(swar_matches(word, 7), 0b0010_0101);
+        assert_eq!(swar_matches(word, 100), 0b1000_0010);
+        assert_eq!(swar_matches(word, 0), 0b0000_1000);
+        assert_eq!(swar_matches(word, 8), 0);
+    }
+
+    #[test]
+    fn test_bucket_sizes() {
+        fn fill<const N: usize>() {
+            let mut bucket = Bucket::<N>::new();
+            for i in 0..N {
+                let fp = Fingerprint::from_data([i as u8]).unwrap();
+                assert!(bucket.insert(fp, 1) == BucketPutStatus::NEWITEM);
+                assert!(bucket.insert(fp, 2) == BucketPutStatus::EXISTING);
+                assert_eq!(bucket.get_fingerprint_index(fp), Some(i));
+                assert_eq!(bucket.values[i], 3);
+            }
+            let fp = Fingerprint::from_data([200]).unwrap();
+            assert!(bucket.insert(fp, 1) == BucketPutStatus::FAILED);
+            assert!(bucket.delete(Fingerprint::from_data([1]).unwrap()));
+            assert!(bucket.insert(fp, 1) == BucketPutStatus::NEWITEM);
+            assert_eq!(bucket.get_fingerprint_index(fp), Some(1));
+        }
+        fill::<4>();
+        fill::<8>();
+        fill::<16>();
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_sse2_matches_swar() {
+        let mut bucket = Bucket::<16>::new();
+        for i in 0..16 {
+            bucket.fingerprints[i] = [3, 100, 7, 3][i % 4];
+            for fp in [3, 7, 9] {
+                let fp = Fingerprint::from_data([fp]).unwrap();
+                assert_eq!(bucket.slot_masks_sse2(fp), bucket.slot_masks_swar(fp));
+            }
+        }
+    }
+}
@@ -44,8 +44,11 @@
 mod bucket;
 mod utils;
 
-use crate::more_streaming::cuckoo::bucket::{Bucket, Fingerprint, BUCKET_SIZE, FINGERPRINT_SIZE};
+use crate::more_streaming::cuckoo::bucket::Bucket;
+pub use crate::more_streaming::cuckoo::bucket::{BUCKET_SIZE, FINGERPRINT_SIZE};
+pub use crate::more_streaming::cuckoo::bucket::Fingerprint;
 use crate::more_streaming::cuckoo::utils::{get_alt_index, get_fai, FaI};
+use crate::more_streaming::prefetch;
 
 use std::cmp;
 use std::collections::hash_map::DefaultHasher;
@@ -55,7 +58,6 @@ use std::hash::{Hash, Hasher};
 use std::iter::repeat;
 use std::marker::PhantomData;
 use std::mem;
-use std::mem::size_of;
 
 use rand::Rng;
 #[cfg(feature = "serde_support")]
@@ -135,9 +137,11 @@ impl StdError for CuckooError {
 /// assert!(cf.is_empty());
 ///
 /// ```
+///
+/// Each bucket holds `B` fingerprints (4, 8 or 16), which are compared at once (see `Bucket`).
 #[derive(Debug)]
-pub struct CuckooCountingFilter<H> {
-    buckets: Box<[Bucket]>,
+pub struct CuckooCountingFilter<H, const B: usize = BUCKET_SIZE> {
+    buckets: Box<[Bucket<B>]>,
     capacity: usize,
     len: usize,
     _hasher: std::marker::PhantomData<H>,
@@ -156,13 +160,13 @@ impl CuckooCountingFilter<DefaultHasher> {
     }
 }
 
-impl<H> CuckooCountingFilter<H>
+impl<H, const B: usize> CuckooCountingFilter<H, B>
 where
     H: Hasher + Default,
 {
     /// Constructs a Cuckoo Counting Filter with a given max capacity
     pub fn with_capacity(cap: usize) -> Self {
-        let capacity = cmp::max(1, cap.next_power_of_two() / BUCKET_SIZE);
+        let capacity = cmp::max(1, cap.next_power_of_two() / B);
 
         Self {
             buckets: repeat(Bucket::new())
@@ -210,28 +214,47 @@ where
     /// actually added to the filter, but some random *other* element was
     /// removed. This might improve in the future.
     pub fn add<T: ?Sized + Hash>(&mut self, data: &T) -> Result<(), CuckooError> {
+        self.add_count(data, 1_u32)
+    }
+
+    /// Adds `count` occurrences of `data` to the filter, with the same semantics as `add`.
+    pub fn add_count<T: ?Sized + Hash>(&mut self, data: &T, count: u32) -> Result<(), CuckooError> {
         let fai = get_fai::<T, H>(data);
-        if (self.put(fai.fp, 1_u32, fai.i1) != BucketPutStatus::FAILED) || (self.put(fai.fp, 1_u32, fai.i2)!=BucketPutStatus::FAILED) {
+        self.add_fai(fai, count)
+    }
+
+    /// Adds a batch of items to the filter: the fingerprints and indexes of all the items are computed first
+    /// and both of their buckets prefetched, and only then are the items added in order, with the semantics of `add`.
+    /// Returns `NotEnoughSpace` if any of the insertions failed (the remaining items are still added).
+    pub fn add_batch<T: Hash>(&mut self, data: &[T]) -> Result<(), CuckooError> {
+        let len = self.buckets.len();
+        let fais: Vec<FaI> = data.iter().map(|item| get_fai::<T, H>(item)).collect();
+        for fai in &fais {
+            prefetch(&self.buckets[fai.i1 % len]);
+            prefetch(&self.buckets[fai.i2 % len]);
+        }
+        let mut result = Ok(());
+        for fai in fais {
+            if let Err(err) = self.add_fai(fai, 1) {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    fn add_fai(&mut self, fai: FaI, count: u32) -> Result<(), CuckooError> {
+        if (self.put(fai.fp, count, fai.i1) != BucketPutStatus::FAILED) || (self.put(fai.fp, count, fai.i2)!=BucketPutStatus::FAILED) {
             return Ok(());
         }
         let len = self.buckets.len();
         let mut rng = rand::thread_rng();
         let mut i = fai.random_index(&mut rng);
         let mut fp = fai.fp;
-        let mut val = 1_u32;
+        let mut val = count;
         for _ in 0..MAX_REBUCKET {
-            let other_fp;
-            let other_val;
-            {
-                let inner_index = rng.gen_range(0..BUCKET_SIZE);
-                let loc_fp = &mut self.buckets[i % len].buffer[inner_index];
-                let loc_val = &mut self.buckets[i % len].values[inner_index];
-                other_fp = *loc_fp;
-                *loc_fp = fp;
-                other_val = *loc_val;
-                *loc_val = val;
-                i = get_alt_index::<H>(other_fp, i);
-            }
+            let inner_index = rng.gen_range(0..B);
+            let (other_fp, other_val) = self.buckets[i % len].swap(inner_index, fp, val);
+            i = get_alt_index::<H>(other_fp, i);
             if self.put(other_fp, other_val, i) != BucketPutStatus::FAILED {
                 return Ok(());
             }
@@ -264,11 +287,35 @@ where
         self.len
     }
 
+    /// Estimated number of distinct items in the filter. Items with the same fingerprint in the same pair of
+    /// alternate buckets share a slot, so `len` undercounts them: with K = buckets/2 * 255 such (bucket pair,
+    /// fingerprint) classes, n items occupy K(1 - e^(-n/K)) of them on average, which is inverted as in linear counting.
+    pub fn cardinality(&self) -> f64 {
+        let classes = (self.buckets.len() as f64 / 2.0).max(1.0) * f64::from(u8::MAX);
+        let occupied = (self.len as f64).min(classes - 1.0);
+        -classes * (1.0 - occupied / classes).ln()
+    }
+
     /// Total capacity of the filter.
     pub fn capacity(&self) -> usize {
         self.capacity
     }
 
+    /// Number of fingerprint slots in the filter (buckets times bucket size).
+    pub fn slots(&self) -> usize {
+        self.buckets.len() * B
+    }
+
+    /// Iterates over the occupied slots of the filter as (bucket index, fingerprint, count).
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Fingerprint, u32)> + '_ {
+        self.buckets.iter().enumerate().flat_map(|(i, bucket)| {
+            (0..B)
+                .map(move |slot| (bucket.fingerprint(slot), bucket.values[slot]))
+                .filter(|(fp, _)| !fp.is_empty())
+                .map(move |(fp, val)| (i, fp, val))
+        })
+    }
+
     /// Exports fingerprints in all buckets, along with the filter's length for storage.
     /// The filter can be recovered by passing the `ExportedCuckooFilter` struct to the
     /// `from` method of `CuckooFilter`.
@@ -278,7 +325,7 @@ where
 
     /// Number of bytes the filter occupies in memory
     pub fn memory_usage(&self) -> usize {
-        mem::size_of_val(self) + self.buckets.len() * mem::size_of::<Bucket>()
+        mem::size_of_val(self) + self.buckets.len() * mem::size_of::<Bucket<B>>()
     }
 
     /// Check if filter is empty
@@ -345,7 +392,7 @@ pub struct ExportedCuckooCountingFilter {
     pub capacity: usize,
 }
 
-impl<H> From<ExportedCuckooCountingFilter> for CuckooCountingFilter<H> {
+impl<H, const B: usize> From<ExportedCuckooCountingFilter> for CuckooCountingFilter<H, B> {
     /// Converts a simplified representation of a filter used for export to a
     /// fully functioning version.
     ///
@@ -358,11 +405,11 @@ impl<H> From<ExportedCuckooCountingFilter> for CuckooCountingFilter<H> {
     /// This value is used as a time saving method, otherwise all fingerprints
     /// would need to be checked for equivalence against the null pattern.
     fn from(exported: ExportedCuckooCountingFilter) -> Self {
-        // Assumes that the `BUCKET_SIZE` and `FINGERPRINT_SIZE` constants do not change.
+        // Assumes that the bucket size and `FINGERPRINT_SIZE` do not change.
         Self {
             buckets: exported
                 .values
-                .chunks(BUCKET_SIZE * (FINGERPRINT_SIZE + size_of::<u32>()))
+                .chunks(B * FINGERPRINT_SIZE)
                 .map(Bucket::from)
                 .collect::<Vec<_>>()
                 .into_boxed_slice(),
@@ -373,17 +420,41 @@ impl<H> From<ExportedCuckooCountingFilter> for CuckooCountingFilter<H> {
     }
 }
 
-impl<H> From<&CuckooCountingFilter<H>> for ExportedCuckooCountingFilter
+impl<H, const B: usize> From<&CuckooCountingFilter<H, B>> for ExportedCuckooCountingFilter
 where
     H: Hasher + Default,
 {
     /// Converts a `CuckooFilter` into a simplified version which can be serialized and stored
     /// for later use.
-    fn from(cuckoo: &CuckooCountingFilter<H>) -> Self {
+    fn from(cuckoo: &CuckooCountingFilter<H, B>) -> Self {
         Self {
             values: cuckoo.values(),
             length: cuckoo.len(),
             capacity: cuckoo.capacity()
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CuckooCountingFilter;
+    use super::utils::get_fai;
+    use std::collections::hash_map::DefaultHasher;
+
+    #[test]
+    fn test_iter() {
+        let mut filter = CuckooCountingFilter::<DefaultHasher>::with_capacity(64);
+        for key in ["a", "a", "a", "b"] {
+            filter.add(key).unwrap();
+        }
+        let mut entries: Vec<_> = filter.iter().collect();
+        entries.sort_by_key(|(_, _, count)| *count);
+        assert_eq!(entries.len(), 2);
+        for ((bucket, fp, count), (key, expected)) in entries.into_iter().zip([("b", 1), ("a", 3)]) {
+            let fai = get_fai::<_, DefaultHasher>(key);
+            let buckets = filter.buckets.len();
+            assert_eq!((fp, count), (fai.fp, expected));
+            assert!(bucket == fai.i1 % buckets || bucket == fai.i2 % buckets);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Number of fractional bits of the scaled integer (fixed point) decayed counters
+pub const SCALE_BITS: u32 = 16;
+
+/// The forward decay weights are rescaled (moving the landmark to the current time) once they reach 2^RESCALE_EXPONENT,
+/// which leaves 64 - SCALE_BITS - RESCALE_EXPONENT bits for the sums of weights in scaled integer counters
+const RESCALE_EXPONENT: f64 = 16.0;
+
+/// How counts decay with time (measured in items, or in the units of the timestamps)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decay {
+    /// Exponential decay with the given half-life, computed by forward decay (Cormode, Shkapenyuk, Srivastava and Xu):
+    /// an arrival at time t adds 2^((t-L)/half_life) to the counters, for a landmark L, and the counters are divided by
+    /// 2^((T-L)/half_life) when queried at time T, so that each arrival counts 2^(-(T-t)/half_life)
+    Forward { half_life: f64 },
+    /// Periodic global aging: each arrival adds 1, and all the counters are halved every `period`
+    Halving { period: f64 },
+}
+
+/// The clock of a decayed structure: tracks the landmark of forward decay or the last halving, and tells the structure
+/// when to rescale its counters
+#[derive(Debug, Clone)]
+pub struct DecayClock {
+    decay: Decay,
+    landmark: f64,
+}
+
+impl DecayClock {
+    pub fn new(decay: Decay) -> Self {
+        match decay {
+            Decay::Forward { half_life } => assert!(half_life > 0.0),
+            Decay::Halving { period } => assert!(period > 0.0),
+        }
+        Self { decay, landmark: 0.0 }
+    }
+
+    /// Advance the clock to time `time`, returning the factor by which all the counters must be multiplied, if any:
+    /// the forward decay weights are rescaled once they get too large, and the counters are halved once per period
+    pub fn advance(&mut self, time: f64) -> Option<f64> {
+        match self.decay {
+            Decay::Forward { half_life } => {
+                let exponent = (time - self.landmark) / half_life;
+                if exponent < RESCALE_EXPONENT {
+                    return None;
+                }
+                self.landmark = time;
+                Some((-exponent).exp2())
+            }
+            Decay::Halving { period } => {
+                let periods = ((time - self.landmark) / period).floor();
+                if periods < 1.0 {
+                    return None;
+                }
+                self.landmark += periods * period;
+                Some((-periods).exp2())
+            }
+        }
+    }
+
+    /// The weight of an arrival at time `time` (once the clock was advanced to `time`)
+    pub fn weight(&self, time: f64) -> f64 {
+        match self.decay {
+            Decay::Forward { half_life } => ((time - self.landmark) / half_life).exp2(),
+            Decay::Halving { .. } => 1.0,
+        }
+    }
+
+    /// The factor turning the counters into decayed counts at time `time`
+    pub fn query_factor(&self, time: f64) -> f64 {
+        match self.decay {
+            Decay::Forward { half_life } => (-(time - self.landmark) / half_life).exp2(),
+            Decay::Halving { .. } => 1.0,
+        }
+    }
+}
+
+/// The exactly decayed count of every key, the ground truth of the decayed structures
+#[derive(Debug, Clone)]
+pub struct ExactDecay<K: Hash + Eq> {
+    counts: HashMap<K, f64>,
+    clock: DecayClock,
+    time: f64,
+}
+
+impl<K: Hash + Eq> ExactDecay<K> {
+    pub fn new(decay: Decay) -> Self {
+        Self { counts: HashMap::new(), clock: DecayClock::new(decay), time: 0.0 }
+    }
+
+    /// Count an arrival of `key` at time `time`
+    pub fn insert(&mut self, key: K, time: f64) {
+        if let Some(factor) = self.clock.advance(time) {
+            self.counts.values_mut().for_each(|count| *count *= factor);
+        }
+        self.time = time;
+        *self.counts.entry(key).or_insert(0.0) += self.clock.weight(time);
+    }
+
+    /// The decayed count of `key` at the time of the last arrival
+    pub fn get(&self, key: &K) -> f64 {
+        self.counts.get(key).map_or(0.0, |count| count * self.clock.query_factor(self.time))
+    }
+
+    /// Iterate over the keys and their decayed counts at the time of the last arrival
+    pub fn iter(&self) -> impl Iterator<Item = (&K, f64)> + '_ {
+        let factor = self.clock.query_factor(self.time);
+        self.counts.iter().map(move |(key, count)| (key, count * factor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decay, ExactDecay};
+
+    #[test]
+    fn test_exact_decay() {
+        let mut forward = ExactDecay::new(Decay::Forward { half_life: 10.0 });
+        let mut halving = ExactDecay::new(Decay::Halving { period: 10.0 });
+        for time in 0..1000 {
+            forward.insert(time % 2, f64::from(time));
+            halving.insert(time % 2, f64::from(time));
+        }
+        // 1 + 2^(-2/10) + 2^(-4/10) + ... for the key of the last arrival
+        let expected = 1.0 / (1.0 - (-0.2_f64).exp2());
+        assert!((forward.get(&1) - expected).abs() < 1e-6, "{}", forward.get(&1));
+        assert!((forward.get(&0) - expected * (-0.1_f64).exp2()).abs() < 1e-6);
+        // 5 arrivals of each key per period, halved at the end of each period: 5 + 5/2 + 5/4 + ...
+        assert!((halving.get(&1) - 10.0).abs() < 1e-6, "{}", halving.get(&1));
+    }
+}
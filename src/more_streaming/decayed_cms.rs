@@ -0,0 +1,180 @@
+use std::{
+	borrow::Borrow, fmt, hash::Hash, marker::PhantomData
+};
+use super::nitro_cms::{key_hash, row_hash, NitroCMS};
+use super::hashing::{SketchHasher, Xxh3Hasher};
+use super::decay::{Decay, DecayClock};
+use super::traits::DecayCounter;
+use rand;
+use rand_distr::{Geometric, Distribution};
+
+/// A NitroCMS of time-decayed counts: each arrival adds its decay weight (see `Decay`) instead of 1, to floating point
+/// or scaled integer counters `C`. It has the dimensions and the row hashes of a `NitroCMS` with the same parameters.
+/// As in `NitroCMS`, the counters are numbered consecutively across the arrivals and each is updated with probability
+/// `sample_prob`, by its weight divided by `sample_prob`; without sampling, the update can be conservative.
+/// Time is the number of arrivals, unless the arrivals are inserted with their timestamps (`push_at`).
+pub struct DecayedNitroCMS<K: ?Sized, C, H = Xxh3Hasher> {
+	counters: Vec<C>, // k_num rows of width counters, stored contiguously
+	width: usize,
+	shift: u32,
+	k_num: usize,
+	minimum_increment: bool,
+	geo: Geometric,
+	sample_prob: f64,
+	next_row: usize,
+	clock: DecayClock,
+	time: f64,
+	arrivals: u64,
+	marker: PhantomData<fn(K) -> H>,
+}
+
+impl<K: ?Sized, C, H> DecayedNitroCMS<K, C, H>
+where
+	K: Hash,
+	C: DecayCounter,
+	H: SketchHasher,
+{
+	/// Create an empty `DecayedNitroCMS` with the specified error tolerance and decay.
+	pub fn new(probability: f64, tolerance: f64, sample_prob: f64, minimum_increment: bool, decay: Decay) -> Self {
+		let width = NitroCMS::<K, u32, H>::optimal_width(tolerance);
+		let k_num = NitroCMS::<K, u32, H>::optimal_k_num(probability);
+		let geo = Geometric::new(sample_prob).unwrap();
+		Self {
+			counters: vec![C::default(); k_num * width],
+			width,
+			shift: NitroCMS::<K, u32, H>::shift(width),
+			k_num,
+			minimum_increment,
+			geo,
+			sample_prob,
+			next_row: geo.sample(&mut rand::thread_rng()) as usize,
+			clock: DecayClock::new(decay),
+			time: 0.0,
+			arrivals: 0,
+			marker: PhantomData,
+		}
+	}
+
+	/// Count an arrival of `key`, at the time given by the number of arrivals.
+	pub fn push<Q>(&mut self, key: &Q)
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		self.push_at(key, self.arrivals as f64);
+	}
+
+	/// Count an arrival of `key` at time `time` (not before the previous arrivals).
+	pub fn push_at<Q>(&mut self, key: &Q, time: f64)
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		self.arrivals += 1;
+		self.time = time;
+		if let Some(factor) = self.clock.advance(time) {
+			self.counters.iter_mut().for_each(|counter| counter.scale(factor));
+		}
+		if self.next_row >= self.k_num {
+			self.next_row -= self.k_num;
+			return;
+		}
+		let hash = key_hash::<Q, H>(key);
+		let weight = C::from_weight(self.clock.weight(time) / self.sample_prob);
+		if self.sample_prob == 1.0 && self.minimum_increment {
+			let target = self.min_counter(hash).plus(weight);
+			for row in 0..self.k_num {
+				let offset = self.offset(hash, row);
+				if self.counters[offset] < target {
+					self.counters[offset] = target;
+				}
+			}
+			return;
+		}
+		let mut row = self.next_row;
+		while row < self.k_num {
+			let offset = self.offset(hash, row);
+			self.counters[offset] = self.counters[offset].plus(weight);
+			row += 1 + self.geo.sample(&mut rand::thread_rng()) as usize;
+		}
+		self.next_row = row - self.k_num;
+	}
+
+	/// Retrieve an estimate of the decayed count of `key` at the time of the last arrival.
+	pub fn estimate<Q>(&self, key: &Q) -> f64
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		self.min_counter(key_hash::<Q, H>(key)).to_weight() * self.clock.query_factor(self.time)
+	}
+
+	/// The number of counters in each row
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	/// The sampling probability
+	pub fn sample_prob(&self) -> f64 {
+		self.sample_prob
+	}
+
+	/// Iterate over the non-zero counters as (row, column, decayed counter at the time of the last arrival).
+	pub fn iter(&self) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+		let factor = self.clock.query_factor(self.time);
+		self.counters
+			.iter()
+			.enumerate()
+			.filter(|(_, counter)| **counter != C::default())
+			.map(move |(offset, counter)| (offset / self.width, offset % self.width, counter.to_weight() * factor))
+	}
+
+	/// returns an estimation of the memory used
+	pub fn estimate_memory_size(&self) -> usize {
+		self.counters.len() * std::mem::size_of::<C>()
+	}
+
+	fn min_counter(&self, hash: (u64, u64)) -> C {
+		(0..self.k_num)
+			.map(|row| self.counters[self.offset(hash, row)])
+			.reduce(|min, counter| if counter < min { counter } else { min })
+			.unwrap()
+	}
+
+	fn offset(&self, hash: (u64, u64), row: usize) -> usize {
+		row * self.width + usize::try_from(row_hash(hash, row) >> self.shift).unwrap()
+	}
+}
+
+impl<K: ?Sized, C, H> fmt::Debug for DecayedNitroCMS<K, C, H> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("DecayedNitroCMS")
+			.field("width", &self.width)
+			.field("k_num", &self.k_num)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::decay::Decay;
+	type DecayedNitroCMS<K, C> = super::DecayedNitroCMS<K, C>;
+
+	#[test]
+	fn test_decay() {
+		let decay = Decay::Forward { half_life: 100.0 };
+		let mut float = DecayedNitroCMS::<u32, f64>::new(0.01, 0.01, 1.0, true, decay);
+		let mut scaled = DecayedNitroCMS::<u32, u64>::new(0.01, 0.01, 1.0, true, decay);
+		for time in 0..10_000_u32 {
+			// key 0 only arrives in the first half
+			let key = if time < 5000 { time % 2 } else { 1 };
+			float.push(&key);
+			scaled.push(&key);
+		}
+		// 1 + 2^(-1/100) + 2^(-2/100) + ...
+		let expected = 1.0 / (1.0 - (-0.01_f64).exp2());
+		assert!((float.estimate(&1) - expected).abs() < 1e-6, "{}", float.estimate(&1));
+		assert!((scaled.estimate(&1) - expected).abs() < 0.1, "{}", scaled.estimate(&1));
+		assert!(float.estimate(&0) < 1e-9);
+	}
+}
@@ -0,0 +1,86 @@
+use std::hash::{BuildHasher, Hash};
+use std::collections::hash_map::RandomState;
+use super::space_saving::SpaceSaving;
+use super::decay::{Decay, DecayClock};
+use super::traits::DecayCounter;
+
+/// Space saving of time-decayed counts: each arrival adds its decay weight (see `Decay`) instead of 1, as a weighted
+/// insertion into a `SpaceSaving` of scaled integer counters (which keeps them ordered), and the aging of the counters
+/// (the rescaling of forward decay, or the halving) scales all of them at once.
+/// Time is the number of arrivals, unless the arrivals are inserted with their timestamps (`insert_at`).
+
+#[derive(Debug)]
+pub struct DecayedSpaceSaving<K: Hash + Eq, S = RandomState> {
+    counts: SpaceSaving<K, u64, S>,
+    clock: DecayClock,
+    time: f64,
+    arrivals: u64,
+}
+
+impl <K, S>DecayedSpaceSaving<K, S>
+where
+K: Clone + Hash + Eq,
+S: BuildHasher + Default,
+{
+    pub fn new(error: f64, decay: Decay) -> Self {
+        Self {
+            counts: SpaceSaving::new(error, false),
+            clock: DecayClock::new(decay),
+            time: 0.0,
+            arrivals: 0,
+        }
+    }
+
+    /// insert an arrival of an item, at the time given by the number of arrivals
+    pub fn insert(&mut self, id: K) {
+        self.insert_at(id, self.arrivals as f64);
+    }
+
+    /// insert an arrival of an item at time `time` (not before the previous arrivals)
+    pub fn insert_at(&mut self, id: K, time: f64) {
+        self.arrivals += 1;
+        self.time = time;
+        if let Some(factor) = self.clock.advance(time) {
+            self.counts.scale_counts(|mut count| {
+                count.scale(factor);
+                count
+            });
+        }
+        self.counts.insert_weighted(id, u64::from_weight(self.clock.weight(time)));
+    }
+
+    /// return an estimate of an item's decayed count at the time of the last arrival
+    pub fn get(&self, id: K) -> f64 {
+        self.counts.get(id).to_weight() * self.clock.query_factor(self.time)
+    }
+
+    /// returns the number of entries in space saving
+    pub fn capacity(&self) -> usize {
+        self.counts.capacity()
+    }
+
+    /// iterate over the monitored items and their estimated decayed counts
+    pub fn iter(&self) -> impl Iterator<Item = (&K, f64)> + '_ {
+        let factor = self.clock.query_factor(self.time);
+        self.counts.iter().map(move |(id, count)| (id, count.to_weight() * factor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decay::Decay;
+    type DecayedSpaceSaving = super::DecayedSpaceSaving<u32>;
+
+    #[test]
+    fn test_decay() {
+        let mut halving = DecayedSpaceSaving::new(0.1, Decay::Halving { period: 100.0 });
+        for time in 0..1000_u32 {
+            // an elephant of the first half, then one of the second half
+            halving.insert(if time % 2 == 0 { time % 7 } else if time < 500 { 100 } else { 200 });
+        }
+        // 50 arrivals per period, halved at the end of each period: 50 + 50/2 + 50/4 + 50/8 + 50/16
+        assert_eq!(halving.get(200), 96.875);
+        // and halved 5 times since the last arrival
+        assert_eq!(halving.get(100), 96.875 / 32.0);
+    }
+}
@@ -1,55 +1,49 @@
-use std::hash::Hash;
-use std::collections::HashMap;
-use std::fmt::Debug;
-use crate::{FlowId,more_streaming::nitro_cms::NitroCMS};
-use crate::more_streaming::traits::{ItemIncrement,ItemQuery};
+use std::collections::HashSet;
+use std::hash::Hasher;
+use std::fmt::{self, Debug};
+use std::mem::size_of;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use crate::FlowId;
+use crate::more_streaming::cuckoo::{CuckooCountingFilter, CuckooError, FINGERPRINT_SIZE};
+use crate::more_streaming::traits::{FacsPermanent, FacsWindow};
 
 /// FAst Combined Sketch
 /// In this sketch design, we divide the stream into windows of size window_size
-/// During the current window, we add items to a hashmap, or compact hashmap like Counting Cuckoo Filter
-/// At the end of the window, we update the permanent sketch with respective values and clear the window hash/cuckoo
-
-const DEFAULT_WINDOW: u32 = 10_000;
-
-#[derive(Debug)]
-pub struct FACS {
-    window_sketch: HashMap<FlowId, u32>,
-    permanent_sketch: NitroCMS<FlowId, u32>,
-    next_item: u32,
-    window_size: u32,
+/// During the current window, we add items to a hashmap, or compact hashmap like Counting Cuckoo Filter (the window sketch `W`)
+/// At the end of the window, we update the permanent sketch `P` (e.g., NitroCMS) with respective values and clear the window sketch
+pub struct FACS<W, P> {
+    window_sketch: W,
+    permanent_sketch: P,
+    next_item: usize,
+    window_size: usize,
 }
 
-impl FACS
+impl<W: FacsWindow, P: FacsPermanent> FACS<W, P>
 {
-    pub fn new(sample_prob: f64) -> Self
+    /// Combines an empty window sketch and an empty permanent sketch, moving the counts of the window sketch to the
+    /// permanent one every `window_size` items
+    pub fn new(window_sketch: W, permanent_sketch: P, window_size: usize) -> Self
     {
-        let window_sketch:HashMap<FlowId, u32> = HashMap::new();
-        //let permanent_sketch: NitroCMS<FlowId,u32> = NitroCMS::new(config.confidence, config.error, 1.0 , !(config.avoid_mi), ());
-        let permanent_sketch: NitroCMS<FlowId, u32> = NitroCMS::new(0.01, 0.01, 1.0 , true, ());
-        let next_item = 0;
-        let window_size = DEFAULT_WINDOW;
+        assert!(window_size > 0);
         Self {
             window_sketch,
             permanent_sketch,
-            next_item,
+            next_item: 0,
             window_size,
         }
     }
 
-    // TODO: add a parameterized constructor
-
     /// "Visit" an element: add 1 to the item's count in window_sketch
     /// If we completed the window, add all values to the respective items' count in permanent_sketch and reset window_sketch
-    pub fn insert(&mut self, id: FlowId) 
+    pub fn insert(&mut self, id: FlowId)
 	{
         self.window_sketch.item_increment(id);
         self.next_item += 1;
-        if self.next_item % self.window_size == 0 { // TODO: spawn in a separate thread
+        if self.next_item == self.window_size { // see BackgroundFACS for a flush in a separate thread
             self.next_item = 0;
-            for (key, val) in self.window_sketch.iter() {
-                self.permanent_sketch.push(key, val);
-            }
-            self.window_sketch.clear();
+            let permanent_sketch = &mut self.permanent_sketch;
+            self.window_sketch.flush(&mut |key, val| permanent_sketch.add(key, val));
         }
     }
 
@@ -59,43 +53,301 @@ impl FACS
         return self.window_sketch.item_query(id) + self.permanent_sketch.item_query(id);
     }
 
-    /// return the hash table's capacity
-    pub fn capacity(&self) -> usize
+    /// return the number of items in a window
+    pub fn window_size(&self) -> usize
     {
-        // TODO - what should we do here?
-        return self.window_sketch.capacity();
+        self.window_size
     }
 
-    /// return the actual number of unique items in the hash table
-    pub fn len(&self) -> usize
+    /// iterate over the items of the current window and their estimated counts
+    /// (the permanent sketch does not store keys, so only items seen in the current window are listed)
+    pub fn iter(&self) -> impl Iterator<Item = (FlowId, u32)> + '_
     {
-        // TODO - what should we do here?
-        return self.window_sketch.len();
+        self.window_sketch.window_keys().into_iter().map(|id| (id, self.get(id)))
+    }
+
+    /// returns an estimation of the memory used by the window sketch
+    pub fn estimate_window_memory_size(&self) -> usize {
+        self.window_sketch.memory_size()
     }
 
     /// returns an estimation of the memory used by permanent sketch
 	pub fn estimate_permanent_memory_size(&self) -> usize {
-        return self.permanent_sketch.estimate_memory_size();
+        self.permanent_sketch.memory_size()
    }
 }
 
-#[cfg(test)]
-use crate::id_from_line;
+impl<W: Debug, P: Debug> Debug for FACS<W, P> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("FACS")
+			.field("window_sketch", &self.window_sketch)
+			.field("permanent_sketch", &self.permanent_sketch)
+			.field("next_item", &self.next_item)
+			.field("window_size", &self.window_size)
+			.finish()
+	}
+}
+
+/// The windows and permanent sketch shared by a `BackgroundFACS` and its flushing thread
+struct FlushState<W, P> {
+    in_flight: Option<Arc<W>>, // a full window, not yet added to the permanent sketch
+    spare: Option<W>, // an empty window, to replace the live one at the end of the window
+    permanent_sketch: P,
+    stop: bool,
+}
+
+/// A double-buffered FACS: at the end of a window, the full window sketch is handed to a flushing thread, which adds its
+/// counts to the permanent sketch, while an empty window sketch keeps counting the next window.
+/// The permanent sketch is also kept twice: the flushing thread adds the in-flight window to a staged copy without
+/// holding the lock of the shared state, swaps it with the queried copy under the lock, and then brings the other copy
+/// up to date. A query thus sees the in-flight window either before or after it was added to the permanent sketch,
+/// never halfway, and only waits for the swap. Insertions only wait when a window ends before the previous one was
+/// flushed.
+pub struct BackgroundFACS<W, P> {
+    window_sketch: W,
+    shared: Arc<(Mutex<FlushState<W, P>>, Condvar)>,
+    flusher: Option<JoinHandle<()>>,
+    next_item: usize,
+    window_size: usize,
+}
+
+impl<W, P> BackgroundFACS<W, P>
+where
+    W: FacsWindow + Send + Sync + 'static,
+    P: FacsPermanent + Send + 'static,
+{
+    /// Like `FACS::new`, with two empty window sketches to alternate between and two empty permanent sketches, and
+    /// starts the flushing thread
+    pub fn new(window_sketch: W, spare_window_sketch: W, permanent_sketch: P, staged_permanent_sketch: P, window_size: usize) -> Self
+    {
+        assert!(window_size > 0);
+        let shared = Arc::new((Mutex::new(FlushState {
+            in_flight: None,
+            spare: Some(spare_window_sketch),
+            permanent_sketch,
+            stop: false,
+        }), Condvar::new()));
+        let flusher_shared = Arc::clone(&shared);
+        let flusher = thread::spawn(move || Self::flush_windows(&flusher_shared, staged_permanent_sketch));
+        Self {
+            window_sketch,
+            shared,
+            flusher: Some(flusher),
+            next_item: 0,
+            window_size,
+        }
+    }
+
+    /// The flushing thread: add each in-flight window to the staged permanent sketch, swap it in, and keep the emptied
+    /// window as the spare one
+    fn flush_windows(shared: &(Mutex<FlushState<W, P>>, Condvar), mut staged_sketch: P) {
+        let (state, handed_over) = shared;
+        loop {
+            let window_sketch = {
+                let state = handed_over.wait_while(state.lock().unwrap(), |state| state.in_flight.is_none() && !state.stop).unwrap();
+                match &state.in_flight {
+                    Some(window_sketch) => Arc::clone(window_sketch),
+                    None => return,
+                }
+            };
+            let counts: Vec<(FlowId, u32)> = window_sketch.window_keys().into_iter()
+                .map(|id| (id, window_sketch.item_query(id)))
+                .collect();
+            counts.iter().for_each(|&(id, count)| staged_sketch.add(id, count));
+            {
+                let mut state = state.lock().unwrap();
+                std::mem::swap(&mut state.permanent_sketch, &mut staged_sketch);
+                state.in_flight = None;
+            }
+            // the queries only read the in-flight window under the lock, so this is now its only reference
+            let mut window_sketch = Arc::try_unwrap(window_sketch).ok().unwrap();
+            window_sketch.flush(&mut |_, _| ());
+            state.lock().unwrap().spare = Some(window_sketch);
+            handed_over.notify_all();
+            counts.into_iter().for_each(|(id, count)| staged_sketch.add(id, count));
+        }
+    }
+
+    /// "Visit" an element: add 1 to the item's count in the live window sketch
+    /// If we completed the window, hand it to the flushing thread and continue with the spare window sketch
+    pub fn insert(&mut self, id: FlowId)
+    {
+        self.window_sketch.item_increment(id);
+        self.next_item += 1;
+        if self.next_item == self.window_size {
+            self.next_item = 0;
+            let (state, handed_over) = &*self.shared;
+            let mut state = handed_over.wait_while(state.lock().unwrap(), |state| state.spare.is_none()).unwrap();
+            let full = std::mem::replace(&mut self.window_sketch, state.spare.take().unwrap());
+            state.in_flight = Some(Arc::new(full));
+            handed_over.notify_all();
+        }
+    }
+
+    /// return an item's estimated count by combining the live window, the in-flight window and the permanent count
+    pub fn get(&self, id: FlowId) -> u32
+    {
+        let state = self.state();
+        let in_flight = state.in_flight.as_ref().map_or(0, |window_sketch| window_sketch.item_query(id));
+        self.window_sketch.item_query(id) + in_flight + state.permanent_sketch.item_query(id)
+    }
+
+    /// return the number of items in a window
+    pub fn window_size(&self) -> usize
+    {
+        self.window_size
+    }
+
+    /// iterate over the items of the live and in-flight windows and their estimated counts
+    pub fn iter(&self) -> impl Iterator<Item = (FlowId, u32)> + '_
+    {
+        let mut keys = self.window_sketch.window_keys();
+        if let Some(window_sketch) = &self.state().in_flight {
+            keys.extend(window_sketch.window_keys());
+        }
+        let mut seen = HashSet::new();
+        keys.retain(|id| seen.insert(*id));
+        keys.into_iter().map(|id| (id, self.get(id)))
+    }
 
+    /// returns an estimation of the memory used by both window sketches
+    pub fn estimate_window_memory_size(&self) -> usize {
+        let state = self.state();
+        let other = state.in_flight.as_deref().or(state.spare.as_ref()).map_or(0, |window_sketch| window_sketch.memory_size());
+        self.window_sketch.memory_size() + other
+    }
+
+    /// returns an estimation of the memory used by both copies of the permanent sketch
+    pub fn estimate_permanent_memory_size(&self) -> usize {
+        2 * self.state().permanent_sketch.memory_size()
+    }
+
+    fn state(&self) -> MutexGuard<'_, FlushState<W, P>> {
+        self.shared.0.lock().unwrap()
+    }
+}
+
+/// Stops the flushing thread, once the in-flight window (if any) was flushed
+impl<W, P> Drop for BackgroundFACS<W, P> {
+    fn drop(&mut self) {
+        let (state, handed_over) = &*self.shared;
+        state.lock().unwrap().stop = true;
+        handed_over.notify_all();
+        if let Some(flusher) = self.flusher.take() {
+            let _ = flusher.join();
+        }
+    }
+}
+
+impl<W: Debug, P: Debug> Debug for BackgroundFACS<W, P> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("BackgroundFACS")
+			.field("window_sketch", &self.window_sketch)
+			.field("permanent_sketch", &self.shared.0.lock().unwrap().permanent_sketch)
+			.field("next_item", &self.next_item)
+			.field("window_size", &self.window_size)
+			.finish()
+	}
+}
+
+/// A counting cuckoo filter used as the window sketch of FACS.
+/// The filter does not store keys, so the keys of the window are also listed (when first counted) to move their counts
+/// to the permanent sketch. Keys whose fingerprints collide share a counter, so only the first one is listed.
+#[derive(Debug)]
+pub struct CuckooWindow<H> {
+    filter: CuckooCountingFilter<H>,
+    keys: Vec<FlowId>,
+}
+
+impl<H: Hasher + Default> CuckooWindow<H> {
+    /// A filter with room for the items of a window
+    pub fn with_capacity(window_size: usize) -> Self {
+        Self {
+            filter: CuckooCountingFilter::with_capacity(window_size),
+            keys: Vec::with_capacity(window_size),
+        }
+    }
+
+    /// Count an occurrence of `id`
+    pub fn add(&mut self, id: FlowId) -> Result<(), CuckooError> {
+        self.filter.add(&id)?;
+        if self.filter.get(&id) == 1 {
+            self.keys.push(id);
+        }
+        Ok(())
+    }
+
+    /// The count of `id` in the window
+    pub fn get(&self, id: FlowId) -> u32 {
+        self.filter.get(&id)
+    }
+
+    /// Pass the count of each key to `flush`, and empty the window
+    pub fn flush(&mut self, flush: &mut dyn FnMut(FlowId, u32)) {
+        for id in self.keys.drain(..) {
+            flush(id, self.filter.get(&id));
+        }
+        self.filter.clear();
+    }
+
+    /// The listed keys of the window
+    pub fn keys(&self) -> &[FlowId] {
+        &self.keys
+    }
+
+    /// The memory of the filter slots and of the key list
+    pub fn memory_size(&self) -> usize {
+        self.filter.slots() * (FINGERPRINT_SIZE + size_of::<u32>()) + self.keys.capacity() * size_of::<FlowId>()
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use crate::{id_from_line, FlowId};
+    use crate::more_streaming::count_sketch::CountSketch;
+    use crate::more_streaming::nitro_cms::NitroCMS;
+    use std::collections::HashMap;
+    use super::{BackgroundFACS, CuckooWindow, FACS};
 
     const TEST_PROBABILITY: f64 = 0.01;
     const TEST_N_ITEMS: usize = 1_000_000;
     const TEST_ERROR_TOLERANCE: usize = 1;
+    const TEST_WINDOW: usize = 10_000;
 
     #[test]
     fn test_increment() {
         let id: FlowId = id_from_line("1 2 3 4 5 6 7 8").unwrap();
-		let mut facs:super::FACS = super::FACS::new(TEST_PROBABILITY);
+        let window: HashMap<FlowId, u32> = HashMap::new();
+        let permanent: NitroCMS<FlowId, u32> = NitroCMS::new(TEST_PROBABILITY, 0.01, 1.0, true, ());
+		let mut facs = FACS::new(window, permanent, TEST_WINDOW);
 		for _ in 0..TEST_N_ITEMS {
 			let _ = facs.insert(id);
 		}
 		assert!(TEST_N_ITEMS.abs_diff(usize::try_from(facs.get(id)).unwrap()) < TEST_ERROR_TOLERANCE, "DIFF facs = {}", facs.get(id));
 	}
-}
\ No newline at end of file
+
+    #[test]
+    fn test_cuckoo_window() {
+        let ids: Vec<FlowId> = (0..100).map(|i| id_from_line(&format!("1 2 3 {} 5 6 7 8", i)).unwrap()).collect();
+        let window: CuckooWindow<std::collections::hash_map::DefaultHasher> = CuckooWindow::with_capacity(TEST_WINDOW);
+        let permanent: CountSketch<FlowId> = CountSketch::new(TEST_PROBABILITY, 0.0001);
+		let mut facs = FACS::new(window, permanent, TEST_WINDOW);
+		for _ in 0..1000 {
+			ids.iter().for_each(|id| facs.insert(*id));
+		}
+		// 10 windows moved to the permanent sketch, wide enough for the 100 flows to (almost) never collide
+		assert!(ids.iter().all(|id| facs.get(*id).abs_diff(1000) < 50));
+	}
+
+    #[test]
+    fn test_background_flush() {
+        let ids: Vec<FlowId> = (0..100).map(|i| id_from_line(&format!("1 2 3 {} 5 6 7 8", i)).unwrap()).collect();
+        let new_permanent = || NitroCMS::<FlowId, u32>::new(TEST_PROBABILITY, 0.0001, 1.0, true, ());
+        let mut facs = BackgroundFACS::new(HashMap::new(), HashMap::new(), new_permanent(), new_permanent(), 1000);
+        for round in 1..=50 {
+            ids.iter().for_each(|id| facs.insert(*id));
+            // whether or not the last window is still in flight, every item is counted exactly once
+            assert!(ids.iter().all(|id| facs.get(*id) == round), "round {}", round);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+
+/// A concurrent hash table of counters keyed by fingerprints: the top `fp_size` bits of the hash of the key
+/// (computed with `H`) rather than the key itself. Keys with the same fingerprint share a counter, so the
+/// estimates can only be too high, unless the table is full: at most `max_size` fingerprints are stored,
+/// and the items of new fingerprints beyond that are rejected (counted, and estimated as 0).
+/// All operations take `&self`, so the table can be updated from several threads at once.
+pub struct FingerprintDash<H = DefaultHasher> {
+    counters: DashMap<u64, u32, BuildHasherDefault<H>>,
+    fp_size: u8,
+    max_size: usize,
+    len: AtomicUsize, // the number of stored fingerprints, including ones being inserted
+    rejected: AtomicU64,
+    _hasher: PhantomData<fn() -> H>,
+}
+
+impl<H> FingerprintDash<H>
+where
+    H: Hasher + Default,
+{
+    /// Creates an empty table of at most `max_size` fingerprints of `fp_size` bits (1 to 64)
+    pub fn new(fp_size: u8, max_size: usize) -> Self {
+        assert!((1..=64).contains(&fp_size));
+        // no more than 2^fp_size distinct fingerprints exist
+        let capacity = if fp_size < 32 { max_size.min(1 << fp_size) } else { max_size };
+        Self {
+            counters: DashMap::with_capacity_and_hasher(capacity, BuildHasherDefault::default()),
+            fp_size,
+            max_size,
+            len: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+            _hasher: PhantomData,
+        }
+    }
+
+    /// The fingerprint of `key`: the top `fp_size` bits of its hash
+    pub fn fingerprint<T: ?Sized + Hash>(&self, key: &T) -> u64 {
+        let mut hasher = H::default();
+        key.hash(&mut hasher);
+        hasher.finish() >> (64 - u32::from(self.fp_size))
+    }
+
+    /// Counts an occurrence of `key`
+    pub fn insert<T: ?Sized + Hash>(&self, key: &T) {
+        let fp = self.fingerprint(key);
+        if let Some(mut count) = self.counters.get_mut(&fp) {
+            *count += 1;
+            return;
+        }
+        match self.counters.entry(fp) {
+            Entry::Occupied(mut count) => *count.get_mut() += 1,
+            Entry::Vacant(entry) => {
+                if self.len.fetch_add(1, Ordering::Relaxed) < self.max_size {
+                    entry.insert(1);
+                } else {
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    self.rejected.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// The count of the fingerprint of `key`
+    pub fn get<T: ?Sized + Hash>(&self, key: &T) -> u32 {
+        self.counters.get(&self.fingerprint(key)).map_or(0, |count| *count)
+    }
+
+    /// The number of stored fingerprints
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Whether no fingerprint is stored
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    /// The number of fingerprints the table has room for
+    pub fn capacity(&self) -> usize {
+        self.counters.capacity()
+    }
+
+    /// The fingerprint size, in bits
+    pub fn fp_size(&self) -> u8 {
+        self.fp_size
+    }
+
+    /// The number of items rejected because the table was full
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Iterate over the stored (fingerprint, count) pairs
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u32)> + '_ {
+        self.counters.iter().map(|entry| (*entry.key(), *entry.value()))
+    }
+}
+
+impl<H> fmt::Debug for FingerprintDash<H> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("FingerprintDash")
+            .field("fp_size", &self.fp_size)
+            .field("max_size", &self.max_size)
+            .field("rejected", &self.rejected)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FingerprintDash;
+
+    #[test]
+    fn test_increment() {
+        let table = FingerprintDash::<std::collections::hash_map::DefaultHasher>::new(32, 1000);
+        for key in 0..100_u32 {
+            for _ in 0..=key {
+                table.insert(&key);
+            }
+        }
+        assert_eq!(table.len(), 100);
+        // 32 bit fingerprints of 100 keys are very unlikely to collide
+        assert!((0..100_u32).all(|key| table.get(&key) == key + 1));
+    }
+
+    #[test]
+    fn test_max_size() {
+        let table = FingerprintDash::<std::collections::hash_map::DefaultHasher>::new(64, 10);
+        for key in 0..100_u32 {
+            table.insert(&key);
+            table.insert(&key);
+        }
+        assert_eq!(table.len(), 10);
+        assert_eq!(table.rejected(), 180);
+        assert_eq!((0..100_u32).map(|key| table.get(&key)).sum::<u32>(), 20);
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::Hasher;
+use std::sync::OnceLock;
+use twox_hash::XxHash64;
+use twox_hash::xxh3::{Hash128, HasherExt};
+
+/// Number of tables of the tabulation hash: byte i of the input is looked up in table i mod TABULATION_TABLES
+const TABULATION_TABLES: usize = 32;
+
+/// Seed of the (deterministic) random tables of the tabulation hash
+const TABULATION_SEED: u64 = 0x5eed_7ab1_e5ee_d000;
+
+/// A hash function usable by all the structures, selected with --hash.
+/// Hashers are created with `Default`, so a hasher type is enough to select the hash function,
+/// as the cuckoo filter already does with its `H` type parameter.
+/// `finish_pair` returns the two 64-bit hashes used for double hashing; the default derives the
+/// second one from the first, and hashers with a wider output (xxh3) return two independent halves.
+pub trait SketchHasher: Hasher + Default + fmt::Debug {
+	fn finish_pair(&self) -> (u64, u64) {
+		let hash = self.finish();
+		(hash, mix64(hash))
+	}
+}
+
+/// SipHash 1-3 with fixed keys, as used (with random keys) by the std `HashMap`
+impl SketchHasher for DefaultHasher {}
+
+impl SketchHasher for XxHash64 {}
+
+/// 128-bit xxh3, whose two halves are used for double hashing
+#[derive(Clone, Default)]
+pub struct Xxh3Hasher(Hash128);
+
+impl Hasher for Xxh3Hasher {
+	fn write(&mut self, bytes: &[u8]) {
+		self.0.write(bytes);
+	}
+
+	fn finish(&self) -> u64 {
+		self.0.finish()
+	}
+}
+
+impl SketchHasher for Xxh3Hasher {
+	fn finish_pair(&self) -> (u64, u64) {
+		let hash = self.0.finish_ext();
+		(hash as u64, (hash >> 64) as u64)
+	}
+}
+
+impl fmt::Debug for Xxh3Hasher {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("Xxh3Hasher").finish()
+	}
+}
+
+/// The multiply-rotate hash of Firefox and rustc (FxHash): very fast, but with weak mixing of the low bits
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FxHasher {
+	hash: u64,
+}
+
+impl FxHasher {
+	const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+	fn add_to_hash(&mut self, word: u64) {
+		self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(Self::SEED);
+	}
+}
+
+impl Hasher for FxHasher {
+	fn write(&mut self, bytes: &[u8]) {
+		let mut chunks = bytes.chunks_exact(8);
+		for chunk in &mut chunks {
+			self.add_to_hash(u64::from_le_bytes(chunk.try_into().unwrap()));
+		}
+		for &byte in chunks.remainder() {
+			self.add_to_hash(u64::from(byte));
+		}
+	}
+
+	fn write_u8(&mut self, i: u8) {
+		self.add_to_hash(u64::from(i));
+	}
+
+	fn write_u16(&mut self, i: u16) {
+		self.add_to_hash(u64::from(i));
+	}
+
+	fn write_u32(&mut self, i: u32) {
+		self.add_to_hash(u64::from(i));
+	}
+
+	fn write_u64(&mut self, i: u64) {
+		self.add_to_hash(i);
+	}
+
+	fn write_usize(&mut self, i: usize) {
+		self.add_to_hash(i as u64);
+	}
+
+	fn finish(&self) -> u64 {
+		self.hash
+	}
+}
+
+impl SketchHasher for FxHasher {}
+
+/// Simple tabulation hashing (Zobrist; Patrascu and Thorup): the hash is the XOR of one random table entry per input byte.
+/// The tables are filled once, from a fixed seed, so all hashers (and runs) agree.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TabulationHasher {
+	hash: u64,
+	position: usize,
+}
+
+impl TabulationHasher {
+	fn tables() -> &'static [[u64; 256]; TABULATION_TABLES] {
+		static TABLES: OnceLock<Box<[[u64; 256]; TABULATION_TABLES]>> = OnceLock::new();
+		TABLES.get_or_init(|| {
+			let mut state = TABULATION_SEED;
+			let mut tables = Box::new([[0; 256]; TABULATION_TABLES]);
+			for entry in tables.iter_mut().flat_map(|table| table.iter_mut()) {
+				state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+				*entry = mix64(state);
+			}
+			tables
+		})
+	}
+}
+
+impl Hasher for TabulationHasher {
+	fn write(&mut self, bytes: &[u8]) {
+		let tables = Self::tables();
+		for &byte in bytes {
+			self.hash ^= tables[self.position % TABULATION_TABLES][usize::from(byte)];
+			self.position += 1;
+		}
+	}
+
+	fn finish(&self) -> u64 {
+		self.hash
+	}
+}
+
+impl SketchHasher for TabulationHasher {}
+
+/// An intentionally weak hash (the polynomial string hash of Java with multiplier 31), for adversarial testing:
+/// colliding keys are trivial to construct, and short keys leave the high bits mostly zero
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WeakHasher {
+	hash: u64,
+}
+
+impl Hasher for WeakHasher {
+	fn write(&mut self, bytes: &[u8]) {
+		for &byte in bytes {
+			self.hash = self.hash.wrapping_mul(31).wrapping_add(u64::from(byte));
+		}
+	}
+
+	fn finish(&self) -> u64 {
+		self.hash
+	}
+}
+
+impl SketchHasher for WeakHasher {}
+
+/// The finalizer of SplitMix64 (a bijection on u64 with full avalanche)
+pub fn mix64(mut x: u64) -> u64 {
+	x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+	x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+	x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashSet;
+	use std::hash::Hash;
+	use super::{SketchHasher, TabulationHasher, WeakHasher, Xxh3Hasher};
+
+	fn distinct_buckets<H: SketchHasher>() -> usize {
+		(0..1000_u32)
+			.map(|key| {
+				let mut hasher = H::default();
+				key.hash(&mut hasher);
+				hasher.finish_pair().0 >> 54
+			})
+			.collect::<HashSet<_>>()
+			.len()
+	}
+
+	#[test]
+	fn test_bucket_spread() {
+		// 1000 keys into 1024 buckets should occupy about 1024(1-e^(-1000/1024)) = 640 buckets
+		assert!(distinct_buckets::<TabulationHasher>() > 550);
+		assert!(distinct_buckets::<Xxh3Hasher>() > 550);
+		assert!(distinct_buckets::<WeakHasher>() < 10);
+	}
+}
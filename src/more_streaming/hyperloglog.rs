@@ -0,0 +1,201 @@
+use std::{
+    borrow::Borrow, collections::HashMap, fmt, hash::Hash, marker::PhantomData, mem
+};
+use super::nitro_cms::key_hash;
+use super::hashing::{SketchHasher, Xxh3Hasher};
+
+/// The precision of the sparse representation: the index of a hash is its top 25 bits
+const SPARSE_PRECISION: u32 = 25;
+const MIN_PRECISION: u32 = 4;
+const MAX_PRECISION: u32 = 18;
+
+/// A HyperLogLog (Flajolet, Fusy, Gandouet and Meunier) estimating the number of distinct keys, with the sparse
+/// representation of HyperLogLog++ (Heule, Nunkesser and Hall): while few registers are set, only the set registers are
+/// stored, at a higher precision of 25 bits, and they are folded into the 2^p dense registers once they would take more
+/// memory. Both representations are estimated by the improved estimator of Ertl ("New cardinality estimation algorithms
+/// for HyperLogLog sketches"), which corrects the bias of the raw estimate over the whole range of cardinalities
+/// without the empirical bias tables of HyperLogLog++.
+pub struct HyperLogLog<K: ?Sized, H = Xxh3Hasher> {
+    registers: Vec<u8>, // empty in sparse mode
+    sparse: HashMap<u32, u8>, // the set registers at SPARSE_PRECISION
+    precision: u32,
+    marker: PhantomData<fn(K) -> H>,
+}
+
+impl<K: ?Sized, H> HyperLogLog<K, H>
+where
+    K: Hash,
+    H: SketchHasher,
+{
+    /// Create an empty `HyperLogLog` with a relative standard error of about `error` (1.04/sqrt(2^p) for 2^p registers,
+    /// with p between 4 and 18).
+    pub fn new(error: f64) -> Self {
+        assert!(error > 0.0 && error < 1.0);
+        let precision = (1.04 / error).powi(2).log2().ceil() as u32;
+        Self {
+            registers: Vec::new(),
+            sparse: HashMap::new(),
+            precision: precision.clamp(MIN_PRECISION, MAX_PRECISION),
+            marker: PhantomData,
+        }
+    }
+
+    /// Count `key`
+    pub fn insert<Q>(&mut self, key: &Q)
+    where
+        Q: ?Sized + Hash,
+        K: Borrow<Q>,
+    {
+        let hash = key_hash::<Q, H>(key).0;
+        if self.is_sparse() {
+            let (index, rank) = index_and_rank(hash, SPARSE_PRECISION);
+            let register = self.sparse.entry(index as u32).or_insert(0);
+            *register = (*register).max(rank);
+            if self.sparse.len() > self.registers() / 4 {
+                self.densify();
+            }
+        } else {
+            let (index, rank) = index_and_rank(hash, self.precision);
+            self.registers[index] = self.registers[index].max(rank);
+        }
+    }
+
+    /// Estimate the number of distinct keys
+    pub fn cardinality(&self) -> f64 {
+        if self.is_sparse() {
+            let mut histogram = vec![0; (64 - SPARSE_PRECISION + 2) as usize];
+            histogram[0] = (1 << SPARSE_PRECISION) - self.sparse.len();
+            self.sparse.values().for_each(|rank| histogram[*rank as usize] += 1);
+            return estimate(&histogram, SPARSE_PRECISION);
+        }
+        let mut histogram = vec![0; (64 - self.precision + 2) as usize];
+        self.registers.iter().for_each(|rank| histogram[*rank as usize] += 1);
+        estimate(&histogram, self.precision)
+    }
+
+    /// The number of index bits p of the 2^p dense registers
+    pub fn precision(&self) -> u32 {
+        self.precision
+    }
+
+    /// Whether the registers are still in the sparse representation
+    pub fn is_sparse(&self) -> bool {
+        self.registers.is_empty()
+    }
+
+    /// returns an estimation of the memory used
+    pub fn estimate_memory_size(&self) -> usize {
+        if self.is_sparse() {
+            return self.sparse.len() * (mem::size_of::<u32>() + mem::size_of::<u8>());
+        }
+        self.registers.len() * mem::size_of::<u8>()
+    }
+
+    fn registers(&self) -> usize {
+        1 << self.precision
+    }
+
+    /// Fold the sparse registers into the dense ones: the index bits beyond p become the leading bits of the rank
+    fn densify(&mut self) {
+        let extra_bits = SPARSE_PRECISION - self.precision;
+        let mut registers = vec![0; self.registers()];
+        for (index, rank) in self.sparse.drain() {
+            let low = index & ((1 << extra_bits) - 1);
+            let rank = if low == 0 {
+                extra_bits as u8 + rank
+            } else {
+                (low.leading_zeros() - (32 - extra_bits)) as u8 + 1
+            };
+            let register = &mut registers[(index >> extra_bits) as usize];
+            *register = (*register).max(rank);
+        }
+        self.registers = registers;
+        self.sparse = HashMap::new();
+    }
+}
+
+impl<K: ?Sized, H> fmt::Debug for HyperLogLog<K, H> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("HyperLogLog")
+            .field("precision", &self.precision)
+            .field("sparse", &self.registers.is_empty())
+            .finish()
+    }
+}
+
+/// The register of `hash` (its top `precision` bits) and the rank of the remaining bits (the position of their
+/// leading one, at most 65 - precision)
+fn index_and_rank(hash: u64, precision: u32) -> (usize, u8) {
+    let index = (hash >> (64 - precision)) as usize;
+    let rank = (hash << precision).leading_zeros().min(64 - precision) + 1;
+    (index, rank as u8)
+}
+
+/// Ertl's improved estimator, from the histogram of the register values of 2^precision registers
+fn estimate(histogram: &[usize], precision: u32) -> f64 {
+    let registers = f64::from(1_u32 << precision);
+    let q = histogram.len() - 2;
+    let mut z = registers * tau(1.0 - histogram[q + 1] as f64 / registers);
+    for count in histogram[1..=q].iter().rev() {
+        z = 0.5 * (z + *count as f64);
+    }
+    z += registers * sigma(histogram[0] as f64 / registers);
+    registers * registers / (2.0 * std::f64::consts::LN_2 * z)
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    type HyperLogLog = super::HyperLogLog<u64>;
+
+    #[test]
+    fn test_cardinality() {
+        let mut hll = HyperLogLog::new(0.01);
+        assert_eq!(hll.precision(), 14);
+        assert_eq!(hll.cardinality(), 0.0);
+        for key in 0..1000_u64 {
+            hll.insert(&key);
+            hll.insert(&key);
+        }
+        assert!(hll.is_sparse());
+        assert!((hll.cardinality() - 1000.0).abs() < 5.0, "{}", hll.cardinality());
+        for key in 1000..200_000_u64 {
+            hll.insert(&key);
+        }
+        assert!(!hll.is_sparse());
+        assert!((hll.cardinality() / 200_000.0 - 1.0).abs() < 0.04, "{}", hll.cardinality());
+    }
+}
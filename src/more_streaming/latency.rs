@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+/// Number of bits of each value kept exactly: values are recorded with a relative error below 2^-SUB_BUCKET_BITS
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+
+/// A histogram of latencies (in nanoseconds) in the style of HdrHistogram: values below 2^SUB_BUCKET_BITS have their
+/// own bucket, and each larger power of two range is split into 2^SUB_BUCKET_BITS equal buckets, so that quantiles are
+/// reported with a bounded relative error (below 1%) over the whole u64 range, in a fixed (60KB) array.
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    len: u64,
+    max: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS],
+            len: 0,
+            max: 0,
+        }
+    }
+
+    /// Record a latency of `nanos` nanoseconds
+    #[inline]
+    pub fn record(&mut self, nanos: u64) {
+        self.counts[Self::bucket(nanos)] += 1;
+        self.len += 1;
+        self.max = self.max.max(nanos);
+    }
+
+    /// Record the latency `elapsed` (saturating at u64::MAX nanoseconds)
+    #[inline]
+    pub fn record_duration(&mut self, elapsed: Duration) {
+        self.record(u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX));
+    }
+
+    /// The bucket of `nanos`: the bits below the top SUB_BUCKET_BITS + 1 are dropped
+    fn bucket(nanos: u64) -> usize {
+        if nanos < SUB_BUCKETS as u64 {
+            return nanos as usize;
+        }
+        let shift = 63 - nanos.leading_zeros() - SUB_BUCKET_BITS;
+        (shift as usize + 1) * SUB_BUCKETS + ((nanos >> shift) as usize - SUB_BUCKETS)
+    }
+
+    /// The highest value of `bucket`
+    fn bucket_high(bucket: usize) -> u64 {
+        if bucket < SUB_BUCKETS {
+            return bucket as u64;
+        }
+        let shift = bucket / SUB_BUCKETS - 1;
+        let low = ((bucket % SUB_BUCKETS + SUB_BUCKETS) as u64) << shift;
+        low + ((1_u64 << shift) - 1)
+    }
+
+    /// The number of recorded latencies
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether no latency was recorded
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The largest recorded latency (exact)
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// The latency at quantile `q` (in [0,1]): the highest value of the bucket holding the ceil(q*len)-th smallest
+    /// latency, but never above the maximum
+    pub fn quantile(&self, q: f64) -> u64 {
+        assert!((0.0..=1.0).contains(&q));
+        let rank = ((q * self.len as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::bucket_high(bucket).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LatencyHistogram;
+
+    #[test]
+    fn test_quantiles() {
+        let mut histogram = LatencyHistogram::new();
+        for nanos in 1..=100_000_u64 {
+            histogram.record(nanos);
+        }
+        histogram.record(1 << 40);
+        assert_eq!(histogram.len(), 100_001);
+        assert_eq!(histogram.max(), 1 << 40);
+        assert_eq!(histogram.quantile(0.0), 1);
+        assert_eq!(histogram.quantile(0.0005), 51); // the 51st smallest, below 128 values are exact
+        for (q, expected) in [(0.5, 50_000.0), (0.9, 90_000.0), (0.999, 99_900.0)] {
+            let relative_error = (histogram.quantile(q) as f64 - expected).abs() / expected;
+            assert!(relative_error < 0.01, "quantile {q}: {}", histogram.quantile(q));
+        }
+        assert_eq!(histogram.quantile(1.0), 1 << 40);
+    }
+}
@@ -24,79 +24,169 @@
 // SOFTWARE.
 
 use std::{
-	borrow::Borrow, cmp::max, convert::TryFrom, fmt, hash::{Hash, Hasher}, marker::PhantomData, ops
+	borrow::Borrow, cmp::max, convert::TryFrom, fmt, hash::Hash, marker::PhantomData, ops
 };
-use twox_hash::XxHash;
-use super::f64_to_usize;
-use super::traits::{Intersect, IntersectPlusUnionIsPlus, New, UnionAssign};
+use super::{f64_to_usize, median, prefetch, sampling_confidence_interval};
+use super::traits::{Intersect, IntersectPlusUnionIsPlus, New, UnionAssign, VtoUsize};
+use super::adaptive::AdaptiveRate;
+use super::hashing::{SketchHasher, Xxh3Hasher};
+use super::change_detection::DifferenceSketch;
 use rand;
+use rand::Rng;
 use rand_distr::{Geometric, Distribution};
 use core::fmt::Debug;
 
 /// An implementation of the NitroSketch optimization as reported in https://dl.acm.org/doi/10.1145/3341302.3342076
 /// of a [count-min sketch](https://en.wikipedia.org/wiki/Count–min_sketch) data structure.
-pub struct NitroCMS<K: ?Sized, C: New> {
-	counters: Vec<Vec<C>>,
+/// Keys are hashed with `H` (128-bit xxh3 by default).
+pub struct NitroCMS<K: ?Sized, C: New, H = Xxh3Hasher> {
+	counters: Vec<C>, // k_num rows of width counters, stored contiguously
 	offsets: Vec<usize>, // to avoid malloc/free each push
-	mask: usize,
+	batch_offsets: Vec<usize>, // to avoid malloc/free each batch
+	width: usize,
+	shift: u32, // row hashes are reduced to their top log2(width) bits
 	k_num: usize,
     default: C,
     geo: Geometric,
 	sample_prob: f64,
-    factor: usize,
 	minimum_increment: bool,
-    curr_counter: usize,
-    next_counter: usize,
-    last_index: usize,
+	element_sampling: bool,
+    next_row: usize,
+    skip_elements: usize, // elements to skip until the next sampled one, when sampling whole elements
+    adaptive: Option<AdaptiveRate>,
+    convergence_volume: Option<u64>,
+    volume: u64,
+    switch_point: Option<u64>,
 	config: <C as New>::Config,
-	marker: PhantomData<fn(K)>,
+	marker: PhantomData<fn(K) -> H>,
 }
 
-impl<K: ?Sized, C> NitroCMS<K, C>
+impl<K: ?Sized, C, H> NitroCMS<K, C, H>
 where
 	K: Hash,
+	H: SketchHasher,
 	C: New + for<'a> UnionAssign<&'a C> + Intersect + Clone + std::convert::TryFrom<usize> + std::ops::Mul<Output = C>,
 {
 	/// Create an empty `NitroCMS` data structure with the specified error tolerance.
 	pub fn new(probability: f64, tolerance: f64, sample_prob: f64, minimum_increment: bool, config: C::Config) -> Self {
 		let width = Self::optimal_width(tolerance);
 		let k_num = Self::optimal_k_num(probability);
-		let counters: Vec<Vec<C>> = (0..k_num)
-			.map(|_| (0..width).map(|_| C::new(&config)).collect())
-			.collect();
-        let default = counters[0][0].clone();
+		let counters: Vec<C> = (0..k_num * width).map(|_| C::new(&config)).collect();
+        let default = counters[0].clone();
 		let offsets = vec![0; k_num];
         let geo = Geometric::new(sample_prob).unwrap();
-        let factor = f64_to_usize((1.0/sample_prob).round());
-        let curr_counter = 0;
-        let last_index = 0;
-        let next_counter = Self::calc_skip(geo,curr_counter);
+        let next_row = Self::calc_skip(geo,0);
+        let skip_elements = Self::calc_skip(geo,0);
 		Self {
 			counters,
 			offsets,
-			mask: Self::mask(width),
+			batch_offsets: Vec::new(),
+			width,
+			shift: Self::shift(width),
 			k_num,
             default,
             geo,
 			sample_prob,
-            factor,
 			minimum_increment,
-            curr_counter,
-            next_counter,
-            last_index,
+			element_sampling: false,
+            next_row,
+            skip_elements,
+            adaptive: None,
+            convergence_volume: None,
+            volume: 0,
+            switch_point: None,
 			config,
 			marker: PhantomData,
 		}
 	}
 
+	/// Create an empty `NitroCMS` data structure whose sampling probability adapts to the stream according to `rate`.
+	/// Sampled updates add 1/p times the value, so the counters hold estimates regardless of rate changes.
+	pub fn new_adaptive(probability: f64, tolerance: f64, minimum_increment: bool, config: C::Config, rate: AdaptiveRate) -> Self {
+		let mut cms = Self::new(probability, tolerance, rate.sample_prob(), minimum_increment, config);
+		cms.adaptive = Some(rate);
+		cms
+	}
+
+	/// Create an empty `NitroCMS` data structure in the "AlwaysCorrect" mode of NitroSketch: every row is updated exactly
+	/// (as a plain or conservative update CMS) until the stream volume reaches the convergence threshold,
+	/// and only then the sketch switches to sampling with `sample_prob`.
+	/// Sampled updates then add 1/p times the value (randomly rounded when 1/p is not an integer) on top of the exact counts.
+	pub fn new_always_correct(probability: f64, tolerance: f64, sample_prob: f64, minimum_increment: bool, config: C::Config) -> Self {
+		let mut cms = Self::new(probability, tolerance, sample_prob, minimum_increment, config);
+		cms.convergence_volume = Some(Self::convergence_threshold(probability, tolerance, sample_prob));
+		cms
+	}
+
+	/// Sample whole elements instead of rows in the sampled conservative update: a sampled element (with probability p)
+	/// conservatively updates all its rows, as in `full_push`, instead of only its sampled rows. The rows of an element
+	/// then move together, so that the sampling noise of one row does not hold back the updates of the others.
+	pub fn with_element_sampling(mut self) -> Self {
+		assert!(self.minimum_increment, "element sampling is a conservative update mode");
+		self.element_sampling = true;
+		self
+	}
+
+	/// Whether the sketch samples whole elements (see `with_element_sampling`)
+	pub fn is_element_sampling(&self) -> bool {
+		self.element_sampling
+	}
+
+	/// Whether the sketch conservatively updates sampled rows (see `sampled_conservative_push`)
+	pub fn is_row_sampled_conservative(&self) -> bool {
+		self.minimum_increment && !self.element_sampling && self.sample_prob < 1.0
+	}
+
+	/// The stream volume after which sampling no longer violates the error guarantee: sampling a flow of size n
+	/// adds an error with variance n(1-p)/p <= N/p, which stays below tolerance*N with probability 1-probability
+	/// once z^2/(tolerance^2 p) <= N, using the bound z^2 <= 2ln(2/probability) on the normal quantile.
+	pub fn convergence_threshold(probability: f64, tolerance: f64, sample_prob: f64) -> u64 {
+		(2.0 * (2.0 / probability).ln() / (tolerance * tolerance * sample_prob)).ceil() as u64
+	}
+
+	/// Whether the sketch was created in the "AlwaysCorrect" mode
+	pub fn is_always_correct(&self) -> bool {
+		self.convergence_volume.is_some()
+	}
+
+	/// The position in the stream at which an "AlwaysCorrect" sketch switched to sampling, if it did
+	pub fn switch_point(&self) -> Option<u64> {
+		self.switch_point
+	}
+
+	/// The number of counters in each row
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	/// The number of rows
+	pub fn k_num(&self) -> usize {
+		self.k_num
+	}
+
+	/// The current sampling probability
+	pub fn sample_prob(&self) -> f64 {
+		self.sample_prob
+	}
+
+	/// The adaptive sampling rate, if the sketch was created with one
+	pub fn adaptive_rate(&self) -> Option<&AdaptiveRate> {
+		self.adaptive.as_ref()
+	}
+
     /// "Visit" an element.
 	pub fn push<Q: ?Sized, V: ?Sized>(&mut self, key: &Q, value: &V) -> C
 	where
 		Q: Hash,
 		K: Borrow<Q>,
-		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
+		C: for<'a> ops::AddAssign<&'a V> + for<'a> ops::AddAssign<&'a C> + IntersectPlusUnionIsPlus,
+        <C as TryFrom<usize>>::Error:Debug
 	{
-        if self.sample_prob < 1.0 {
+        if self.convergence_volume.is_some() {
+            self.always_correct_push(key, value)
+        } else if self.adaptive.is_some() {
+            self.adaptive_push(key, value)
+        } else if self.sample_prob < 1.0 {
             self.sampled_push(key, value)
 		} else if self.minimum_increment {
 			self.full_push(key, value)
@@ -106,29 +196,170 @@ where
 	}
 
     /// "Visit" an element - sampled version - only update sampled cpunters
+    /// With minimum increment, this is the sampled conservative update (see `sampled_conservative_push`,
+    /// or `element_sampled_push` when sampling whole elements)
 	fn sampled_push<Q: ?Sized, V: ?Sized>(&mut self, key: &Q, value: &V) -> C
 	where
 		Q: Hash,
 		K: Borrow<Q>,
 		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
 	{
-        if self.next_counter - self.curr_counter > self.k_num {
-            self.curr_counter += self.k_num;
-        } else if self.next_counter - self.curr_counter + self.last_index >= self.k_num {
-            self.curr_counter += self.k_num - self.last_index;
-			self.last_index = 0;
-        } else { 
-            loop {
-                self.curr_counter = self.next_counter;
-                self.last_index = self.curr_counter % self.k_num;
-                let offset = usize::try_from(self.single_offset(key,self.last_index)).unwrap();
-                self.counters[self.last_index][offset] += value;
-                self.next_counter = Self::calc_skip(self.geo,self.curr_counter);
-                if self.next_counter - self.curr_counter + self.last_index >= self.k_num {
-                    break;
-                }
+        if self.element_sampling {
+            return self.element_sampled_push(key, value);
+        }
+        if self.minimum_increment {
+            return self.sampled_conservative_push(key, value);
+        }
+        // counters are numbered consecutively across packets (row r of the i-th packet is i*k_num+r),
+        // and next_row is the position of the next sampled counter relative to the current packet
+        // the key is only hashed if one of its rows is sampled
+        let mut hash = None;
+        while self.next_row < self.k_num {
+            let hash = *hash.get_or_insert_with(|| key_hash::<Q, H>(key));
+            let offset = self.single_offset(hash, self.next_row);
+            self.counters[offset] += value;
+            self.next_row = Self::calc_skip(self.geo,self.next_row + 1);
+        }
+        self.next_row -= self.k_num;
+        self.default.clone()
+    }
+
+    /// "Visit" an element - sampled conservative update - the rows are sampled as in `sampled_push`, and only the sampled
+    /// rows whose counters are at or below the current estimate of the element (the minimum over all its rows) are raised,
+    /// to the estimate plus the value. The key is hashed when at least one of its rows is sampled, with probability
+    /// 1-(1-p)^k_num (about p*k_num for a small p), and all its rows are then read to find its estimate.
+    /// A row that the sampling moved ahead of the others is held back until they all catch up, so the estimates of the
+    /// large flows are biased down (by about half with 4 rows), and the error bound of the sketch no longer holds.
+	fn sampled_conservative_push<Q, V: ?Sized>(&mut self, key: &Q, value: &V) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
+	{
+        if self.next_row >= self.k_num {
+            self.next_row -= self.k_num;
+            return self.default.clone();
+        }
+        let offsets = self.offsets(key);
+        self.offsets
+            .iter_mut()
+            .zip(offsets)
+            .for_each(|(offset, offset_new)| {
+                *offset = offset_new;
+            });
+        let raised = (!<C as IntersectPlusUnionIsPlus>::VAL).then(|| {
+            let mut lowest = C::intersect(self.offsets.iter().map(|&offset| &self.counters[offset])).unwrap();
+            lowest += value;
+            lowest
+        });
+        while self.next_row < self.k_num {
+            let counter = &mut self.counters[self.offsets[self.next_row]];
+            match &raised {
+                Some(raised) => counter.union_assign(raised),
+                None => *counter += value,
+            }
+            self.next_row = Self::calc_skip(self.geo,self.next_row + 1);
+        }
+        self.next_row -= self.k_num;
+        self.default.clone()
+    }
+
+    /// "Visit" an element - element sampled conservative update (see `with_element_sampling`) - a sampled element
+    /// conservatively updates all its rows. The key is hashed with probability p.
+	fn element_sampled_push<Q, V: ?Sized>(&mut self, key: &Q, value: &V) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
+	{
+        if self.skip_elements > 0 {
+            self.skip_elements -= 1;
+            return self.default.clone();
+        }
+        self.skip_elements = Self::calc_skip(self.geo,0);
+        self.full_push(key, value)
+    }
+
+    /// "Visit" an element - adaptive version - update sampled counters by 1/p times the value,
+    /// and lower the sampling probability as dictated by the adaptive rate
+	fn adaptive_push<Q, V: ?Sized>(&mut self, key: &Q, value: &V) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: for<'a> ops::AddAssign<&'a V> + for<'a> ops::AddAssign<&'a C> + IntersectPlusUnionIsPlus,
+        <C as TryFrom<usize>>::Error:Debug
+	{
+        let rate = self.adaptive.as_mut().unwrap();
+        if let Some(sample_prob) = rate.observe() {
+            self.sample_prob = sample_prob;
+            self.geo = Geometric::new(sample_prob).unwrap();
+            self.next_row = Self::calc_skip(self.geo,0);
+            self.skip_elements = Self::calc_skip(self.geo,0);
+        }
+        let weight = self.adaptive.as_ref().unwrap().weight();
+        self.weighted_sampled_push(key, value, weight)
+    }
+
+    /// "Visit" an element - AlwaysCorrect version - update all counters until converging, then update sampled counters
+    /// by 1/p times the value
+	fn always_correct_push<Q, V: ?Sized>(&mut self, key: &Q, value: &V) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: for<'a> ops::AddAssign<&'a V> + for<'a> ops::AddAssign<&'a C> + IntersectPlusUnionIsPlus,
+        <C as TryFrom<usize>>::Error:Debug
+	{
+        if self.switch_point.is_none() {
+            self.volume += 1;
+            if self.volume < self.convergence_volume.unwrap() {
+                return if self.minimum_increment {
+                    self.full_push(key, value)
+                } else {
+                    self.all_push(key, value)
+                };
             }
+            self.switch_point = Some(self.volume);
         }
+        // randomized rounding keeps the increment unbiased when 1/p is not an integer
+        let scale = 1.0 / self.sample_prob;
+        let weight = scale.floor() as usize + usize::from(rand::thread_rng().gen_bool(scale.fract()));
+        self.weighted_sampled_push(key, value, weight)
+    }
+
+    /// Update the sampled counters of an element by weight times its value
+    /// (conservatively with minimum increment, as in `sampled_conservative_push` or `element_sampled_push`)
+	fn weighted_sampled_push<Q, V: ?Sized>(&mut self, key: &Q, value: &V, weight: usize) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: for<'a> ops::AddAssign<&'a V> + for<'a> ops::AddAssign<&'a C> + IntersectPlusUnionIsPlus,
+        <C as TryFrom<usize>>::Error:Debug
+	{
+        let sampled = if self.element_sampling { self.skip_elements == 0 } else { self.next_row < self.k_num };
+        if !sampled {
+            if self.element_sampling {
+                self.skip_elements -= 1;
+            } else {
+                self.next_row -= self.k_num;
+            }
+            return self.default.clone();
+        }
+        let mut increment = C::new(&self.config);
+        increment += value;
+        let increment = increment * C::try_from(weight).unwrap();
+        if self.element_sampling {
+            return self.element_sampled_push(key, &increment);
+        }
+        if self.minimum_increment {
+            return self.sampled_conservative_push(key, &increment);
+        }
+        let hash = key_hash::<Q, H>(key);
+        while self.next_row < self.k_num {
+            let offset = self.single_offset(hash, self.next_row);
+            self.counters[offset] += &increment;
+            self.next_row = Self::calc_skip(self.geo,self.next_row + 1);
+        }
+        self.next_row -= self.k_num;
         self.default.clone()
     }
 
@@ -140,12 +371,9 @@ where
 		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
 	{
 		let offsets = self.offsets(key);
-		self.counters
-			.iter_mut()
-			.zip(offsets)
-			.for_each(|(counters, offset)| {
-				counters[offset] += value;
-			});
+		for offset in offsets {
+			self.counters[offset] += value;
+		}
 		self.default.clone()
 	}
 
@@ -157,42 +385,94 @@ where
 		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
 	{
 		let offsets = self.offsets(key);
+		self.offsets
+			.iter_mut()
+			.zip(offsets)
+			.for_each(|(offset, offset_new)| {
+				*offset = offset_new;
+			});
+		Self::conservative_update(&mut self.counters, &self.offsets, value)
+	}
+
+	/// Conservatively update the counters at `offsets` (one per row) by `value`
+	fn conservative_update<V: ?Sized>(counters: &mut [C], offsets: &[usize], value: &V) -> C
+	where
+		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
+	{
 		if !<C as IntersectPlusUnionIsPlus>::VAL {
-			self.offsets
-				.iter_mut()
-				.zip(offsets)
-				.for_each(|(offset, offset_new)| {
-					*offset = offset_new;
-				});
 			let mut lowest = C::intersect(
-				self.offsets
+				offsets
 					.iter()
-					.enumerate()
-					.map(|(k_i, &offset)| &self.counters[k_i][offset]),
+					.map(|&offset| &counters[offset]),
 			)
 			.unwrap();
 			lowest += value;
-			self.counters
-				.iter_mut()
-				.zip(self.offsets.iter())
-				.for_each(|(counters, &offset)| {
-					counters[offset].union_assign(&lowest);
-				});
+			for &offset in offsets {
+				counters[offset].union_assign(&lowest);
+			}
 			lowest
 		} else {
+			for &offset in offsets {
+				counters[offset] += value;
+			}
 			C::intersect(
-				self.counters
-					.iter_mut()
-					.zip(offsets)
-					.map(|(counters, offset)| {
-						counters[offset] += value;
-						&counters[offset]
-					}),
+				offsets
+					.iter()
+					.map(|&offset| &counters[offset]),
 			)
 			.unwrap()
 		}
 	}
 
+	/// "Visit" a batch of elements, each with `value`: first find the counters the batch updates (hashing each
+	/// element at most once, and only the sampled ones when sampling) and prefetch them, then apply the updates.
+	/// The result is the same as pushing the elements one by one. The adaptive, AlwaysCorrect and sampled
+	/// conservative update modes push the elements one by one.
+	pub fn push_batch<Q, V: ?Sized>(&mut self, keys: &[Q], value: &V)
+	where
+		Q: Hash,
+		K: Borrow<Q>,
+		C: for<'a> ops::AddAssign<&'a V> + for<'a> ops::AddAssign<&'a C> + IntersectPlusUnionIsPlus,
+        <C as TryFrom<usize>>::Error:Debug
+	{
+		if self.scaled_counters() || (self.minimum_increment && self.sample_prob < 1.0) {
+			for key in keys {
+				self.push(key, value);
+			}
+			return;
+		}
+		let mut offsets = std::mem::take(&mut self.batch_offsets);
+		offsets.clear();
+		if self.sample_prob < 1.0 {
+			for key in keys {
+				let mut hash = None;
+				while self.next_row < self.k_num {
+					let hash = *hash.get_or_insert_with(|| key_hash::<Q, H>(key));
+					offsets.push(self.single_offset(hash, self.next_row));
+					self.next_row = Self::calc_skip(self.geo,self.next_row + 1);
+				}
+				self.next_row -= self.k_num;
+			}
+		} else {
+			for key in keys {
+				offsets.extend(self.offsets(key));
+			}
+		}
+		for &offset in &offsets {
+			prefetch(&self.counters[offset]);
+		}
+		if self.minimum_increment && self.sample_prob == 1.0 {
+			for key_offsets in offsets.chunks(self.k_num) {
+				Self::conservative_update(&mut self.counters, key_offsets, value);
+			}
+		} else {
+			for &offset in &offsets {
+				self.counters[offset] += value;
+			}
+		}
+		self.batch_offsets = offsets;
+	}
+
 	/// Union the aggregated value for `key` with `value`.
 	pub fn union_assign<Q: ?Sized>(&mut self, key: &Q, value: &C)
 	where
@@ -200,33 +480,134 @@ where
 		K: Borrow<Q>,
 	{
 		let offsets = self.offsets(key);
-		self.counters
-			.iter_mut()
-			.zip(offsets)
-			.for_each(|(counters, offset)| {
-				counters[offset].union_assign(value);
-			})
+		for offset in offsets {
+			self.counters[offset].union_assign(value);
+		}
 	}
 
 	/// Retrieve an estimate of the aggregated value for `key`.
-	pub fn get<Q: ?Sized>(&self, key: &Q) -> C // TODO: multiply by sample probability
+	/// When sampling, this is `estimate_f64` rounded to the nearest integer.
+	pub fn get<Q>(&self, key: &Q) -> C
 	where
-		Q: Hash,
+		Q: ?Sized + Hash,
 		K: Borrow<Q>,
+		C: VtoUsize,
         <C as TryFrom<usize>>::Error:Debug
 	{
-		C::intersect(
-			self.counters
-				.iter()
-				.zip(self.offsets(key))
-				.map(|(counters, offset)| &counters[offset]),
-		)
-		.unwrap() * C::try_from(self.factor).unwrap()
+		if !self.scaled_counters() && self.sample_prob < 1.0 {
+			C::try_from(f64_to_usize(self.estimate_f64(key).round())).unwrap()
+		} else {
+			self.min_counter(key)
+		}
+	}
+
+	/// Retrieve an unbiased estimate of the aggregated value for `key`, scaling the sampled counters by exactly 1/p.
+	/// With an adaptive rate, or in AlwaysCorrect mode, the counters are already scaled on update.
+	pub fn estimate_f64<Q>(&self, key: &Q) -> f64
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: VtoUsize,
+	{
+		if self.scaled_counters() {
+			self.min_counter(key).v_to_usize() as f64
+		} else {
+			self.min_counter(key).v_to_usize() as f64 / self.sample_prob
+		}
+	}
+
+	/// Retrieve a confidence interval, holding with probability 1-delta, for the aggregated value of `key`.
+	/// The interval only accounts for the sampling variance; hash collisions can only push the estimate up.
+	pub fn confidence_interval<Q>(&self, key: &Q, delta: f64) -> (f64, f64)
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: VtoUsize,
+	{
+		let sample_prob = if self.convergence_volume.is_some() && self.switch_point.is_none() { 1.0 } else { self.sample_prob };
+		sampling_confidence_interval(self.estimate_f64(key), sample_prob, delta)
+	}
+
+	// whether sampled updates are already scaled by 1/p in the counters
+	fn scaled_counters(&self) -> bool {
+		self.adaptive.is_some() || self.convergence_volume.is_some()
+	}
+
+	fn min_counter<Q>(&self, key: &Q) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		C::intersect(self.offsets(key).map(|offset| &self.counters[offset])).unwrap()
+	}
+
+	/// Iterate over the non-zero counters as (row, column, counter).
+	/// The sketch does not store keys, so this is the raw content of the counter matrix.
+	pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &C)> + '_
+	where
+		C: PartialEq,
+	{
+		let width = self.width;
+		self.counters
+			.iter()
+			.enumerate()
+			.filter(move |(_, counter)| **counter != self.default)
+			.map(move |(offset, counter)| (offset / width, offset % width, counter))
+	}
+
+	/// Estimate the number of distinct (sampled) keys by linear counting (Whang, Vander-Zanden and Taylor) on each row:
+	/// -w ln(z/w) for z zero counters out of w, taking the median over the rows. Conservative update does not change which
+	/// counters are zero, as a zero counter is always among the minimal ones of its key.
+	pub fn cardinality(&self) -> f64
+	where
+		C: PartialEq,
+	{
+		let width = self.width as f64;
+		let mut estimates: Vec<f64> = self.counters
+			.chunks(self.width)
+			.map(|row| {
+				let zeros = row.iter().filter(|counter| **counter == self.default).count();
+				-width * (zeros.max(1) as f64 / width).ln()
+			})
+			.collect();
+		median(&mut estimates)
 	}
 
 	/// returns an estimation of the memory used
 	pub fn estimate_memory_size(&self) -> usize {
-	 	return self.counters[0].len() * std::mem::size_of::<C>() * self.k_num;
+	 	self.counters.len() * std::mem::size_of::<C>()
+	}
+
+	/// Add the counters of `other`, a sketch with the same dimensions, hash and (fixed) sampling probability,
+	/// as when merging the sketches of several threads. Summing conservatively updated sketches keeps every counter
+	/// at or above the counts of its keys.
+	pub fn merge(&mut self, other: &Self)
+	where
+		C: for<'a> ops::AddAssign<&'a C>,
+	{
+		assert_eq!(self.counters.len(), other.counters.len());
+		assert!(!self.scaled_counters() && !other.scaled_counters() && self.sample_prob == other.sample_prob);
+		self.counters
+			.iter_mut()
+			.zip(&other.counters)
+			.for_each(|(counter, other)| *counter += other);
+	}
+
+	/// The sketch of the changes of the counts from `previous`, a sketch with the same dimensions, hash and (fixed)
+	/// sampling probability, to this one. The counters are linear in the counts only without conservative update.
+	pub fn difference(&self, previous: &Self) -> DifferenceSketch<K, H>
+	where
+		C: VtoUsize,
+	{
+		assert_eq!(self.counters.len(), previous.counters.len());
+		assert!(!self.minimum_increment && !previous.minimum_increment);
+		assert!(!self.scaled_counters() && !previous.scaled_counters() && self.sample_prob == previous.sample_prob);
+		let counters = self.counters
+			.iter()
+			.zip(&previous.counters)
+			.map(|(counter, previous)| (counter.v_to_usize() as f64 - previous.v_to_usize() as f64) / self.sample_prob)
+			.collect();
+		DifferenceSketch::new(counters, self.width, self.k_num, false)
 	}
 
 	/// Clears the `NitroCMS` data structure, as if it was new.
@@ -234,13 +615,12 @@ where
 		let config = &self.config;
 		self.counters
 			.iter_mut()
-			.flat_map(|x| x.iter_mut())
 			.for_each(|counter| {
 				*counter = C::new(config);
 			})
 	}
 
-	fn optimal_width(tolerance: f64) -> usize {
+	pub(super) fn optimal_width(tolerance: f64) -> usize {
 		let e = tolerance;
 		let width = f64_to_usize((2.0 / e).round());
 		max(2, width)
@@ -248,13 +628,13 @@ where
 			.expect("Width would be way too large")
 	}
 
-	fn mask(width: usize) -> usize {
+	pub(super) fn shift(width: usize) -> u32 {
 		assert!(width > 1);
 		assert_eq!(width & (width - 1), 0);
-		width - 1
+		u64::BITS - width.trailing_zeros()
 	}
 
-	fn optimal_k_num(probability: f64) -> usize {
+	pub(super) fn optimal_k_num(probability: f64) -> usize {
 		max(
 			1,
 			//f64_to_usize(((1.0 - probability).ln() / 0.5_f64.ln()).floor()), //
@@ -262,13 +642,14 @@ where
 		)
 	}
 
+	/// The positions of the counters of `key` in all rows
 	fn offsets<Q: ?Sized>(&self, key: &Q) -> impl Iterator<Item = usize>
 	where
 		Q: Hash,
 		K: Borrow<Q>,
 	{
-		let mask = self.mask;
-		hashes(key).map(move |hash| usize::try_from(hash & u64::try_from(mask).unwrap()).unwrap())
+		let (hash, width, shift) = (key_hash::<Q, H>(key), self.width, self.shift);
+		(0..self.k_num).map(move |row| row * width + usize::try_from(row_hash(hash, row) >> shift).unwrap())
 	}
 
     fn calc_skip(geo: Geometric, current_counter: usize) -> usize {
@@ -276,57 +657,52 @@ where
         return current_counter + v
     }
 
-    fn single_offset<Q: ?Sized + Hash>(&self, key: &Q, index: usize) -> u64 {
-        let mut hasher = XxHash::default();
-        key.hash(&mut hasher);
-        for _ in 0..index {
-            hasher.write(&[123]);
-        }
-        let offest = hasher.finish();
-        return offest & u64::try_from(self.mask).unwrap()
+    /// The position of the counter of a hashed key in `row`
+    fn single_offset(&self, hash: (u64, u64), row: usize) -> usize {
+        row * self.width + usize::try_from(row_hash(hash, row) >> self.shift).unwrap()
     }
 }
 
-fn hashes<Q: ?Sized>(key: &Q) -> impl Iterator<Item = u64>
-where
-	Q: Hash,
-{
-	#[allow(missing_copy_implementations, missing_debug_implementations)]
-	struct X(XxHash);
-	impl Iterator for X {
-		type Item = u64;
-		fn next(&mut self) -> Option<Self::Item> {
-			let ret = self.0.finish();
-			self.0.write(&[123]);
-			Some(ret)
-		}
-	}
-	let mut hasher = XxHash::default();
+/// Hash a key once into the two 64-bit hashes used for double hashing
+pub(super) fn key_hash<Q: ?Sized + Hash, H: SketchHasher>(key: &Q) -> (u64, u64) {
+	let mut hasher = H::default();
 	key.hash(&mut hasher);
-	X(hasher)
+	hasher.finish_pair()
 }
 
-impl<K: ?Sized, C: New + Clone> Clone for NitroCMS<K, C> {
+/// The hash of row `row`, derived by double hashing (Kirsch and Mitzenmacher, "Less hashing, same performance").
+/// Rows use the top bits of the hash: the low bits of h1 + row*h2 only depend on the low bits of h1 and h2,
+/// so masking them would correlate the rows.
+pub(super) fn row_hash((h1, h2): (u64, u64), row: usize) -> u64 {
+	h1.wrapping_add((row as u64).wrapping_mul(h2))
+}
+
+impl<K: ?Sized, C: New + Clone, H> Clone for NitroCMS<K, C, H> {
 	fn clone(&self) -> Self {
 		Self {
 			counters: self.counters.clone(),
 			offsets: vec![0; self.offsets.len()],
-			mask: self.mask,
+			batch_offsets: Vec::new(),
+			width: self.width,
+			shift: self.shift,
 			k_num: self.k_num,
             default: self.default.clone(),
             geo: self.geo,
 			sample_prob: self.sample_prob,
-            factor: self.factor,
 			minimum_increment: self.minimum_increment,
-            curr_counter: self.curr_counter,
-            next_counter: self.next_counter,
-            last_index: self.last_index,
+			element_sampling: self.element_sampling,
+            next_row: self.next_row,
+            skip_elements: self.skip_elements,
+            adaptive: self.adaptive.clone(),
+            convergence_volume: self.convergence_volume,
+            volume: self.volume,
+            switch_point: self.switch_point,
 			config: self.config.clone(),
 			marker: PhantomData,
 		}
 	}
 }
-impl<K: ?Sized, C: New> fmt::Debug for NitroCMS<K, C> {
+impl<K: ?Sized, C: New, H> fmt::Debug for NitroCMS<K, C, H> {
 	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
 		fmt.debug_struct("NitroCMS")
 			// .field("counters", &self.counters)
@@ -336,6 +712,7 @@ impl<K: ?Sized, C: New> fmt::Debug for NitroCMS<K, C> {
 
 #[cfg(test)]
 mod tests {
+	use super::AdaptiveRate;
 	type NitroCMS8<K> = super::NitroCMS<K, u8>;
 	type NitroCMS32<K> = super::NitroCMS<K, u32>;
 	type NitroCMS64<K> = super::NitroCMS<K, u64>;
@@ -361,6 +738,96 @@ mod tests {
 		assert!(300_000u32.abs_diff(cms.get("key")) < 30_000, "key = {}", cms.get("key"));
 	}
 
+	#[test]
+	fn test_adaptive() {
+		let mut cms = NitroCMS32::<&str>::new_adaptive(0.95, 2.0 / 100.0, true, (), AdaptiveRate::new(0.01, 1_000, None));
+		for _ in 0..300_000 {
+			let _ = cms.push("key", &1);
+		}
+		assert_eq!(cms.sample_prob(), 1.0 / 64.0);
+		assert!(300_000u32.abs_diff(cms.get("key")) < 30_000, "key = {}", cms.get("key"));
+	}
+
+	#[test]
+	fn test_always_correct() {
+		let threshold = NitroCMS32::<&str>::convergence_threshold(0.05, 2.0 / 100.0, 0.3);
+		let mut cms = NitroCMS32::<&str>::new_always_correct(0.05, 2.0 / 100.0, 0.3, true, ()).with_element_sampling();
+		for _ in 1..threshold {
+			let _ = cms.push("key", &1);
+		}
+		assert_eq!(cms.get("key"), u32::try_from(threshold - 1).unwrap());
+		assert_eq!(cms.switch_point(), None);
+		for _ in 0..threshold {
+			let _ = cms.push("key", &1);
+		}
+		assert_eq!(cms.switch_point(), Some(threshold));
+		let expected = 2 * u32::try_from(threshold).unwrap() - 1;
+		assert!(expected.abs_diff(cms.get("key")) < expected / 20, "key = {}", cms.get("key"));
+	}
+
+	#[test]
+	fn test_iter() {
+		let mut cms = NitroCMS32::<&str>::new(0.01, 0.01, 1.0, false, ());
+		let _ = cms.push("key", &5);
+		let entries: Vec<(usize, usize, u32)> = cms.iter().map(|(row, column, counter)| (row, column, *counter)).collect();
+		assert_eq!(entries.len(), 4);
+		for (row, (entry_row, column, counter)) in entries.into_iter().enumerate() {
+			assert_eq!((entry_row, counter), (row, 5));
+			assert!(column < NitroCMS32::<&str>::optimal_width(0.01));
+		}
+	}
+
+	#[test]
+	fn test_sampled_conservative_update() {
+		for element_sampling in [false, true] {
+			let mut cu = NitroCMS32::<u32>::new(0.01, 10.0 / 100.0, 0.1, true, ());
+			if element_sampling {
+				cu = cu.with_element_sampling();
+			}
+			let mut plain = NitroCMS32::<u32>::new(0.01, 10.0 / 100.0, 0.1, false, ());
+			for i in 0..300_000 {
+				let _ = cu.push(&(i % 1000), &1);
+				let _ = plain.push(&(i % 1000), &1);
+			}
+			let cu_error: u32 = (0..1000).map(|key| cu.get(&key).abs_diff(300)).sum();
+			let plain_error: u32 = (0..1000).map(|key| plain.get(&key).abs_diff(300)).sum();
+			assert!(cu_error < plain_error, "CU error {} plain error {} (element sampling {})", cu_error, plain_error, element_sampling);
+		}
+	}
+
+	#[test]
+	fn test_row_sampled_conservative_update() {
+		// a sampled row only rises to the estimate plus the value, so the rows of a single key stay within 1 of each other
+		let mut cms = NitroCMS32::<&str>::new(0.01, 10.0 / 100.0, 0.1, true, ());
+		for _ in 0..10_000 {
+			let _ = cms.push("key", &1);
+		}
+		let counters: Vec<u32> = cms.iter().map(|(_, _, counter)| *counter).collect();
+		assert_eq!(counters.len(), 4);
+		assert!(counters.iter().max().unwrap() - counters.iter().min().unwrap() <= 1, "counters = {:?}", counters);
+	}
+
+	#[test]
+	fn test_fractional_sample_prob() {
+		let mut cms = NitroCMS32::<&str>::new(0.95, 2.0 / 100.0, 0.3, true, ());
+		for _ in 0..300_000 {
+			let _ = cms.push("key", &1);
+		}
+		assert!(300_000u32.abs_diff(cms.get("key")) < 10_000, "key = {}", cms.get("key"));
+		let (low, high) = cms.confidence_interval("key", 0.001);
+		assert!(low <= cms.estimate_f64("key") && cms.estimate_f64("key") <= high);
+		assert!(high - low < 10_000.0, "interval = ({}, {})", low, high);
+	}
+
+	#[test]
+	fn test_cardinality() {
+		let mut cms = NitroCMS32::<u64>::new(0.01, 2.0 / 1_000.0, 1.0, true, ());
+		for key in 0..500_u64 {
+			let _ = cms.push(&key, &(key as u32 + 1));
+		}
+		assert!((cms.cardinality() - 500.0).abs() < 50.0, "cardinality = {}", cms.cardinality());
+	}
+
 	#[test]
 	#[cfg_attr(miri, ignore)]
 	fn test_increment_multi() {
@@ -376,4 +843,22 @@ mod tests {
 		//     assert!(cms.get(&key) < 11_000);
 		// }
 	}
+
+	#[test]
+	fn test_push_batch() {
+		let keys: Vec<u64> = (0..100_000).map(|i| (i * i) % 1_000).collect();
+		for minimum_increment in [false, true] {
+			let mut cms = NitroCMS32::<u64>::new(0.01, 0.01, 1.0, minimum_increment, ());
+			let mut batched = NitroCMS32::<u64>::new(0.01, 0.01, 1.0, minimum_increment, ());
+			for key in &keys {
+				let _ = cms.push(key, &1);
+			}
+			for batch in keys.chunks(64) {
+				batched.push_batch(batch, &1);
+			}
+			for key in 0..1_000 {
+				assert_eq!(cms.get(&key), batched.get(&key));
+			}
+		}
+	}
 }
\ No newline at end of file
@@ -0,0 +1,328 @@
+// This file modifies the source code from https://github.com/jedisct1/rust-count-min-sketch/blob/088274e22a3decc986dec928c92cc90a709a0274/src/lib.rs
+// as well as from https://github.com/constellation-rs/amadeus/blob/master/amadeus-streaming/src/count_min.rs under the following MIT License:
+// The modifications implement the NitroSketch optimization as proposed in https://dl.acm.org/doi/10.1145/3341302.3342076
+// This is the original nested (one vector per row) counter layout of NitroCMS, kept to benchmark it against the flat layout
+
+// Copyright (c) 2022 Roy Friedman - the NitroSketch modifications
+// Copyright (c) 2016 Frank Denis
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::{
+	borrow::Borrow, convert::TryFrom, fmt, hash::{Hash, Hasher}, marker::PhantomData, ops
+};
+use twox_hash::XxHash;
+use super::f64_to_usize;
+use super::hashing::SketchHasher;
+use super::nitro_cms::NitroCMS;
+use super::traits::{Intersect, IntersectPlusUnionIsPlus, New, UnionAssign, VtoUsize};
+use rand;
+use rand_distr::{Geometric, Distribution};
+use core::fmt::Debug;
+
+/// The previous layout of `NitroCMS`: one vector of counters per row, and row hashes derived by repeatedly
+/// feeding the same hasher state (`H`, `XxHash` by default). Only the push and get paths are kept, for timing the two
+/// layouts against each other (--nested-layout), with a fixed sampling probability.
+pub struct NestedNitroCMS<K: ?Sized, C: New, H = XxHash> {
+	counters: Vec<Vec<C>>,
+	offsets: Vec<usize>, // to avoid malloc/free each push
+	mask: usize,
+	k_num: usize,
+    default: C,
+    geo: Geometric,
+	sample_prob: f64,
+	minimum_increment: bool,
+    next_row: usize,
+	marker: PhantomData<fn(K) -> H>,
+}
+
+impl<K: ?Sized, C, H> NestedNitroCMS<K, C, H>
+where
+	K: Hash,
+	H: SketchHasher,
+	C: New + for<'a> UnionAssign<&'a C> + Intersect + Clone + std::convert::TryFrom<usize> + std::ops::Mul<Output = C>,
+{
+	/// Create an empty `NestedNitroCMS` data structure with the specified error tolerance.
+	pub fn new(probability: f64, tolerance: f64, sample_prob: f64, minimum_increment: bool, config: C::Config) -> Self {
+		let width = NitroCMS::<K, C, H>::optimal_width(tolerance);
+		let k_num = NitroCMS::<K, C, H>::optimal_k_num(probability);
+		let counters: Vec<Vec<C>> = (0..k_num)
+			.map(|_| (0..width).map(|_| C::new(&config)).collect())
+			.collect();
+        let default = counters[0][0].clone();
+		let offsets = vec![0; k_num];
+        let geo = Geometric::new(sample_prob).unwrap();
+        let next_row = Self::calc_skip(geo,0);
+		Self {
+			counters,
+			offsets,
+			mask: Self::mask(width),
+			k_num,
+            default,
+            geo,
+			sample_prob,
+			minimum_increment,
+            next_row,
+			marker: PhantomData,
+		}
+	}
+
+    /// "Visit" an element.
+	pub fn push<Q, V: ?Sized>(&mut self, key: &Q, value: &V) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
+	{
+        if self.sample_prob < 1.0 {
+            self.sampled_push(key, value)
+		} else if self.minimum_increment {
+			self.full_push(key, value)
+		} else {
+            self.all_push(key, value)
+        }
+	}
+
+    /// "Visit" an element - sampled version - only update sampled cpunters
+    /// With minimum increment, this is the sampled conservative update (see `sampled_conservative_push`)
+	fn sampled_push<Q, V: ?Sized>(&mut self, key: &Q, value: &V) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
+	{
+        if self.minimum_increment {
+            return self.sampled_conservative_push(key, value);
+        }
+        // counters are numbered consecutively across packets (row r of the i-th packet is i*k_num+r),
+        // and next_row is the position of the next sampled counter relative to the current packet
+        while self.next_row < self.k_num {
+            let offset = usize::try_from(self.single_offset(key,self.next_row)).unwrap();
+            self.counters[self.next_row][offset] += value;
+            self.next_row = Self::calc_skip(self.geo,self.next_row + 1);
+        }
+        self.next_row -= self.k_num;
+        self.default.clone()
+    }
+
+    /// "Visit" an element - sampled conservative update - only the sampled rows whose counters are at or below the
+    /// current estimate of the element are raised, to the estimate plus the value (see `NitroCMS`)
+	fn sampled_conservative_push<Q, V: ?Sized>(&mut self, key: &Q, value: &V) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
+	{
+        if self.next_row >= self.k_num {
+            self.next_row -= self.k_num;
+            return self.default.clone();
+        }
+        let offsets = self.offsets(key);
+        self.offsets
+            .iter_mut()
+            .zip(offsets)
+            .for_each(|(offset, offset_new)| {
+                *offset = offset_new;
+            });
+        let raised = (!<C as IntersectPlusUnionIsPlus>::VAL).then(|| {
+            let mut lowest = C::intersect(
+                self.offsets
+                    .iter()
+                    .enumerate()
+                    .map(|(k_i, &offset)| &self.counters[k_i][offset]),
+            )
+            .unwrap();
+            lowest += value;
+            lowest
+        });
+        while self.next_row < self.k_num {
+            let counter = &mut self.counters[self.next_row][self.offsets[self.next_row]];
+            match &raised {
+                Some(raised) => counter.union_assign(raised),
+                None => *counter += value,
+            }
+            self.next_row = Self::calc_skip(self.geo,self.next_row + 1);
+        }
+        self.next_row -= self.k_num;
+        self.default.clone()
+    }
+
+	/// "Visit" an element - increment all counters
+	fn all_push<Q, V: ?Sized>(&mut self, key: &Q, value: &V) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
+	{
+		let offsets = self.offsets(key);
+		self.counters
+			.iter_mut()
+			.zip(offsets)
+			.for_each(|(counters, offset)| {
+				counters[offset] += value;
+			});
+		self.default.clone()
+	}
+
+	/// "Visit" an element - full version - taken from the original CMS implementation
+	fn full_push<Q, V: ?Sized>(&mut self, key: &Q, value: &V) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: for<'a> ops::AddAssign<&'a V> + IntersectPlusUnionIsPlus,
+	{
+		let offsets = self.offsets(key);
+		if !<C as IntersectPlusUnionIsPlus>::VAL {
+			self.offsets
+				.iter_mut()
+				.zip(offsets)
+				.for_each(|(offset, offset_new)| {
+					*offset = offset_new;
+				});
+			let mut lowest = C::intersect(
+				self.offsets
+					.iter()
+					.enumerate()
+					.map(|(k_i, &offset)| &self.counters[k_i][offset]),
+			)
+			.unwrap();
+			lowest += value;
+			self.counters
+				.iter_mut()
+				.zip(self.offsets.iter())
+				.for_each(|(counters, &offset)| {
+					counters[offset].union_assign(&lowest);
+				});
+			lowest
+		} else {
+			C::intersect(
+				self.counters
+					.iter_mut()
+					.zip(offsets)
+					.map(|(counters, offset)| {
+						counters[offset] += value;
+						&counters[offset]
+					}),
+			)
+			.unwrap()
+		}
+	}
+
+	/// Retrieve an estimate of the aggregated value for `key`, scaling the sampled counters by 1/p.
+	pub fn get<Q>(&self, key: &Q) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+		C: VtoUsize,
+        <C as TryFrom<usize>>::Error:Debug
+	{
+		if self.sample_prob < 1.0 {
+			let estimate = self.min_counter(key).v_to_usize() as f64 / self.sample_prob;
+			C::try_from(f64_to_usize(estimate.round())).unwrap()
+		} else {
+			self.min_counter(key)
+		}
+	}
+
+	fn min_counter<Q>(&self, key: &Q) -> C
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		C::intersect(
+			self.counters
+				.iter()
+				.zip(self.offsets(key))
+				.map(|(counters, offset)| &counters[offset]),
+		)
+		.unwrap()
+	}
+
+	fn mask(width: usize) -> usize {
+		assert!(width > 1);
+		assert_eq!(width & (width - 1), 0);
+		width - 1
+	}
+
+	fn offsets<Q>(&self, key: &Q) -> impl Iterator<Item = usize>
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		let mask = self.mask;
+		hashes::<Q, H>(key).map(move |hash| usize::try_from(hash & u64::try_from(mask).unwrap()).unwrap())
+	}
+
+    fn calc_skip(geo: Geometric, current_counter: usize) -> usize {
+        let v = geo.sample(&mut rand::thread_rng()) as usize;
+        current_counter + v
+    }
+
+    fn single_offset<Q: ?Sized + Hash>(&self, key: &Q, index: usize) -> u64 {
+        let mut hasher = H::default();
+        key.hash(&mut hasher);
+        for _ in 0..index {
+            hasher.write(&[123]);
+        }
+        let offest = hasher.finish();
+        offest & u64::try_from(self.mask).unwrap()
+    }
+}
+
+fn hashes<Q, H>(key: &Q) -> impl Iterator<Item = u64>
+where
+	Q: ?Sized + Hash,
+	H: SketchHasher,
+{
+	#[allow(missing_copy_implementations, missing_debug_implementations)]
+	struct X<H>(H);
+	impl<H: Hasher> Iterator for X<H> {
+		type Item = u64;
+		fn next(&mut self) -> Option<Self::Item> {
+			let ret = self.0.finish();
+			self.0.write(&[123]);
+			Some(ret)
+		}
+	}
+	let mut hasher = H::default();
+	key.hash(&mut hasher);
+	X(hasher)
+}
+
+impl<K: ?Sized, C: New, H> fmt::Debug for NestedNitroCMS<K, C, H> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("NestedNitroCMS")
+			// .field("counters", &self.counters)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	type NestedNitroCMS32<K> = super::NestedNitroCMS<K, u32>;
+
+	#[test]
+	fn test_increment() {
+		let mut cms = NestedNitroCMS32::<&str>::new(0.95, 2.0 / 100.0, 0.1, true, ());
+		for _ in 0..300_000 {
+			let _ = cms.push("key", &1);
+		}
+		assert!(300_000u32.abs_diff(cms.get("key")) < 30_000, "key = {}", cms.get("key"));
+	}
+}
@@ -1,9 +1,10 @@
-use super::f64_to_usize;
+use super::{f64_to_usize, sampling_confidence_interval};
 use std::fmt::Debug;
 use rand;
 use rand_distr::{Geometric, Distribution};
 use crate::CuckooCountingFilter;
-use crate::more_streaming::cuckoo::{CuckooError,DEFAULT_CAPACITY};
+use crate::more_streaming::cuckoo::{CuckooError,Fingerprint,DEFAULT_CAPACITY,BUCKET_SIZE};
+use crate::more_streaming::adaptive::AdaptiveRate;
 use crate::{Hash,Hasher};
 
 /// A wrapper over CuckooCountingFilter that adds nitro (as in NitroSketch) type sampling to it
@@ -11,14 +12,15 @@ use crate::{Hash,Hasher};
 /// each time how many inserts to ignore rather than deciding on each insert whether to accept it
 
 #[derive(Debug)]
-pub struct NitroCuckoo<H> {
-    counters: CuckooCountingFilter<H>,
+pub struct NitroCuckoo<H, const B: usize = BUCKET_SIZE> {
+    counters: CuckooCountingFilter<H, B>,
     geometric_distribution_provider: Geometric,
-    factor: usize,
+    sample_probability: f64,
     item_skip : usize,
+    adaptive: Option<AdaptiveRate>,
 }
 
-impl <H>NitroCuckoo<H> 
+impl <H, const B: usize>NitroCuckoo<H, B> 
 where
 H: Hasher+Default,
 {
@@ -30,34 +32,99 @@ H: Hasher+Default,
     /// starts a new filter with a given capacity
     pub fn with_capacity(capacity: usize, sample_probability: f64) -> Self 
     {
-        let counters = CuckooCountingFilter::<H>::with_capacity(capacity);
+        let counters = CuckooCountingFilter::<H, B>::with_capacity(capacity);
         let geometric_distribution_provider = Geometric::new(sample_probability).unwrap();
-        let factor = f64_to_usize((1.0/sample_probability).round());
-        let item_skip = 0;
+        let item_skip = geometric_distribution_provider.sample(&mut rand::thread_rng()) as usize;
         Self {
             counters,
             geometric_distribution_provider,
-            factor,
+            sample_probability,
             item_skip,
+            adaptive: None,
         }
     }
 
+    /// starts a new filter with a given capacity whose sampling probability adapts to the stream according to `rate`;
+    /// sampled items add 1/p to their counter, so the counters hold estimates regardless of rate changes
+    pub fn with_capacity_adaptive(capacity: usize, rate: AdaptiveRate) -> Self
+    {
+        let mut filter = Self::with_capacity(capacity, rate.sample_prob());
+        filter.adaptive = Some(rate);
+        filter
+    }
+
+    /// the current sampling probability
+    pub fn sample_prob(&self) -> f64 {
+        self.sample_probability
+    }
+
+    /// the adaptive sampling rate, if the filter was created with one
+    pub fn adaptive_rate(&self) -> Option<&AdaptiveRate> {
+        self.adaptive.as_ref()
+    }
+
     /// "Visit" an element - sampled version - only update sampled counters
     pub fn add<T: ?Sized + Hash>(&mut self, id: &T) -> Result<(), CuckooError>
 	{
+        if let Some(sample_probability) = self.adaptive.as_mut().and_then(|rate| rate.observe()) {
+            self.sample_probability = sample_probability;
+            self.geometric_distribution_provider = Geometric::new(sample_probability).unwrap();
+            self.item_skip = self.geometric_distribution_provider.sample(&mut rand::thread_rng()) as usize;
+        }
         if self.item_skip > 0 {
             self.item_skip -= 1;
             Ok(())
         } else {
             self.item_skip = self.geometric_distribution_provider.sample(&mut rand::thread_rng()) as usize;
-            self.counters.add(&id)
+            match &self.adaptive {
+                Some(rate) => self.counters.add_count(&id, u32::try_from(rate.weight()).unwrap()),
+                None => self.counters.add(&id),
+            }
         }
     }
 
-    /// return an estimate of an item's count
+    /// "Visit" a batch of elements - first pick the sampled elements, then add them to the filter as a batch
+    /// (see `CuckooCountingFilter::add_batch`). With an adaptive rate the elements are added one by one.
+    pub fn add_batch<T: Hash>(&mut self, ids: &[T]) -> Result<(), CuckooError>
+    {
+        if self.adaptive.is_some() {
+            let mut result = Ok(());
+            for id in ids {
+                if let Err(err) = self.add(id) {
+                    result = Err(err);
+                }
+            }
+            return result;
+        }
+        let mut sampled = Vec::new();
+        for id in ids {
+            if self.item_skip > 0 {
+                self.item_skip -= 1;
+            } else {
+                self.item_skip = self.geometric_distribution_provider.sample(&mut rand::thread_rng()) as usize;
+                sampled.push(id);
+            }
+        }
+        self.counters.add_batch(&sampled)
+    }
+
+    /// return an estimate of an item's count, i.e., `estimate_f64` rounded to the nearest integer
     pub fn get<T: ?Sized + Hash>(&self, id: &T) -> u32
     {
-        self.counters.get(&id) * u32::try_from(self.factor).unwrap()
+        self.scale(self.counters.get(&id))
+    }
+
+    /// return an unbiased estimate of an item's count, scaling the sampled count by exactly 1/p
+    /// (with an adaptive rate the counters are already scaled on update)
+    pub fn estimate_f64<T: ?Sized + Hash>(&self, id: &T) -> f64
+    {
+        f64::from(self.counters.get(&id)) / self.scale_probability()
+    }
+
+    /// return a confidence interval, holding with probability 1-delta, for an item's count
+    pub fn confidence_interval<T: ?Sized + Hash>(&self, id: &T, delta: f64) -> (f64, f64)
+    {
+        sampling_confidence_interval(self.estimate_f64(id), self.sample_probability, delta)
     }
 
     /// return the capacity of the filter
@@ -65,11 +132,37 @@ H: Hasher+Default,
         self.counters.capacity()
     }
 
+    /// return the number of fingerprint slots of the filter
+    pub fn slots(&self) -> usize {
+        self.counters.slots()
+    }
+
     /// retun the actual number of unique items (fingerprints to be precise) in the filter
     pub fn len(&self) -> usize {
         self.counters.len()
     }
 
+    /// return the estimated number of distinct sampled items, corrected for the items sharing a fingerprint
+    /// (see `CuckooCountingFilter::cardinality`); flows that were never sampled are not counted
+    pub fn cardinality(&self) -> f64 {
+        self.counters.cardinality()
+    }
+
+    /// iterate over the occupied slots as (bucket, fingerprint, estimated count)
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Fingerprint, u32)> + '_ {
+        self.counters.iter().map(move |(bucket, fp, val)| (bucket, fp, self.scale(val)))
+    }
+
+    // scale a sampled count by 1/p and round to the nearest integer
+    fn scale(&self, val: u32) -> u32 {
+        u32::try_from(f64_to_usize((f64::from(val) / self.scale_probability()).round())).unwrap()
+    }
+
+    // the probability by which stored counts should be divided
+    fn scale_probability(&self) -> f64 {
+        if self.adaptive.is_some() { 1.0 } else { self.sample_probability }
+    }
+
 }
 
 #[cfg(test)]
@@ -1,65 +1,111 @@
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
 use std::collections::HashMap;
-use super::f64_to_usize;
+use std::collections::hash_map::RandomState;
+use super::{f64_to_usize, sampling_confidence_interval};
 use std::fmt::Debug;
 use rand;
 use rand_distr::{Geometric, Distribution};
+use super::adaptive::AdaptiveRate;
 
-/// A simple hash table of counters with geometric sampling
+/// A simple hash table of counters with geometric sampling, hashing keys with `S`
 
 #[derive(Debug)]
-pub struct NitroHash<K: Hash + std::cmp::Eq, V> {
-    counters: HashMap<K, V>,
+pub struct NitroHash<K: Hash + std::cmp::Eq, V, S = RandomState> {
+    counters: HashMap<K, V, S>,
     geometric_distribution_provider: Geometric,
-    factor: V,
+    sample_prob: f64,
     item_skip: usize,
+    adaptive: Option<AdaptiveRate>,
 }
 
-impl <K, V>NitroHash<K,V> 
+impl <K, V, S>NitroHash<K,V,S> 
 where
 K: Clone + Hash + std::cmp::Eq,
+S: BuildHasher + Default,
 V: std::ops::Add<Output=V> + std::ops::AddAssign + TryFrom<usize> + TryFrom<u64> + TryFrom<u32> + TryFrom<u16> + TryFrom<u8> + Copy + super::traits::VtoUsize + std::fmt::Debug +  std::ops::Mul<Output = V>
 {
     pub fn new(sample_prob: f64) -> Self 
-    where <V as TryFrom<usize>>::Error: Debug
     {
-        let counters = HashMap::new();
+        let counters = HashMap::default();
         let geometric_distribution_provider = Geometric::new(sample_prob).unwrap();
-        let factor = V::try_from(f64_to_usize((1.0/sample_prob).round())).unwrap();
-        let item_skip = 0;
+        let item_skip = geometric_distribution_provider.sample(&mut rand::thread_rng()) as usize;
         Self {
             counters,
             geometric_distribution_provider,
-            factor,
+            sample_prob,
             item_skip,
+            adaptive: None,
         }
     }
 
+    /// starts a table whose sampling probability adapts to the stream according to `rate`;
+    /// sampled items add 1/p to their counter, so the counters hold estimates regardless of rate changes
+    pub fn new_adaptive(rate: AdaptiveRate) -> Self
+    {
+        let mut nitrohash = Self::new(rate.sample_prob());
+        nitrohash.adaptive = Some(rate);
+        nitrohash
+    }
+
+    /// the current sampling probability
+    pub fn sample_prob(&self) -> f64
+    {
+        self.sample_prob
+    }
+
+    /// the adaptive sampling rate, if the table was created with one
+    pub fn adaptive_rate(&self) -> Option<&AdaptiveRate>
+    {
+        self.adaptive.as_ref()
+    }
+
     /// "Visit" an element - sampled version - only update sampled cpunters
     pub fn insert(&mut self, id: K) 
-    where <V as TryFrom<u8>>::Error: Debug
+    where <V as TryFrom<u8>>::Error: Debug, <V as TryFrom<usize>>::Error: Debug
 	{
+        if let Some(sample_prob) = self.adaptive.as_mut().and_then(|rate| rate.observe()) {
+            self.sample_prob = sample_prob;
+            self.geometric_distribution_provider = Geometric::new(sample_prob).unwrap();
+            self.item_skip = self.geometric_distribution_provider.sample(&mut rand::thread_rng()) as usize;
+        }
         if self.item_skip > 0 {
             self.item_skip -= 1;
         } else { 
+            let increment = match &self.adaptive {
+                Some(rate) => V::try_from(rate.weight()).unwrap(),
+                None => V::try_from(1_u8).unwrap(),
+            };
             if let Some(counter) = self.counters.get_mut(&id) {
-                *counter += V::try_from(1_u8).unwrap();
+                *counter += increment;
             } else {
-                self.counters.insert(id,V::try_from(1_u8).unwrap());
+                self.counters.insert(id,increment);
             }
             self.item_skip = self.geometric_distribution_provider.sample(&mut rand::thread_rng()) as usize;
 
         }
     }
 
-    /// return an item's estimated count
+    /// return an item's estimated count, i.e., `estimate_f64` rounded to the nearest integer
     pub fn get(&self, id: K) -> V
-    where <V as TryFrom<u8>>::Error: Debug
+    where <V as TryFrom<usize>>::Error: Debug
+    {
+        self.scale(self.counters.get(&id))
+    }
+
+    /// return an item's unbiased estimated count, scaling the sampled count by exactly 1/p
+    /// (with an adaptive rate the counters are already scaled on update)
+    pub fn estimate_f64(&self, id: K) -> f64
     {
         if let Some(val) = self.counters.get(&id) {
-            return *val * self.factor;
+            return val.v_to_usize() as f64 / self.scale_prob();
         }
-        return V::try_from(0_u8).unwrap();
+        0.0
+    }
+
+    /// return a confidence interval, holding with probability 1-delta, for an item's count
+    pub fn confidence_interval(&self, id: K, delta: f64) -> (f64, f64)
+    {
+        sampling_confidence_interval(self.estimate_f64(id), self.sample_prob, delta)
     }
 
     /// return the hash table's capacity
@@ -73,6 +119,32 @@ V: std::ops::Add<Output=V> + std::ops::AddAssign + TryFrom<usize> + TryFrom<u64>
     {
         return self.counters.len();
     }
+
+    /// remove all the items (keeping the sampling state)
+    pub fn clear(&mut self)
+    {
+        self.counters.clear();
+    }
+
+    /// iterate over the stored items and their estimated counts
+    pub fn iter(&self) -> impl Iterator<Item = (&K, V)> + '_
+    where <V as TryFrom<usize>>::Error: Debug
+    {
+        self.counters.iter().map(|(id, val)| (id, self.scale(Some(val))))
+    }
+
+    // scale a sampled count by 1/p and round to the nearest integer
+    fn scale(&self, val: Option<&V>) -> V
+    where <V as TryFrom<usize>>::Error: Debug
+    {
+        let count = val.map_or(0, |v| v.v_to_usize());
+        V::try_from(f64_to_usize((count as f64 / self.scale_prob()).round())).unwrap()
+    }
+
+    // the probability by which stored counts should be divided
+    fn scale_prob(&self) -> f64 {
+        if self.adaptive.is_some() { 1.0 } else { self.sample_prob }
+    }
 }
 
 #[cfg(test)]
@@ -89,4 +161,15 @@ mod tests {
 		}
 		assert!(TEST_N_ITEMS.abs_diff(usize::try_from(nitrohash.get("key")).unwrap()) < TEST_ERROR_TOLERANCE, "DIFF nitrohash = {}", nitrohash.get("key"));
 	}
+
+    #[test]
+    fn test_fractional_sample_prob() {
+		let mut nitrohash:super::NitroHash<&str,u32> = super::NitroHash::new(0.3);
+		for _ in 0..TEST_N_ITEMS {
+			nitrohash.insert("key");
+		}
+		assert!(TEST_N_ITEMS.abs_diff(usize::try_from(nitrohash.get("key")).unwrap()) < TEST_ERROR_TOLERANCE, "DIFF nitrohash = {}", nitrohash.get("key"));
+		let (low, high) = nitrohash.confidence_interval("key", 0.001);
+		assert!(low <= nitrohash.estimate_f64("key") && nitrohash.estimate_f64("key") <= high);
+	}
 }
\ No newline at end of file
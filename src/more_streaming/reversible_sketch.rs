@@ -0,0 +1,153 @@
+use std::{
+	fmt, marker::PhantomData
+};
+use super::nitro_cms::{key_hash, row_hash, NitroCMS};
+use super::hashing::{SketchHasher, Xxh3Hasher};
+use super::median;
+
+/// Number of bits of the keys
+const KEY_BITS: usize = 64;
+/// Each bucket holds its total, followed by the total of the keys with each bit set
+const BUCKET_LEN: usize = 1 + KEY_BITS;
+
+/// A k-ary sketch (Krishnamurthy, Sen, Zhang and Chen) of u64 keys, whose heavy keys can be recovered from the sketch
+/// itself, without a key store. As with the reversible sketches of Schweller et al., the difference of the sketches of
+/// two epochs reports the keys of the large changes. The keys are recovered as in the deltoids of Cormode and
+/// Muthukrishnan: each bucket also counts, for each key bit, the keys with the bit set, so that the key whose change
+/// dominates a bucket is read bit by bit (a bit is set when the keys with the bit set carry the larger part of the
+/// bucket's total). It has the dimensions and row hashes of a `NitroCMS` with the same parameters, with 65 counters per
+/// bucket.
+pub struct ReversibleSketch<H = Xxh3Hasher> {
+	counters: Vec<i64>, // k_num rows of width buckets of BUCKET_LEN counters, stored contiguously
+	width: usize,
+	shift: u32,
+	k_num: usize,
+	volume: i64,
+	marker: PhantomData<fn() -> H>,
+}
+
+impl<H: SketchHasher> ReversibleSketch<H> {
+	/// Create an empty `ReversibleSketch` with the specified error tolerance.
+	pub fn new(probability: f64, tolerance: f64) -> Self {
+		let width = NitroCMS::<u64, u32, H>::optimal_width(tolerance);
+		let k_num = NitroCMS::<u64, u32, H>::optimal_k_num(probability);
+		Self {
+			counters: vec![0; k_num * width * BUCKET_LEN],
+			width,
+			shift: NitroCMS::<u64, u32, H>::shift(width),
+			k_num,
+			volume: 0,
+			marker: PhantomData,
+		}
+	}
+
+	/// Add `value` to the count of `key`
+	pub fn push(&mut self, key: u64, value: i64) {
+		let hash = key_hash::<u64, H>(&key);
+		for row in 0..self.k_num {
+			let bucket = self.bucket(hash, row);
+			self.counters[bucket] += value;
+			for bit in (0..KEY_BITS).filter(|bit| (key >> bit) & 1 == 1) {
+				self.counters[bucket + 1 + bit] += value;
+			}
+		}
+		self.volume += value;
+	}
+
+	/// Retrieve an estimate of the count of `key`: the median over the rows of its bucket's total, minus the average
+	/// bucket total, scaled by w/(w-1)
+	pub fn estimate(&self, key: u64) -> f64 {
+		let hash = key_hash::<u64, H>(&key);
+		let mut estimates: Vec<f64> = (0..self.k_num)
+			.map(|row| self.bucket_estimate(self.bucket(hash, row)))
+			.collect();
+		median(&mut estimates)
+	}
+
+	/// Recover the keys whose estimates reach `threshold` in absolute value, from the buckets whose estimate reaches it
+	pub fn recover(&self, threshold: f64) -> Vec<u64> {
+		let mut keys = Vec::new();
+		for row in 0..self.k_num {
+			for column in 0..self.width {
+				let bucket = (row * self.width + column) * BUCKET_LEN;
+				if self.bucket_estimate(bucket).abs() < threshold {
+					continue;
+				}
+				let total = self.counters[bucket];
+				let key = (0..KEY_BITS)
+					.filter(|bit| {
+						let set = self.counters[bucket + 1 + bit];
+						set.abs() > (total - set).abs()
+					})
+					.fold(0, |key, bit| key | (1 << bit));
+				// without a dominant key, the decoded key is noise, which most likely does not hash to the bucket
+				if self.bucket(key_hash::<u64, H>(&key), row) == bucket && !keys.contains(&key) && self.estimate(key).abs() >= threshold {
+					keys.push(key);
+				}
+			}
+		}
+		keys
+	}
+
+	/// The sketch of the changes of the counts from `previous`, a sketch with the same dimensions, to this one
+	pub fn difference(&self, previous: &Self) -> Self {
+		assert_eq!(self.counters.len(), previous.counters.len());
+		Self {
+			counters: self.counters.iter().zip(&previous.counters).map(|(counter, previous)| counter - previous).collect(),
+			width: self.width,
+			shift: self.shift,
+			k_num: self.k_num,
+			volume: self.volume - previous.volume,
+			marker: PhantomData,
+		}
+	}
+
+	/// Clears the `ReversibleSketch`, as if it was new.
+	pub fn clear(&mut self) {
+		self.counters.fill(0);
+		self.volume = 0;
+	}
+
+	/// returns an estimation of the memory used
+	pub fn estimate_memory_size(&self) -> usize {
+		self.counters.len() * std::mem::size_of::<i64>()
+	}
+
+	fn bucket(&self, hash: (u64, u64), row: usize) -> usize {
+		(row * self.width + usize::try_from(row_hash(hash, row) >> self.shift).unwrap()) * BUCKET_LEN
+	}
+
+	fn bucket_estimate(&self, bucket: usize) -> f64 {
+		let width = self.width as f64;
+		(self.counters[bucket] as f64 - self.volume as f64 / width) / (1.0 - 1.0 / width)
+	}
+}
+
+impl<H> fmt::Debug for ReversibleSketch<H> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("ReversibleSketch")
+			.field("width", &self.width)
+			.field("k_num", &self.k_num)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	type ReversibleSketch = super::ReversibleSketch;
+
+	#[test]
+	fn test_recover() {
+		let mut previous = ReversibleSketch::new(0.01, 0.01);
+		let mut current = ReversibleSketch::new(0.01, 0.01);
+		for key in 0..2000_u64 {
+			previous.push(key << 20, 10);
+			current.push(key << 20, if key == 7 { 2010 } else if key == 9 { 10 - 1500 } else { 10 });
+		}
+		let difference = current.difference(&previous);
+		assert!((difference.estimate(7 << 20) - 2000.0).abs() < 100.0, "{}", difference.estimate(7 << 20));
+		let mut keys = difference.recover(1000.0);
+		keys.sort();
+		assert_eq!(keys, vec![7 << 20, 9 << 20]);
+	}
+}
@@ -0,0 +1,165 @@
+use std::{
+	borrow::Borrow, collections::VecDeque, fmt, hash::Hash
+};
+use super::nitro_cms::NitroCMS;
+use super::hashing::{SketchHasher, Xxh3Hasher};
+use super::sampling_confidence_interval;
+
+/// A sliding window `NitroCMS`: the last `window` items are split into panes of window/panes items, each counted by its
+/// own `NitroCMS`, and the estimate of a key is the sum of its estimates over the panes of the window and the current
+/// (partial) pane. The window thus jumps by a pane at a time, covering between `window` and window + window/panes of the
+/// last items, so that (without sampling) the count of a key in the window is never underestimated.
+/// The pane that leaves the window is cleared and reused for the next pane.
+pub struct SlidingNitroCMS<K: ?Sized, H = Xxh3Hasher> {
+	panes: VecDeque<(NitroCMS<K, u32, H>, usize)>, // each pane and the items it counted, oldest first, the last is the current one
+	pane_size: usize,
+	sample_prob: f64,
+}
+
+impl<K: ?Sized, H> SlidingNitroCMS<K, H>
+where
+	K: Hash,
+	H: SketchHasher,
+{
+	/// Create an empty `SlidingNitroCMS` over the last `window` items split into `panes` panes, each a `NitroCMS` with
+	/// the specified error tolerance and sampling probability.
+	pub fn new(probability: f64, tolerance: f64, sample_prob: f64, minimum_increment: bool, window: usize, panes: usize) -> Self {
+		assert!(panes > 0 && window >= panes);
+		Self {
+			panes: (0..=panes).map(|_| (NitroCMS::new(probability, tolerance, sample_prob, minimum_increment, ()), 0)).collect(),
+			pane_size: window.div_ceil(panes),
+			sample_prob,
+		}
+	}
+
+	/// Add `value` items of `key` to the current pane, moving to the next pane once it is full
+	/// (the items that do not fit in the current pane are added to the next ones).
+	pub fn push<Q>(&mut self, key: &Q, mut value: u32)
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		while value > 0 {
+			let (pane, items) = self.panes.back_mut().unwrap();
+			let part = value.min(u32::try_from(self.pane_size - *items).unwrap_or(u32::MAX));
+			pane.push(key, &part);
+			*items += part as usize;
+			value -= part;
+			if *items == self.pane_size {
+				let (mut expired, _) = self.panes.pop_front().unwrap();
+				expired.clear();
+				self.panes.push_back((expired, 0));
+			}
+		}
+	}
+
+	/// Retrieve an estimate of the count of `key` in the window.
+	pub fn get<Q>(&self, key: &Q) -> u32
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		self.panes.iter().map(|(pane, _)| pane.get(key)).sum()
+	}
+
+	/// Retrieve an unbiased estimate of the count of `key` in the panes (see `NitroCMS::estimate_f64`).
+	pub fn estimate_f64<Q>(&self, key: &Q) -> f64
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		self.panes.iter().map(|(pane, _)| pane.estimate_f64(key)).sum()
+	}
+
+	/// Retrieve a confidence interval, holding with probability 1-delta, for the count of `key` in the panes (see `NitroCMS`).
+	pub fn confidence_interval<Q>(&self, key: &Q, delta: f64) -> (f64, f64)
+	where
+		Q: ?Sized + Hash,
+		K: Borrow<Q>,
+	{
+		sampling_confidence_interval(self.estimate_f64(key), self.sample_prob, delta)
+	}
+
+	/// The number of items counted by the panes, at least the window once the stream is longer than the window
+	pub fn covered_items(&self) -> usize {
+		self.panes.iter().map(|(_, items)| items).sum()
+	}
+
+	/// The number of items in a pane
+	pub fn pane_size(&self) -> usize {
+		self.pane_size
+	}
+
+	/// The number of counters in each row of a pane
+	pub fn width(&self) -> usize {
+		self.panes[0].0.width()
+	}
+
+	/// The number of rows of each pane
+	pub fn k_num(&self) -> usize {
+		self.panes[0].0.k_num()
+	}
+
+	/// The number of panes, including the current one
+	pub fn pane_count(&self) -> usize {
+		self.panes.len()
+	}
+
+	/// The sampling probability
+	pub fn sample_prob(&self) -> f64 {
+		self.sample_prob
+	}
+
+	/// Whether the panes conservatively update sampled rows (see `NitroCMS::is_row_sampled_conservative`)
+	pub fn is_row_sampled_conservative(&self) -> bool {
+		self.panes[0].0.is_row_sampled_conservative()
+	}
+
+	/// Iterate over the non-zero counters as (pane, row, column, counter), the oldest pane first.
+	pub fn iter(&self) -> impl Iterator<Item = (usize, usize, usize, &u32)> + '_ {
+		self.panes
+			.iter()
+			.enumerate()
+			.flat_map(|(index, (pane, _))| pane.iter().map(move |(row, column, counter)| (index, row, column, counter)))
+	}
+
+	/// returns an estimation of the memory used
+	pub fn estimate_memory_size(&self) -> usize {
+		self.panes.iter().map(|(pane, _)| pane.estimate_memory_size()).sum()
+	}
+}
+
+impl<K: ?Sized, H> fmt::Debug for SlidingNitroCMS<K, H> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("SlidingNitroCMS")
+			.field("panes", &self.panes.len())
+			.field("pane_size", &self.pane_size)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	type SlidingNitroCMS<K> = super::SlidingNitroCMS<K>;
+
+	#[test]
+	fn test_window() {
+		let mut cms = SlidingNitroCMS::<u32>::new(0.01, 0.01, 1.0, true, 1000, 4);
+		for _ in 0..1000 {
+			cms.push(&1, 1);
+		}
+		assert_eq!(cms.get(&1), 1000);
+		assert_eq!(cms.covered_items(), 1000);
+		for item in 0..1100_u32 {
+			cms.push(&(2 + item % 10), 1);
+		}
+		// the window jumped past all the items of key 1
+		assert_eq!(cms.get(&1), 0);
+		assert_eq!(cms.covered_items(), 1100);
+		assert!(cms.get(&2) >= 110);
+		// a weighted push spills over into the next panes
+		cms.push(&3, 600);
+		assert_eq!(cms.covered_items(), 1200);
+		assert!(cms.get(&3) >= 600);
+	}
+}
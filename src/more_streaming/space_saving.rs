@@ -11,7 +11,8 @@
 //         replace entry with x and counter=(min+1) (priority=(min+1))
 //   }
 
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
+use std::collections::hash_map::RandomState;
 use priority_queue::DoublePriorityQueue;
 use super::f64_to_usize;
 use rand::Rng;
@@ -22,21 +23,22 @@ use increment::*;
 /// RAP optimization of Ben Basat, Chen, Einziger, Friedman, and Kassner
 
 #[derive(Debug)]
-pub struct SpaceSaving<K: Hash + std::cmp::Eq, V: std::cmp::Ord> {
-    counters: DoublePriorityQueue<K, V>,
+pub struct SpaceSaving<K: Hash + std::cmp::Eq, V: std::cmp::Ord, S = RandomState> {
+    counters: DoublePriorityQueue<K, V, S>,
     capacity: usize,
     num: usize,
     rap: bool, // indicates whether we execute the RAP optimization
 }
 
-impl <K, V>SpaceSaving<K,V> 
+impl <K, V, S>SpaceSaving<K,V,S> 
 where
 K: Clone + Hash + std::cmp::Eq,
+S: BuildHasher + Default,
 V: std::cmp::Ord + std::ops::Add<Output=V> + std::ops::AddAssign + TryFrom<u8> + Copy + super::traits::VtoUsize + std::fmt::Debug + increment::Incrementable
 {
     pub fn new(error: f64, rap: bool) -> Self {
         let capacity = f64_to_usize((1.0/error).round());
-        let counters = DoublePriorityQueue::with_capacity(capacity);
+        let counters = DoublePriorityQueue::with_capacity_and_hasher(capacity, S::default());
         let num = 0;
         Self {
             counters,
@@ -67,14 +69,52 @@ V: std::cmp::Ord + std::ops::Add<Output=V> + std::ops::AddAssign + TryFrom<u8> +
         }
     }
 
+    /// insert an item with a weight, i.e., the count of several occurrences at once (as when merging the counts of a window);
+    /// an unmonitored item replaces the minimum and adds its weight to it (with RAP, with probability weight/(min+weight))
+    pub fn insert_weighted(&mut self, id: K, weight: V)
+    {
+        if let Some(counter) = self.counters.get_priority(&id) {
+            let added = *counter + weight;
+            self.counters.change_priority(&id, added);
+        } else if self.num < self.capacity {
+            self.counters.push(id, weight);
+            self.num += 1;
+        } else {
+            let (_minkey,minval) = self.counters.peek_min().unwrap();
+            let added = *minval + weight;
+            if !self.rap || rand::thread_rng().gen_range(0..added.v_to_usize()) < weight.v_to_usize() {
+                self.counters.pop_min();
+                self.counters.push(id,added);
+            }
+        }
+    }
+
+    /// replace each counter by `scale` of it (e.g., to age the counts); `scale` must keep the order of the counters
+    pub fn scale_counts(&mut self, scale: impl Fn(V) -> V) {
+        for (_, val) in self.counters.iter_mut() {
+            *val = scale(*val);
+        }
+    }
+
     /// return an estimate of an item's value from space saving
     pub fn get(&self, id: K) -> V {
         if let Some(val) = self.counters.get_priority(&id) {
             return *val;
         }
-        let (_,val) = self.counters.peek_min().unwrap();
-        return *val;
+        if let Some((_,val)) = self.counters.peek_min() {
+            return *val;
+        }
+        V::try_from(0_u8).ok().unwrap()
+    }
+
+    /// returns the number of monitored items
+    pub fn len(&self) -> usize {
+        self.num
+    }
 
+    /// returns whether no item is monitored
+    pub fn is_empty(&self) -> bool {
+        self.num == 0
     }
 
     /// returns the number of entries in space saving
@@ -82,6 +122,16 @@ V: std::cmp::Ord + std::ops::Add<Output=V> + std::ops::AddAssign + TryFrom<u8> +
         return self.capacity;
     }
 
+    /// returns whether the RAP optimization is used
+    pub fn is_rap(&self) -> bool {
+        self.rap
+    }
+
+    /// iterate over the monitored items and their estimated counts
+    pub fn iter(&self) -> impl Iterator<Item = (&K, V)> + '_ {
+        self.counters.iter().map(|(id, val)| (id, *val))
+    }
+
     fn coin_flip(&self, probability: usize) -> bool {
         let mut rng = rand::thread_rng();
         return rng.gen_range(0..probability) == 0;
@@ -110,4 +160,15 @@ mod tests {
 		}
 		assert!(TEST_N_ITEMS.abs_diff(usize::try_from(spacesaving.get("key")).unwrap()) < TEST_ERROR_TOLERANCE, "DIFF spacesaving = {}", spacesaving.get("key"));
 	}
+    #[test]
+    fn test_iter() {
+		let mut spacesaving:super::SpaceSaving<&str,u32> = super::SpaceSaving::new(TEST_ERROR,false);
+		for _ in 0..TEST_N_ITEMS {
+			spacesaving.insert("key");
+		}
+		spacesaving.insert("other");
+		let mut entries: Vec<(&str,u32)> = spacesaving.iter().map(|(id, val)| (*id, val)).collect();
+		entries.sort();
+		assert_eq!(entries, vec![("key", u32::try_from(TEST_N_ITEMS).unwrap()), ("other", 1)]);
+	}
 }
\ No newline at end of file
@@ -0,0 +1,74 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::{Mutex, MutexGuard};
+
+/// Salt of the stripe hash, so that the choice of a stripe is independent of the positions inside the stripe
+const STRIPE_SALT: u64 = 0x57a1_9e5d_0c0f_fee5;
+
+/// A structure split into independent stripes, each behind its own lock, so that several threads can update it at once
+/// (a lock-striped counting cuckoo filter, for instance). A key always goes to the same stripe, chosen by a salted hash
+/// of the key, so each stripe is a smaller copy of the structure that sees a random part of the flows.
+#[derive(Debug)]
+pub struct Striped<S, H = DefaultHasher> {
+    stripes: Vec<Mutex<S>>,
+    _hasher: PhantomData<fn() -> H>,
+}
+
+impl<S, H> Striped<S, H>
+where
+    H: Hasher + Default,
+{
+    /// Creates `stripes` stripes with `new_stripe`
+    pub fn new(stripes: usize, new_stripe: impl FnMut() -> S) -> Self {
+        assert!(stripes > 0);
+        Self {
+            stripes: std::iter::repeat_with(new_stripe).take(stripes).map(Mutex::new).collect(),
+            _hasher: PhantomData,
+        }
+    }
+
+    /// Locks the stripe of `key`
+    pub fn stripe<T: ?Sized + Hash>(&self, key: &T) -> MutexGuard<'_, S> {
+        let mut hasher = H::default();
+        hasher.write_u64(STRIPE_SALT);
+        key.hash(&mut hasher);
+        // multiply-shift range reduction of the top 32 bits
+        let index = ((hasher.finish() >> 32) * self.stripes.len() as u64) >> 32;
+        self.stripes[index as usize].lock().unwrap()
+    }
+
+    /// Locks the stripes one after the other
+    pub fn iter(&self) -> impl Iterator<Item = MutexGuard<'_, S>> + '_ {
+        self.stripes.iter().map(|stripe| stripe.lock().unwrap())
+    }
+
+    /// Number of stripes
+    pub fn num_stripes(&self) -> usize {
+        self.stripes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Striped;
+    use std::collections::HashMap;
+    use std::thread;
+
+    #[test]
+    fn test_concurrent_increment() {
+        let striped: Striped<HashMap<u32, u32>> = Striped::new(8, HashMap::new);
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for key in 0..10_000_u32 {
+                        *striped.stripe(&key).entry(key).or_insert(0) += 1;
+                    }
+                });
+            }
+        });
+        assert!((0..10_000_u32).all(|key| striped.stripe(&key)[&key] == 4));
+        // every stripe holds a fair share of the keys
+        assert!(striped.iter().all(|stripe| stripe.len() > 1000));
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::{Mutex, MutexGuard};
+
+/// Per-thread sketches merged into a shared one. Each thread updates a private copy of an empty sketch without
+/// any synchronization, and adds it to the shared sketch (then clears it) every `merge_interval` items and when
+/// its part of the stream ends. Queries of the shared sketch miss the updates that were not merged yet.
+#[derive(Debug)]
+pub struct ThreadLocalMerge<S> {
+    shared: Mutex<S>,
+    empty: S,
+    merge_interval: usize,
+}
+
+impl<S: Clone> ThreadLocalMerge<S> {
+    /// Starts from the empty sketch `empty`, which is copied for each thread
+    pub fn new(empty: S, merge_interval: usize) -> Self {
+        assert!(merge_interval > 0);
+        Self {
+            shared: Mutex::new(empty.clone()),
+            empty,
+            merge_interval,
+        }
+    }
+
+    /// A private (empty) sketch for a thread
+    pub fn local(&self) -> S {
+        self.empty.clone()
+    }
+
+    /// Number of items a thread inserts between merges
+    pub fn merge_interval(&self) -> usize {
+        self.merge_interval
+    }
+
+    /// Locks the shared sketch
+    pub fn shared(&self) -> MutexGuard<'_, S> {
+        self.shared.lock().unwrap()
+    }
+}
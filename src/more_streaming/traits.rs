@@ -1,19 +1,46 @@
-use crate::{FlowId,NitroHash,SpaceSaving,NitroCMS,CuckooCountingFilter,NitroCuckoo,FACS};
+use crate::{FlowId,NitroHash,SpaceSaving,NitroCMS,NestedNitroCMS,CuckooCountingFilter,NitroCuckoo,FACS};
+use crate::more_streaming::adaptive::AdaptiveRate;
 use amadeus_streaming::CountMinSketch;
 use crate::Hasher;
+use crate::more_streaming::hashing::SketchHasher;
+use crate::more_streaming::atomic_nitro_cms::AtomicNitroCMS;
+use crate::more_streaming::striped::Striped;
+use crate::more_streaming::thread_local_merge::ThreadLocalMerge;
+use crate::more_streaming::fp_dash::FingerprintDash;
+use crate::more_streaming::count_sketch::CountSketch;
+use crate::more_streaming::facs::{BackgroundFACS, CuckooWindow};
+use crate::more_streaming::wcss::WCSS;
+use crate::more_streaming::sliding_cms::SlidingNitroCMS;
+use crate::more_streaming::decay::SCALE_BITS;
+use crate::more_streaming::decayed_cms::DecayedNitroCMS;
+use crate::more_streaming::decayed_space_saving::DecayedSpaceSaving;
+use crate::more_streaming::change_detection::DifferenceSketch;
+use crate::more_streaming::reversible_sketch::ReversibleSketch;
+use crate::more_streaming::hyperloglog::HyperLogLog;
+use dashmap::DashMap;
 use std::collections::HashMap;
+use std::hash::BuildHasher;
 use std::mem::size_of;
+use std::hint::black_box;
+use crate::more_streaming::{interval_error_bound, sketch_bound_failure, sketch_error_bound};
 
 /// Increment an item's count (by 1)
 pub trait ItemIncrement {
 	fn item_increment(&mut self,id: FlowId);
+	/// Increment the count of each item in a batch. The default increments the items one by one,
+	/// structures that can hash the whole batch first and prefetch its counters override it.
+	fn item_increment_batch(&mut self,ids: &[FlowId]) {
+		for id in ids {
+			self.item_increment(*id);
+		}
+	}
 }
-impl ItemIncrement for NitroHash<FlowId,u32> {
+impl <S: BuildHasher + Default>ItemIncrement for NitroHash<FlowId,u32,S> {
 	fn item_increment(&mut self,id: FlowId) {
 		self.insert(id);
 	}
 }
-impl ItemIncrement for SpaceSaving<FlowId,u32> {
+impl <S: BuildHasher + Default>ItemIncrement for SpaceSaving<FlowId,u32,S> {
 	fn item_increment(&mut self,id: FlowId) {
 		self.insert(id);
 	}
@@ -23,12 +50,20 @@ impl ItemIncrement for CountMinSketch<FlowId,u32> {
 		self.push(&id,&1);
 	}
 }
-impl ItemIncrement for NitroCMS<FlowId,u32> {
+impl <H: SketchHasher>ItemIncrement for NitroCMS<FlowId,u32,H> {
+	fn item_increment(&mut self,id: FlowId) {
+		self.push(&id,&1);
+	}
+	fn item_increment_batch(&mut self,ids: &[FlowId]) {
+		self.push_batch(ids,&1);
+	}
+}
+impl <H: SketchHasher>ItemIncrement for NestedNitroCMS<FlowId,u32,H> {
 	fn item_increment(&mut self,id: FlowId) {
 		self.push(&id,&1);
 	}
 }
-impl ItemIncrement for HashMap<FlowId,u32> {
+impl <S: BuildHasher>ItemIncrement for HashMap<FlowId,u32,S> {
 	fn item_increment(&mut self,id: FlowId) {
 		if let Some(count) = self.get_mut(&id) {
 			*count+=1;
@@ -37,43 +72,130 @@ impl ItemIncrement for HashMap<FlowId,u32> {
 		}
 	}
 }
-impl <H>ItemIncrement for CuckooCountingFilter<H> 
+impl <H, const B: usize>ItemIncrement for CuckooCountingFilter<H, B> 
 where H:Hasher + Default,
 {
 	fn item_increment(&mut self,id: FlowId) {
 		self.add(&id).unwrap();
 	}
+	fn item_increment_batch(&mut self,ids: &[FlowId]) {
+		self.add_batch(ids).unwrap();
+	}
 }
-impl <H>ItemIncrement for NitroCuckoo<H> 
+impl <H, const B: usize>ItemIncrement for NitroCuckoo<H, B> 
 where H:Hasher + Default,
 {
 	fn item_increment(&mut self,id: FlowId) {
 		self.add(&id).unwrap();
 	}
+	fn item_increment_batch(&mut self,ids: &[FlowId]) {
+		self.add_batch(ids).unwrap();
+	}
+}
+impl <W: FacsWindow, P: FacsPermanent>ItemIncrement for FACS<W, P> {
+	fn item_increment(&mut self,id: FlowId) {
+		self.insert(id);
+	}
+}
+impl <W: FacsWindow + Send + Sync + 'static, P: FacsPermanent + Send + 'static>ItemIncrement for BackgroundFACS<W, P> {
+	fn item_increment(&mut self,id: FlowId) {
+		self.insert(id);
+	}
+}
+impl <H: Hasher + Default>ItemIncrement for CuckooWindow<H> {
+	fn item_increment(&mut self,id: FlowId) {
+		self.add(id).unwrap();
+	}
+}
+impl <H: Hasher + Default>ItemIncrement for FingerprintDash<H> {
+	fn item_increment(&mut self,id: FlowId) {
+		self.insert(&id);
+	}
 }
-impl ItemIncrement for FACS {
+impl <S: BuildHasher + Default>ItemIncrement for WCSS<FlowId,S> {
 	fn item_increment(&mut self,id: FlowId) {
 		self.insert(id);
 	}
 }
+impl <H: SketchHasher>ItemIncrement for SlidingNitroCMS<FlowId,H> {
+	fn item_increment(&mut self,id: FlowId) {
+		self.push(&id,1);
+	}
+}
+impl <C: DecayCounter, H: SketchHasher>ItemIncrement for DecayedNitroCMS<FlowId,C,H> {
+	fn item_increment(&mut self,id: FlowId) {
+		self.push(&id);
+	}
+}
+impl <S: BuildHasher + Default>ItemIncrement for DecayedSpaceSaving<FlowId,S> {
+	fn item_increment(&mut self,id: FlowId) {
+		self.insert(id);
+	}
+}
+impl <H: SketchHasher>ItemIncrement for CountSketch<FlowId,H> {
+	fn item_increment(&mut self,id: FlowId) {
+		self.push(&id,1);
+	}
+}
+impl <H: SketchHasher>ItemIncrement for ReversibleSketch<H> {
+	fn item_increment(&mut self,id: FlowId) {
+		self.push(u64::from(id),1);
+	}
+}
+
+/// Increment an item's count with its arrival time, for the structures whose counts depend on time (the decayed ones)
+pub trait TimedIncrement {
+	fn timed_increment(&mut self,id: FlowId,time: f64);
+}
+impl <C: DecayCounter, H: SketchHasher>TimedIncrement for DecayedNitroCMS<FlowId,C,H> {
+	fn timed_increment(&mut self,id: FlowId,time: f64) {
+		self.push_at(&id,time);
+	}
+}
+impl <S: BuildHasher + Default>TimedIncrement for DecayedSpaceSaving<FlowId,S> {
+	fn timed_increment(&mut self,id: FlowId,time: f64) {
+		self.insert_at(id,time);
+	}
+}
 
 
 /// Query for an item's frequency
 pub trait ItemQuery {
 	type Item;
 	fn item_query(&self,id: FlowId) -> Self::Item;
+	/// The items stored by structures that keep their keys; sketches return None, and are queried with the flows of the trace
+	fn stored_items(&self) -> Option<Vec<FlowId>> {
+		None
+	}
+	/// The estimated number of distinct items, for the structures that estimate it
+	fn cardinality(&self) -> Option<f64> {
+		None
+	}
 }
-impl ItemQuery for NitroHash<FlowId,u32> {
+impl <S: BuildHasher + Default>ItemQuery for NitroHash<FlowId,u32,S> {
 	type Item = u32;
 	fn item_query(&self,id: FlowId) -> u32 {
 		return self.get(id)
 	}
+	fn stored_items(&self) -> Option<Vec<FlowId>> {
+		Some(self.iter().map(|(id, _)| *id).collect())
+	}
+	// once sampling, the flows that were never sampled are missing, and scaling by 1/p would overcount the large flows
+	fn cardinality(&self) -> Option<f64> {
+		if self.sample_prob() < 1.0 {
+			return None;
+		}
+		Some(self.len() as f64)
+	}
 }
-impl ItemQuery for SpaceSaving<FlowId,u32> {
+impl <S: BuildHasher + Default>ItemQuery for SpaceSaving<FlowId,u32,S> {
 	type Item = u32;
 	fn item_query(&self,id: FlowId) -> u32 {
 		return self.get(id)
 	}
+	fn stored_items(&self) -> Option<Vec<FlowId>> {
+		Some(self.iter().map(|(id, _)| *id).collect())
+	}
 }
 impl ItemQuery for CountMinSketch<FlowId,u32> {
 	type Item = u32;
@@ -81,52 +203,283 @@ impl ItemQuery for CountMinSketch<FlowId,u32> {
 		return self.get(&id)
 	}
 }
-impl ItemQuery for NitroCMS<FlowId,u32> {
+impl <H: SketchHasher>ItemQuery for NitroCMS<FlowId,u32,H> {
 	type Item = u32;
 	fn item_query(&self,id: FlowId) -> u32 {
 		return self.get(&id)
 	}
+	// the zero counters of a sampling sketch miss the flows that were never sampled
+	fn cardinality(&self) -> Option<f64> {
+		if self.sample_prob() < 1.0 {
+			return None;
+		}
+		Some(NitroCMS::cardinality(self))
+	}
 }
-impl ItemQuery for HashMap<FlowId,u32> {
+impl <H: SketchHasher>ItemQuery for NestedNitroCMS<FlowId,u32,H> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		self.get(&id)
+	}
+}
+impl <S: BuildHasher>ItemQuery for HashMap<FlowId,u32,S> {
 	type Item = u32;
 	fn item_query(&self,id: FlowId) -> u32 {
 		return *self.get(&id).unwrap_or(&0);
 	}
+	fn stored_items(&self) -> Option<Vec<FlowId>> {
+		Some(self.keys().copied().collect())
+	}
+	fn cardinality(&self) -> Option<f64> {
+		Some(self.len() as f64)
+	}
 }
-impl <H>ItemQuery for CuckooCountingFilter<H> 
+impl <H, const B: usize>ItemQuery for CuckooCountingFilter<H, B> 
 where H:Hasher + Default,
 {
 	type Item = u32;
 	fn item_query(&self,id: FlowId) -> u32 {
 		return self.get(&id);
 	}
+	fn cardinality(&self) -> Option<f64> {
+		Some(CuckooCountingFilter::cardinality(self))
+	}
 }
-impl <H>ItemQuery for NitroCuckoo<H> 
+impl <H, const B: usize>ItemQuery for NitroCuckoo<H, B> 
 where H:Hasher + Default,
 {
 	type Item = u32;
 	fn item_query(&self,id: FlowId) -> u32 {
 		return self.get(&id);
 	}
+	// the fingerprints of a sampling filter miss the flows that were never sampled
+	fn cardinality(&self) -> Option<f64> {
+		if self.sample_prob() < 1.0 {
+			return None;
+		}
+		Some(NitroCuckoo::cardinality(self))
+	}
+}
+impl <W: FacsWindow, P: FacsPermanent>ItemQuery for FACS<W, P> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		self.get(id)
+	}
+}
+impl <W: FacsWindow + Send + Sync + 'static, P: FacsPermanent + Send + 'static>ItemQuery for BackgroundFACS<W, P> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		self.get(id)
+	}
 }
-impl ItemQuery for FACS {
+impl <H: Hasher + Default>ItemQuery for CuckooWindow<H> {
 	type Item = u32;
 	fn item_query(&self,id: FlowId) -> u32 {
 		return self.get(id);
 	}
 }
+/// Negative estimates are reported as 0
+impl <H: SketchHasher>ItemQuery for CountSketch<FlowId,H> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		u32::try_from(self.estimate(&id).max(0)).unwrap_or(u32::MAX)
+	}
+}
+impl <H: Hasher + Default>ItemQuery for FingerprintDash<H> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		self.get(&id)
+	}
+}
+impl <S: BuildHasher + Default>ItemQuery for WCSS<FlowId,S> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		self.get(id)
+	}
+	fn stored_items(&self) -> Option<Vec<FlowId>> {
+		Some(self.keys())
+	}
+}
+impl <H: SketchHasher>ItemQuery for SlidingNitroCMS<FlowId,H> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		self.get(&id)
+	}
+}
+/// The decayed counts are rounded to the nearest integer
+impl <C: DecayCounter, H: SketchHasher>ItemQuery for DecayedNitroCMS<FlowId,C,H> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		self.estimate(&id).round() as u32
+	}
+}
+impl <S: BuildHasher + Default>ItemQuery for DecayedSpaceSaving<FlowId,S> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		self.get(id).round() as u32
+	}
+	fn stored_items(&self) -> Option<Vec<FlowId>> {
+		Some(self.iter().map(|(id, _)| *id).collect())
+	}
+}
+
+/// Query for an item's unrounded frequency estimate and, for sampling based structures,
+/// the confidence interval (holding with probability 1-delta) around it
+pub trait ItemEstimate {
+	fn item_estimate(&self, id: FlowId) -> f64;
+	fn item_confidence_interval(&self, _id: FlowId, _delta: f64) -> Option<(f64, f64)> {
+		None
+	}
+	/// The error |estimate - count| the structure promises for an item after `volume` arrivals, for the
+	/// tolerance `error` and the failure probability `delta` it was configured with, or None for structures
+	/// without a theoretical bound
+	fn item_error_bound(&self, _id: FlowId, _volume: u64, _error: f64, _delta: f64) -> Option<f64> {
+		None
+	}
+	/// The fraction of the items that may exceed `item_error_bound` for the failure probability `delta`
+	fn item_bound_failure(&self, delta: f64) -> f64 {
+		delta
+	}
+}
+impl <S: BuildHasher + Default>ItemEstimate for NitroHash<FlowId,u32,S> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		self.estimate_f64(id)
+	}
+	fn item_confidence_interval(&self, id: FlowId, delta: f64) -> Option<(f64, f64)> {
+		Some(self.confidence_interval(id, delta))
+	}
+	fn item_error_bound(&self, id: FlowId, _volume: u64, _error: f64, delta: f64) -> Option<f64> {
+		Some(interval_error_bound(self.estimate_f64(id), self.confidence_interval(id, delta)))
+	}
+}
+impl <S: BuildHasher + Default>ItemEstimate for SpaceSaving<FlowId,u32,S> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		f64::from(self.get(id))
+	}
+	// the minimum counter never exceeds volume/capacity, RAP only bounds the expected error
+	fn item_error_bound(&self, _id: FlowId, volume: u64, _error: f64, _delta: f64) -> Option<f64> {
+		if self.is_rap() {
+			return None;
+		}
+		Some(volume as f64 / self.capacity() as f64)
+	}
+	fn item_bound_failure(&self, _delta: f64) -> f64 {
+		0.0
+	}
+}
+impl <H: SketchHasher>ItemEstimate for NitroCMS<FlowId,u32,H> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		self.estimate_f64(&id)
+	}
+	fn item_confidence_interval(&self, id: FlowId, delta: f64) -> Option<(f64, f64)> {
+		Some(self.confidence_interval(&id, delta))
+	}
+	// the sampled conservative update of rows biases the estimates down
+	fn item_error_bound(&self, id: FlowId, volume: u64, error: f64, delta: f64) -> Option<f64> {
+		if self.is_row_sampled_conservative() {
+			return None;
+		}
+		Some(sketch_error_bound(error, volume) + interval_error_bound(self.estimate_f64(&id), self.confidence_interval(&id, delta)))
+	}
+	// a union bound over the rows and the sampling interval
+	fn item_bound_failure(&self, delta: f64) -> f64 {
+		sketch_bound_failure(self.k_num()) + if self.sample_prob() < 1.0 { delta } else { 0.0 }
+	}
+}
+impl <S: BuildHasher>ItemEstimate for HashMap<FlowId,u32,S> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		f64::from(self.item_query(id))
+	}
+	fn item_error_bound(&self, _id: FlowId, _volume: u64, _error: f64, _delta: f64) -> Option<f64> {
+		Some(0.0)
+	}
+	fn item_bound_failure(&self, _delta: f64) -> f64 {
+		0.0
+	}
+}
+impl <H, const B: usize>ItemEstimate for CuckooCountingFilter<H, B> 
+where H:Hasher + Default,
+{
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		f64::from(self.get(&id))
+	}
+}
+impl <H, const B: usize>ItemEstimate for NitroCuckoo<H, B> 
+where H:Hasher + Default,
+{
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		self.estimate_f64(&id)
+	}
+	fn item_confidence_interval(&self, id: FlowId, delta: f64) -> Option<(f64, f64)> {
+		Some(self.confidence_interval(&id, delta))
+	}
+	fn item_error_bound(&self, id: FlowId, _volume: u64, _error: f64, delta: f64) -> Option<f64> {
+		Some(interval_error_bound(self.estimate_f64(&id), self.confidence_interval(&id, delta)))
+	}
+}
+impl <W: FacsWindow, P: FacsPermanent>ItemEstimate for FACS<W, P> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		f64::from(self.get(id))
+	}
+}
+impl <W: FacsWindow + Send + Sync + 'static, P: FacsPermanent + Send + 'static>ItemEstimate for BackgroundFACS<W, P> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		f64::from(self.get(id))
+	}
+}
+impl <H: Hasher + Default>ItemEstimate for FingerprintDash<H> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		f64::from(self.get(&id))
+	}
+}
+impl <S: BuildHasher + Default>ItemEstimate for WCSS<FlowId,S> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		f64::from(self.get(id))
+	}
+}
+/// The panes may cover up to a pane of items older than the window
+impl <H: SketchHasher>ItemEstimate for SlidingNitroCMS<FlowId,H> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		self.estimate_f64(&id)
+	}
+	fn item_confidence_interval(&self, id: FlowId, delta: f64) -> Option<(f64, f64)> {
+		Some(self.confidence_interval(&id, delta))
+	}
+	fn item_error_bound(&self, id: FlowId, volume: u64, error: f64, delta: f64) -> Option<f64> {
+		if self.is_row_sampled_conservative() {
+			return None;
+		}
+		let covered = self.covered_items() as u64;
+		Some(sketch_error_bound(error, covered) + covered.saturating_sub(volume) as f64
+			+ interval_error_bound(self.estimate_f64(&id), self.confidence_interval(&id, delta)))
+	}
+	// a union bound over the panes, or Markov's inequality on the sum of their overestimates, and the sampling interval
+	fn item_bound_failure(&self, delta: f64) -> f64 {
+		(self.pane_count() as f64 * sketch_bound_failure(self.k_num())).min(0.5) + if self.sample_prob() < 1.0 { delta } else { 0.0 }
+	}
+}
+impl <C: DecayCounter, H: SketchHasher>ItemEstimate for DecayedNitroCMS<FlowId,C,H> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		self.estimate(&id)
+	}
+}
+impl <S: BuildHasher + Default>ItemEstimate for DecayedSpaceSaving<FlowId,S> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		self.get(id)
+	}
+}
 
 /// Print memory usage info
 pub trait PrintMemoryInfo {
 	fn print_memory_info(&self) -> ();
 }
-impl PrintMemoryInfo for NitroHash<FlowId,u32> {
+impl <S: BuildHasher + Default>PrintMemoryInfo for NitroHash<FlowId,u32,S> {
 	fn print_memory_info(&self) -> () {
 		println!("Total memory: {}", self.capacity() * (size_of::<FlowId>() + size_of::<u32>()));
 		println!("Number of items: {} consuming {} space", self.len(), self.len() * (size_of::<FlowId>() + size_of::<u32>()));
 	}
 }
-impl PrintMemoryInfo for SpaceSaving<FlowId,u32> {
+impl <S: BuildHasher + Default>PrintMemoryInfo for SpaceSaving<FlowId,u32,S> {
 	fn print_memory_info(&self) -> () {
 		println!("Total memory: {}", self.capacity() * (size_of::<FlowId>() + size_of::<u32>()));
 	}
@@ -137,41 +490,603 @@ impl PrintMemoryInfo for CountMinSketch<FlowId,u32> {
 		println!("Total memory: {}", 0_usize); // TODO
 	}
 }
-impl PrintMemoryInfo for NitroCMS<FlowId,u32> {
+impl <H: SketchHasher>PrintMemoryInfo for NitroCMS<FlowId,u32,H> {
 	fn print_memory_info(&self) -> () {
 		println!("Total memory: {}", self.estimate_memory_size());
 	}
 }
-impl PrintMemoryInfo for HashMap<FlowId,u32> {
+impl <H: SketchHasher>PrintMemoryInfo for HyperLogLog<FlowId,H> {
+	fn print_memory_info(&self) {
+		println!("Total memory: {}", self.estimate_memory_size());
+	}
+}
+impl <S: BuildHasher>PrintMemoryInfo for HashMap<FlowId,u32,S> {
 	fn print_memory_info(&self) -> () {
 		println!("Total memory: {}", self.capacity() * (size_of::<FlowId>() + size_of::<u32>()));
 		println!("Number of items: {} consuming {} space", self.len(), self.len() * (size_of::<FlowId>() + size_of::<u32>()));
 	}
 }
-impl <H>PrintMemoryInfo for CuckooCountingFilter<H> 
+impl <H, const B: usize>PrintMemoryInfo for CuckooCountingFilter<H, B> 
 where H:Hasher + Default,
 {
 	fn print_memory_info(&self) -> () {
-		println!("Total memory: {}", self.capacity() * (size_of::<u32>() + size_of::<u8>())); // TODO - replace with fingerprint_size
+		println!("Total memory: {}", self.slots() * (size_of::<u32>() + size_of::<u8>())); // TODO - replace with fingerprint_size
 		println!("Number of items: {} consuming {} space", self.len(), self.len() * (size_of::<FlowId>() + size_of::<u8>())); // TODO - replace with fingerprint_size	
 	}
 }
-impl <H>PrintMemoryInfo for NitroCuckoo<H> 
+impl <H, const B: usize>PrintMemoryInfo for NitroCuckoo<H, B> 
 where H:Hasher + Default,
 {
 	fn print_memory_info(&self) -> () {
-		println!("Total memory: {}", self.capacity() * (size_of::<u32>() + size_of::<u8>())); // TODO - replace with fingerprint_size
+		println!("Total memory: {}", self.slots() * (size_of::<u32>() + size_of::<u8>())); // TODO - replace with fingerprint_size
 		println!("Number of items: {} consuming {} space", self.len(), self.len() * (size_of::<FlowId>() + size_of::<u8>())); // TODO - replace with fingerprint_size
 	}
 }
-impl PrintMemoryInfo for FACS {
+/// The memory of both the window and the permanent sketches
+impl <W: FacsWindow, P: FacsPermanent>PrintMemoryInfo for FACS<W, P> {
 	fn print_memory_info(&self) -> () {
+		println!("Total memory: {}", self.estimate_window_memory_size() + self.estimate_permanent_memory_size());
+		println!("Window memory: {}", self.estimate_window_memory_size());
+		println!("Permanent memory: {}", self.estimate_permanent_memory_size());
+	}
+}
+/// Both window sketches (the live and the in-flight or spare one) are counted
+impl <W: FacsWindow + Send + Sync + 'static, P: FacsPermanent + Send + 'static>PrintMemoryInfo for BackgroundFACS<W, P> {
+	fn print_memory_info(&self) {
+		println!("Total memory: {}", self.estimate_window_memory_size() + self.estimate_permanent_memory_size());
+		println!("Window memory: {}", self.estimate_window_memory_size());
+		println!("Permanent memory: {}", self.estimate_permanent_memory_size());
+	}
+}
+/// Fingerprints are counted as their size rounded up to whole bytes
+impl <H: Hasher + Default>PrintMemoryInfo for FingerprintDash<H> {
+	fn print_memory_info(&self) {
+		let entry_size = usize::from(self.fp_size()).div_ceil(8) + size_of::<u32>();
+		println!("Total memory: {}", self.capacity() * entry_size);
+		println!("Number of items: {} consuming {} space", self.len(), self.len() * entry_size);
+		println!("Rejected items: {}", self.rejected());
+	}
+}
+impl <S: BuildHasher + Default>PrintMemoryInfo for WCSS<FlowId,S> {
+	fn print_memory_info(&self) {
+		println!("Total memory: {}", self.capacity() * (size_of::<FlowId>() + size_of::<u32>()) + self.overflow_count() * (size_of::<FlowId>() + size_of::<u64>()));
+	}
+}
+impl <H: SketchHasher>PrintMemoryInfo for SlidingNitroCMS<FlowId,H> {
+	fn print_memory_info(&self) {
+		println!("Total memory: {}", self.estimate_memory_size());
+	}
+}
+impl <C: DecayCounter, H: SketchHasher>PrintMemoryInfo for DecayedNitroCMS<FlowId,C,H> {
+	fn print_memory_info(&self) {
+		println!("Total memory: {}", self.estimate_memory_size());
+	}
+}
+impl <S: BuildHasher + Default>PrintMemoryInfo for DecayedSpaceSaving<FlowId,S> {
+	fn print_memory_info(&self) {
+		println!("Total memory: {}", self.capacity() * (size_of::<FlowId>() + size_of::<u64>()));
+	}
+}
+
+
+
+/// Print the sampling probability reached by an adaptive sampling rate, and where an AlwaysCorrect NitroCMS
+/// switched to sampling (nothing for other structures)
+pub trait PrintSamplingInfo {
+	fn print_sampling_info(&self) {}
+}
+impl <S: BuildHasher + Default>PrintSamplingInfo for NitroHash<FlowId,u32,S> {
+	fn print_sampling_info(&self) {
+		print_adaptive_rate(self.adaptive_rate(), self.sample_prob());
+	}
+}
+impl <S: BuildHasher + Default>PrintSamplingInfo for SpaceSaving<FlowId,u32,S> {}
+impl <H: SketchHasher>PrintSamplingInfo for NitroCMS<FlowId,u32,H> {
+	fn print_sampling_info(&self) {
+		print_adaptive_rate(self.adaptive_rate(), self.sample_prob());
+		if self.is_always_correct() {
+			match self.switch_point() {
+				Some(item) => println!("Switched to sampling at item: {}", item),
+				None => println!("Switched to sampling at item: never"),
+			}
+		}
+	}
+}
+impl <S: BuildHasher>PrintSamplingInfo for HashMap<FlowId,u32,S> {}
+impl <H, const B: usize>PrintSamplingInfo for CuckooCountingFilter<H, B> 
+where H:Hasher + Default,
+{}
+impl <H, const B: usize>PrintSamplingInfo for NitroCuckoo<H, B> 
+where H:Hasher + Default,
+{
+	fn print_sampling_info(&self) {
+		print_adaptive_rate(self.adaptive_rate(), self.sample_prob());
+	}
+}
+impl <W: FacsWindow, P: FacsPermanent>PrintSamplingInfo for FACS<W, P> {}
+impl <W: FacsWindow + Send + Sync + 'static, P: FacsPermanent + Send + 'static>PrintSamplingInfo for BackgroundFACS<W, P> {}
+impl <H: Hasher + Default>PrintSamplingInfo for FingerprintDash<H> {}
+impl <S: BuildHasher + Default>PrintSamplingInfo for WCSS<FlowId,S> {}
+impl <H: SketchHasher>PrintSamplingInfo for SlidingNitroCMS<FlowId,H> {}
+impl <C: DecayCounter, H: SketchHasher>PrintSamplingInfo for DecayedNitroCMS<FlowId,C,H> {}
+impl <S: BuildHasher + Default>PrintSamplingInfo for DecayedSpaceSaving<FlowId,S> {}
+
+fn print_adaptive_rate(rate: Option<&AdaptiveRate>, sample_prob: f64) {
+	if let Some(rate) = rate {
+		println!("Sampling probability: {}", sample_prob);
+		println!("Sampling rate changes: {}", rate.changes());
+	}
+}
+
+/// List the current contents of a structure as (label, estimated count) pairs.
+/// Key-storing structures label entries by their key, while the filters and sketches
+/// label them by their location (bucket and fingerprint, or row and column)
+pub trait DumpContents {
+	fn dump_contents(&self) -> Vec<(String, u32)>;
+}
+impl <S: BuildHasher + Default>DumpContents for NitroHash<FlowId,u32,S> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(id, val)| (id.to_string(), val)).collect()
+	}
+}
+impl <S: BuildHasher + Default>DumpContents for SpaceSaving<FlowId,u32,S> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(id, val)| (id.to_string(), val)).collect()
+	}
+}
+impl <H: SketchHasher>DumpContents for NitroCMS<FlowId,u32,H> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(row, column, val)| (format!("{} {}", row, column), *val)).collect()
+	}
+}
+impl <S: BuildHasher>DumpContents for HashMap<FlowId,u32,S> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(id, val)| (id.to_string(), *val)).collect()
+	}
+}
+impl <H, const B: usize>DumpContents for CuckooCountingFilter<H, B> 
+where H:Hasher + Default,
+{
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(bucket, fp, val)| (format!("{} {:02x?}", bucket, fp.data), val)).collect()
+	}
+}
+impl <H, const B: usize>DumpContents for NitroCuckoo<H, B> 
+where H:Hasher + Default,
+{
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(bucket, fp, val)| (format!("{} {:02x?}", bucket, fp.data), val)).collect()
+	}
+}
+impl <W: FacsWindow, P: FacsPermanent>DumpContents for FACS<W, P> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(id, val)| (id.to_string(), val)).collect()
+	}
+}
+impl <W: FacsWindow + Send + Sync + 'static, P: FacsPermanent + Send + 'static>DumpContents for BackgroundFACS<W, P> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(id, val)| (id.to_string(), val)).collect()
+	}
+}
+impl <H: Hasher + Default>DumpContents for FingerprintDash<H> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(fp, val)| (format!("{:x}", fp), val)).collect()
+	}
+}
+impl <S: BuildHasher + Default>DumpContents for WCSS<FlowId,S> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.keys().into_iter().map(|id| (id.to_string(), self.get(id))).collect()
+	}
+}
+impl <H: SketchHasher>DumpContents for SlidingNitroCMS<FlowId,H> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(pane, row, column, val)| (format!("{} {} {}", pane, row, column), *val)).collect()
+	}
+}
+impl <C: DecayCounter, H: SketchHasher>DumpContents for DecayedNitroCMS<FlowId,C,H> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(row, column, val)| (format!("{} {}", row, column), val.round() as u32)).collect()
+	}
+}
+impl <S: BuildHasher + Default>DumpContents for DecayedSpaceSaving<FlowId,S> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(id, val)| (id.to_string(), val.round() as u32)).collect()
+	}
+}
+
+/// The window sketch of FACS: counts the items of the current window, and hands their counts over at its end
+pub trait FacsWindow: ItemIncrement + ItemQuery<Item=u32> {
+	/// Pass the count of each item of the window to `flush`, and empty the window
+	fn flush(&mut self,flush: &mut dyn FnMut(FlowId, u32));
+	/// The items of the current window
+	fn window_keys(&self) -> Vec<FlowId>;
+	/// The memory used, counted as in `PrintMemoryInfo`
+	fn memory_size(&self) -> usize;
+}
+impl <S: BuildHasher>FacsWindow for HashMap<FlowId,u32,S> {
+	fn flush(&mut self,flush: &mut dyn FnMut(FlowId, u32)) {
+		self.drain().for_each(|(id, val)| flush(id, val));
+	}
+	fn window_keys(&self) -> Vec<FlowId> {
+		self.keys().copied().collect()
+	}
+	fn memory_size(&self) -> usize {
+		self.capacity() * (size_of::<FlowId>() + size_of::<u32>())
+	}
+}
+/// Sampled counts are handed over scaled by 1/p
+impl <S: BuildHasher + Default>FacsWindow for NitroHash<FlowId,u32,S> {
+	fn flush(&mut self,flush: &mut dyn FnMut(FlowId, u32)) {
+		self.iter().for_each(|(id, val)| flush(*id, val));
+		self.clear();
+	}
+	fn window_keys(&self) -> Vec<FlowId> {
+		self.iter().map(|(id, _)| *id).collect()
+	}
+	fn memory_size(&self) -> usize {
+		self.capacity() * (size_of::<FlowId>() + size_of::<u32>())
+	}
+}
+impl <H: Hasher + Default>FacsWindow for CuckooWindow<H> {
+	fn flush(&mut self,flush: &mut dyn FnMut(FlowId, u32)) {
+		CuckooWindow::flush(self, flush);
+	}
+	fn window_keys(&self) -> Vec<FlowId> {
+		self.keys().to_vec()
+	}
+	fn memory_size(&self) -> usize {
+		CuckooWindow::memory_size(self)
+	}
+}
+
+/// The permanent sketch of FACS, accumulating the counts of the windows
+pub trait FacsPermanent: ItemQuery<Item=u32> {
+	fn add(&mut self,id: FlowId,count: u32);
+	/// The memory used, counted as in `PrintMemoryInfo`
+	fn memory_size(&self) -> usize;
+}
+impl <H: SketchHasher>FacsPermanent for NitroCMS<FlowId,u32,H> {
+	fn add(&mut self,id: FlowId,count: u32) {
+		self.push(&id,&count);
+	}
+	fn memory_size(&self) -> usize {
+		self.estimate_memory_size()
+	}
+}
+impl <H: SketchHasher>FacsPermanent for CountSketch<FlowId,H> {
+	fn add(&mut self,id: FlowId,count: u32) {
+		self.push(&id,i64::from(count));
+	}
+	fn memory_size(&self) -> usize {
+		self.estimate_memory_size()
+	}
+}
+impl <S: BuildHasher + Default>FacsPermanent for SpaceSaving<FlowId,u32,S> {
+	fn add(&mut self,id: FlowId,count: u32) {
+		self.insert_weighted(id,count);
+	}
+	fn memory_size(&self) -> usize {
+		self.capacity() * (size_of::<FlowId>() + size_of::<u32>())
+	}
+}
+impl <H: SketchHasher>FacsPermanent for SlidingNitroCMS<FlowId,H> {
+	fn add(&mut self,id: FlowId,count: u32) {
+		self.push(&id,count);
+	}
+	fn memory_size(&self) -> usize {
+		self.estimate_memory_size()
+	}
+}
+
+/// Increment and query items from several threads at once
+pub trait ConcurrentIncrement: Sync {
+	fn concurrent_increment(&self,id: FlowId);
+	fn concurrent_query(&self,id: FlowId) -> u32;
+	/// Insert the part of the stream of one thread, querying each item right after inserting it if `query` is set.
+	/// The default inserts the items one by one, thread-local sketches override it to merge periodically.
+	fn concurrent_insert_part(&self,part: &[FlowId],query: bool) {
+		for id in part {
+			self.concurrent_increment(*id);
+			if query {
+				black_box(self.concurrent_query(*id));
+			}
+		}
+	}
+}
+impl <H: SketchHasher>ConcurrentIncrement for AtomicNitroCMS<FlowId,H> {
+	fn concurrent_increment(&self,id: FlowId) {
+		self.push(&id);
+	}
+	fn concurrent_query(&self,id: FlowId) -> u32 {
+		self.get(&id)
+	}
+}
+impl <S: BuildHasher + Clone + Send + Sync>ConcurrentIncrement for DashMap<FlowId,u32,S> {
+	fn concurrent_increment(&self,id: FlowId) {
+		*self.entry(id).or_insert(0) += 1;
+	}
+	fn concurrent_query(&self,id: FlowId) -> u32 {
+		self.get(&id).map_or(0, |count| *count)
+	}
+}
+impl <S, H>ConcurrentIncrement for Striped<S, H>
+where S: ItemIncrement + ItemQuery<Item=u32> + Send, H: Hasher + Default,
+{
+	fn concurrent_increment(&self,id: FlowId) {
+		self.stripe(&id).item_increment(id);
+	}
+	fn concurrent_query(&self,id: FlowId) -> u32 {
+		return self.stripe(&id).item_query(id);
+	}
+}
+impl <S>ConcurrentIncrement for ThreadLocalMerge<S>
+where S: ItemIncrement + ItemQuery<Item=u32> + Merge + Clone + Send + Sync,
+{
+	fn concurrent_increment(&self,id: FlowId) {
+		self.shared().item_increment(id);
+	}
+	fn concurrent_query(&self,id: FlowId) -> u32 {
+		return self.shared().item_query(id);
+	}
+	/// Queries read the private sketch of the thread
+	fn concurrent_insert_part(&self,part: &[FlowId],query: bool) {
+		let mut local = self.local();
+		for chunk in part.chunks(self.merge_interval()) {
+			for id in chunk {
+				local.item_increment(*id);
+				if query {
+					black_box(local.item_query(*id));
+				}
+			}
+			self.shared().merge(&local);
+			local.clear();
+		}
+	}
+}
+
+impl <H: Hasher + Default + Send>ConcurrentIncrement for FingerprintDash<H> {
+	fn concurrent_increment(&self,id: FlowId) {
+		self.insert(&id);
+	}
+	fn concurrent_query(&self,id: FlowId) -> u32 {
+		self.get(&id)
+	}
+}
+
+/// Sketches that can be summed, such as the private sketches of several threads
+pub trait Merge {
+	fn merge(&mut self,other: &Self);
+	fn clear(&mut self);
+}
+impl <H: SketchHasher>Merge for NitroCMS<FlowId,u32,H> {
+	fn merge(&mut self,other: &Self) {
+		NitroCMS::merge(self, other);
+	}
+	fn clear(&mut self) {
+		NitroCMS::clear(self);
+	}
+}
+
+/// Sketches that are linear in the counts (NitroCMS without conservative update, the Count Sketch and the reversible
+/// sketch): the difference of the sketches of two epochs, with the same dimensions and hashes, sketches the changes
+pub trait LinearSketch {
+	type Difference: ChangeEstimate;
+	fn difference(&self,previous: &Self) -> Self::Difference;
+	fn clear(&mut self);
+}
+impl <H: SketchHasher>LinearSketch for NitroCMS<FlowId,u32,H> {
+	type Difference = DifferenceSketch<FlowId,H>;
+	fn difference(&self,previous: &Self) -> Self::Difference {
+		NitroCMS::difference(self, previous)
+	}
+	fn clear(&mut self) {
+		NitroCMS::clear(self);
+	}
+}
+impl <H: SketchHasher>LinearSketch for CountSketch<FlowId,H> {
+	type Difference = DifferenceSketch<FlowId,H>;
+	fn difference(&self,previous: &Self) -> Self::Difference {
+		CountSketch::difference(self, previous)
+	}
+	fn clear(&mut self) {
+		CountSketch::clear(self);
+	}
+}
+impl <H: SketchHasher>LinearSketch for ReversibleSketch<H> {
+	type Difference = Self;
+	fn difference(&self,previous: &Self) -> Self::Difference {
+		ReversibleSketch::difference(self, previous)
+	}
+	fn clear(&mut self) {
+		ReversibleSketch::clear(self);
+	}
+}
+
+/// Estimate the change of an item's count between two epochs
+pub trait ChangeEstimate {
+	fn change_estimate(&self,id: FlowId) -> f64;
+	/// The items whose estimated change reaches `threshold` in absolute value, recovered from the sketch itself,
+	/// or None for sketches that need a key store for their candidate items
+	fn changed_items(&self,_threshold: f64) -> Option<Vec<FlowId>> {
+		None
+	}
+}
+impl <H: SketchHasher>ChangeEstimate for DifferenceSketch<FlowId,H> {
+	fn change_estimate(&self,id: FlowId) -> f64 {
+		self.estimate(&id)
+	}
+}
+impl <H: SketchHasher>ChangeEstimate for ReversibleSketch<H> {
+	fn change_estimate(&self,id: FlowId) -> f64 {
+		self.estimate(u64::from(id))
+	}
+	fn changed_items(&self,threshold: f64) -> Option<Vec<FlowId>> {
+		Some(self.recover(threshold).into_iter().map(FlowId::from).collect())
+	}
+}
+
+impl <H: SketchHasher>ItemQuery for AtomicNitroCMS<FlowId,H> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		self.get(&id)
+	}
+}
+impl <S: BuildHasher + Clone>ItemQuery for DashMap<FlowId,u32,S> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		self.get(&id).map_or(0, |count| *count)
+	}
+	fn stored_items(&self) -> Option<Vec<FlowId>> {
+		Some(self.iter().map(|entry| *entry.key()).collect())
+	}
+	fn cardinality(&self) -> Option<f64> {
+		Some(self.len() as f64)
+	}
+}
+impl <S: ItemQuery<Item=u32>, H: Hasher + Default>ItemQuery for Striped<S, H> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		return self.stripe(&id).item_query(id);
+	}
+	/// The stripes hold disjoint sets of items
+	fn cardinality(&self) -> Option<f64> {
+		self.iter().map(|stripe| stripe.cardinality()).sum()
+	}
+}
+impl <S: ItemQuery<Item=u32> + Clone>ItemQuery for ThreadLocalMerge<S> {
+	type Item = u32;
+	fn item_query(&self,id: FlowId) -> u32 {
+		return self.shared().item_query(id);
+	}
+	fn cardinality(&self) -> Option<f64> {
+		self.shared().cardinality()
+	}
+}
+
+impl <H: SketchHasher>ItemEstimate for AtomicNitroCMS<FlowId,H> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		self.estimate_f64(&id)
+	}
+	fn item_confidence_interval(&self, id: FlowId, delta: f64) -> Option<(f64, f64)> {
+		Some(self.confidence_interval(&id, delta))
+	}
+	fn item_error_bound(&self, id: FlowId, volume: u64, error: f64, delta: f64) -> Option<f64> {
+		Some(sketch_error_bound(error, volume) + interval_error_bound(self.estimate_f64(&id), self.confidence_interval(&id, delta)))
+	}
+	fn item_bound_failure(&self, delta: f64) -> f64 {
+		sketch_bound_failure(self.k_num()) + if self.sample_prob() < 1.0 { delta } else { 0.0 }
+	}
+}
+impl <S: BuildHasher + Clone>ItemEstimate for DashMap<FlowId,u32,S> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		f64::from(self.item_query(id))
+	}
+	fn item_error_bound(&self, _id: FlowId, _volume: u64, _error: f64, _delta: f64) -> Option<f64> {
+		Some(0.0)
+	}
+	fn item_bound_failure(&self, _delta: f64) -> f64 {
+		0.0
+	}
+}
+impl <S: ItemEstimate, H: Hasher + Default>ItemEstimate for Striped<S, H> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		self.stripe(&id).item_estimate(id)
+	}
+	fn item_confidence_interval(&self, id: FlowId, delta: f64) -> Option<(f64, f64)> {
+		self.stripe(&id).item_confidence_interval(id, delta)
+	}
+	// each stripe sees at most the whole volume
+	fn item_error_bound(&self, id: FlowId, volume: u64, error: f64, delta: f64) -> Option<f64> {
+		self.stripe(&id).item_error_bound(id, volume, error, delta)
+	}
+	fn item_bound_failure(&self, delta: f64) -> f64 {
+		self.iter().map(|stripe| stripe.item_bound_failure(delta)).fold(0.0, f64::max)
+	}
+}
+impl <S: ItemEstimate + Clone>ItemEstimate for ThreadLocalMerge<S> {
+	fn item_estimate(&self, id: FlowId) -> f64 {
+		self.shared().item_estimate(id)
+	}
+	fn item_confidence_interval(&self, id: FlowId, delta: f64) -> Option<(f64, f64)> {
+		self.shared().item_confidence_interval(id, delta)
+	}
+	fn item_error_bound(&self, id: FlowId, volume: u64, error: f64, delta: f64) -> Option<f64> {
+		self.shared().item_error_bound(id, volume, error, delta)
+	}
+	fn item_bound_failure(&self, delta: f64) -> f64 {
+		self.shared().item_bound_failure(delta)
+	}
+}
+
+impl <H: SketchHasher>PrintMemoryInfo for AtomicNitroCMS<FlowId,H> {
+	fn print_memory_info(&self) {
+		println!("Total memory: {}", self.estimate_memory_size());
+	}
+}
+impl <S: BuildHasher + Clone>PrintMemoryInfo for DashMap<FlowId,u32,S> {
+	fn print_memory_info(&self) {
 		println!("Total memory: {}", self.capacity() * (size_of::<FlowId>() + size_of::<u32>()));
-		// println!("Number of items: {} consuming {} space", self.len(), self.len() * (size_of::<FlowId>() + size_of::<u32>())); // TODO - does this makes sense?
+		println!("Number of items: {} consuming {} space", self.len(), self.len() * (size_of::<FlowId>() + size_of::<u32>()));
+	}
+}
+impl <H, const B: usize>PrintMemoryInfo for Striped<CuckooCountingFilter<H, B>, H>
+where H:Hasher + Default,
+{
+	fn print_memory_info(&self) {
+		let (slots, len) = self.iter().fold((0, 0), |(slots, len), stripe| (slots + stripe.slots(), len + stripe.len()));
+		println!("Total memory: {}", slots * (size_of::<u32>() + size_of::<u8>())); // TODO - replace with fingerprint_size
+		println!("Number of items: {} consuming {} space", len, len * (size_of::<FlowId>() + size_of::<u8>())); // TODO - replace with fingerprint_size
+	}
+}
+impl <H, const B: usize>PrintMemoryInfo for Striped<NitroCuckoo<H, B>, H>
+where H:Hasher + Default,
+{
+	fn print_memory_info(&self) {
+		let (slots, len) = self.iter().fold((0, 0), |(slots, len), stripe| (slots + stripe.slots(), len + stripe.len()));
+		println!("Total memory: {}", slots * (size_of::<u32>() + size_of::<u8>())); // TODO - replace with fingerprint_size
+		println!("Number of items: {} consuming {} space", len, len * (size_of::<FlowId>() + size_of::<u8>())); // TODO - replace with fingerprint_size
+	}
+}
+/// The memory of the shared sketch (each thread adds a private sketch of the same size while it runs)
+impl <S: PrintMemoryInfo + Clone>PrintMemoryInfo for ThreadLocalMerge<S> {
+	fn print_memory_info(&self) {
+		self.shared().print_memory_info();
 	}
 }
 
+impl <H: SketchHasher>PrintSamplingInfo for AtomicNitroCMS<FlowId,H> {}
+impl <S: BuildHasher + Clone>PrintSamplingInfo for DashMap<FlowId,u32,S> {}
+impl <S, H>PrintSamplingInfo for Striped<S, H> {}
+impl <S: PrintSamplingInfo + Clone>PrintSamplingInfo for ThreadLocalMerge<S> {
+	fn print_sampling_info(&self) {
+		self.shared().print_sampling_info();
+	}
+}
 
+impl <H: SketchHasher>DumpContents for AtomicNitroCMS<FlowId,H> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|(row, column, val)| (format!("{} {}", row, column), val)).collect()
+	}
+}
+impl <S: BuildHasher + Clone>DumpContents for DashMap<FlowId,u32,S> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter().map(|entry| (entry.key().to_string(), *entry.value())).collect()
+	}
+}
+/// Entries are prefixed by their stripe
+impl <S: DumpContents, H: Hasher + Default>DumpContents for Striped<S, H> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.iter()
+			.enumerate()
+			.flat_map(|(index, stripe)| stripe.dump_contents().into_iter().map(move |(label, val)| (format!("{} {}", index, label), val)))
+			.collect()
+	}
+}
+impl <S: DumpContents + Clone>DumpContents for ThreadLocalMerge<S> {
+	fn dump_contents(&self) -> Vec<(String, u32)> {
+		self.shared().dump_contents()
+	}
+}
 
 /// translate from a generic parameter to usize
 pub trait VtoUsize {
@@ -340,4 +1255,41 @@ macro_rules! impl_ipuip {
 	)*)
 }
 
-impl_ipuip!(u8 i8 u16 i16 u32 i32 u64 i64 u128 i128 usize isize);
\ No newline at end of file
+impl_ipuip!(u8 i8 u16 i16 u32 i32 u64 i64 u128 i128 usize isize);
+/// A counter of time-decayed (weighted) counts: floating point (f64), or scaled integer (u64, in fixed point with
+/// `SCALE_BITS` fractional bits), which is ordered and takes less care to add up exactly
+pub trait DecayCounter: Copy + Default + PartialOrd {
+	fn from_weight(weight: f64) -> Self;
+	fn to_weight(self) -> f64;
+	fn plus(self, other: Self) -> Self;
+	/// Multiply the counter by `factor` (below 1)
+	fn scale(&mut self, factor: f64);
+}
+impl DecayCounter for f64 {
+	fn from_weight(weight: f64) -> Self {
+		weight
+	}
+	fn to_weight(self) -> f64 {
+		self
+	}
+	fn plus(self, other: Self) -> Self {
+		self + other
+	}
+	fn scale(&mut self, factor: f64) {
+		*self *= factor;
+	}
+}
+impl DecayCounter for u64 {
+	fn from_weight(weight: f64) -> Self {
+		(weight * f64::from(1_u32 << SCALE_BITS)).round() as u64
+	}
+	fn to_weight(self) -> f64 {
+		self as f64 / f64::from(1_u32 << SCALE_BITS)
+	}
+	fn plus(self, other: Self) -> Self {
+		self.saturating_add(other)
+	}
+	fn scale(&mut self, factor: f64) {
+		*self = (*self as f64 * factor).round() as u64;
+	}
+}
@@ -0,0 +1,157 @@
+use std::hash::{BuildHasher, Hash};
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::RandomState;
+use super::space_saving::SpaceSaving;
+
+/// An implementation of Window Compact Space Saving (WCSS) of Ben Basat, Einziger, Friedman, and Kassner: frequency
+/// estimation over the last `window` items.
+/// The window is split into k = 1/error blocks. A space saving of k counters counts the items of the current frame
+/// (the stream is cut into frames of `window` items, and the space saving is reset at each frame), and whenever the count of
+/// an item in the frame reaches a multiple of the block size, an overflow of the item is recorded in the current block.
+/// The overflows of the blocks that entirely left the window are dropped at the end of each block. The estimate of an
+/// item is the block size times its overflows in the blocks overlapping the window, plus the remainder of its count in
+/// the frame, plus a block minus one item for the part of its count in the previous frame that did not overflow. It
+/// never falls below the count of the item in the window, and exceeds it by at most about three blocks (3*window/k).
+
+#[derive(Debug)]
+pub struct WCSS<K: Hash + Eq, S = RandomState> {
+    frame: SpaceSaving<K, u32, S>,
+    overflows: HashMap<K, u32, S>, // overflows of each item in the window
+    overflow_blocks: VecDeque<(u64, K)>, // the block of each overflow, oldest first
+    error: f64,
+    blocks: u64,
+    block_size: u64,
+    items: u64,
+}
+
+impl <K, S>WCSS<K, S>
+where
+K: Clone + Hash + Eq,
+S: BuildHasher + Default,
+{
+    /// Create an empty WCSS over the last `window` items, with 1/`error` counters (and blocks).
+    /// The window is rounded down to a multiple of the block size.
+    pub fn new(error: f64, window: usize) -> Self {
+        assert!(window > 0, "the window of a WCSS must hold at least one item");
+        let frame = SpaceSaving::new(error, false);
+        let blocks = frame.capacity().clamp(1, window) as u64;
+        Self {
+            frame,
+            overflows: HashMap::default(),
+            overflow_blocks: VecDeque::new(),
+            error,
+            blocks,
+            block_size: window as u64 / blocks,
+            items: 0,
+        }
+    }
+
+    /// insert an item into the window
+    pub fn insert(&mut self, id: K) {
+        let block = self.items / self.block_size;
+        self.frame.insert(id.clone());
+        if u64::from(self.frame.get(id.clone())).is_multiple_of(self.block_size) {
+            *self.overflows.entry(id.clone()).or_insert(0) += 1;
+            self.overflow_blocks.push_back((block, id));
+        }
+        self.items += 1;
+        if self.items.is_multiple_of(self.block_size) {
+            self.end_block();
+        }
+    }
+
+    // drop the overflows of the block that entirely left the window, and start a new frame at the end of a frame
+    fn end_block(&mut self) {
+        let block = self.items / self.block_size;
+        while let Some((overflow_block, id)) = self.overflow_blocks.front() {
+            if overflow_block + self.blocks >= block {
+                break;
+            }
+            if let Some(count) = self.overflows.get_mut(id) {
+                *count -= 1;
+                if *count == 0 {
+                    self.overflows.remove(id);
+                }
+            }
+            self.overflow_blocks.pop_front();
+        }
+        if block.is_multiple_of(self.blocks) {
+            self.frame = SpaceSaving::new(self.error, false);
+        }
+    }
+
+    /// return an estimate of an item's count in the window
+    pub fn get(&self, id: K) -> u32 {
+        let overflows = u64::from(self.overflows.get(&id).map_or(0, |count| *count));
+        let remainder = u64::from(self.frame.get(id)) % self.block_size;
+        u32::try_from(overflows * self.block_size + remainder + self.block_size - 1).unwrap()
+    }
+
+    /// returns the number of items in the window
+    pub fn window(&self) -> usize {
+        (self.blocks * self.block_size) as usize
+    }
+
+    /// returns the number of items in a block
+    pub fn block_size(&self) -> usize {
+        self.block_size as usize
+    }
+
+    /// returns the number of counters of the frame's space saving
+    pub fn capacity(&self) -> usize {
+        self.frame.capacity()
+    }
+
+    /// returns the number of overflows recorded in the window
+    pub fn overflow_count(&self) -> usize {
+        self.overflow_blocks.len()
+    }
+
+    /// returns the items overflowing in the window or monitored in the frame
+    pub fn keys(&self) -> Vec<K> {
+        let mut keys: Vec<K> = self.overflows.keys().cloned().collect();
+        keys.extend(self.frame.iter().map(|(id, _)| id).filter(|id| !self.overflows.contains_key(*id)).cloned());
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_window() {
+        let mut wcss = super::WCSS::<u32>::new(0.01, 10_000);
+        assert_eq!(wcss.block_size(), 100);
+        // an elephant that stops after the first window is forgotten
+        for item in 0..10_000_u32 {
+            wcss.insert(if item % 2 == 0 { 0 } else { 1 + item % 50 });
+        }
+        assert!(wcss.get(0).abs_diff(5000) <= 200, "{}", wcss.get(0));
+        for item in 0..10_000_u32 {
+            wcss.insert(1000 + item % 100);
+        }
+        assert!(wcss.get(0) <= 200, "{}", wcss.get(0));
+        assert!(wcss.get(1000).abs_diff(100) <= 200, "{}", wcss.get(1000));
+    }
+
+    #[test]
+    fn test_no_underestimate() {
+        // a skewed stream, with more distinct items than counters, spanning several frames
+        let trace: Vec<u32> = (0..50_000_u32).map(|item| if item % 3 == 0 { item % 7 } else { item.wrapping_mul(2_654_435_761) % 500 }).collect();
+        let mut wcss = super::WCSS::<u32>::new(0.05, 1000);
+        let window = wcss.window();
+        for (position, item) in trace.iter().enumerate() {
+            wcss.insert(*item);
+            let start = (position + 1).saturating_sub(window);
+            for id in [trace[start], *item, 0, 3] {
+                let count = trace[start..=position].iter().filter(|other| **other == id).count();
+                assert!(wcss.get(id) as usize >= count, "{} at {}: {} < {}", id, position, wcss.get(id), count);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one item")]
+    fn test_empty_window() {
+        super::WCSS::<u32>::new(0.01, 0);
+    }
+}
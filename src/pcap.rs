@@ -0,0 +1,127 @@
+use std::net::Ipv4Addr;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+/// The magic numbers of the (classic, not pcapng) pcap format, with microsecond and nanosecond timestamps
+const MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+
+const GLOBAL_HEADER_LEN: usize = 24;
+const RECORD_HEADER_LEN: usize = 16;
+
+/// The supported link types: Ethernet, raw IP (two numberings), IPv4 and Linux cooked captures
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_RAW_OPENBSD: u32 = 12;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: [u16; 3] = [0x8100, 0x88a8, 0x9100];
+
+/// Whether `bytes` start like a pcap file
+pub fn is_pcap(bytes: &[u8]) -> bool {
+    bytes.len() >= 4 && [MAGIC_MICROS, MAGIC_NANOS].iter().any(|magic| {
+        BigEndian::read_u32(bytes) == *magic || LittleEndian::read_u32(bytes) == *magic
+    })
+}
+
+/// Read the IPv4 packets of a pcap file as (source, destination, timestamp in seconds), skipping the other packets
+pub fn read_pcap(bytes: &[u8]) -> Result<Vec<(Ipv4Addr, Ipv4Addr, f64)>, String> {
+    if bytes.len() < GLOBAL_HEADER_LEN || !is_pcap(bytes) {
+        return Err("not a pcap file (pcapng is not supported)".to_string());
+    }
+    if BigEndian::read_u32(bytes) == MAGIC_MICROS || BigEndian::read_u32(bytes) == MAGIC_NANOS {
+        return read_records::<BigEndian>(bytes);
+    }
+    read_records::<LittleEndian>(bytes)
+}
+
+fn read_records<B: ByteOrder>(bytes: &[u8]) -> Result<Vec<(Ipv4Addr, Ipv4Addr, f64)>, String> {
+    let fraction_unit = if B::read_u32(bytes) == MAGIC_NANOS { 1e-9 } else { 1e-6 };
+    let link_type = B::read_u32(&bytes[20..]) & 0x0fff_ffff;
+    if ![LINKTYPE_ETHERNET, LINKTYPE_RAW, LINKTYPE_RAW_OPENBSD, LINKTYPE_LINUX_SLL, LINKTYPE_IPV4].contains(&link_type) {
+        return Err(format!("unsupported pcap link type {}", link_type));
+    }
+    let mut packets = Vec::new();
+    let mut offset = GLOBAL_HEADER_LEN;
+    while offset + RECORD_HEADER_LEN <= bytes.len() {
+        let header = &bytes[offset..offset + RECORD_HEADER_LEN];
+        let seconds = f64::from(B::read_u32(header));
+        let fraction = f64::from(B::read_u32(&header[4..]));
+        let captured = B::read_u32(&header[8..]) as usize;
+        offset += RECORD_HEADER_LEN;
+        if offset + captured > bytes.len() {
+            return Err(format!("truncated pcap record at byte {}", offset - RECORD_HEADER_LEN));
+        }
+        if let Some((srcip, dstip)) = ipv4_addresses(link_type, &bytes[offset..offset + captured]) {
+            packets.push((srcip, dstip, seconds + fraction * fraction_unit));
+        }
+        offset += captured;
+    }
+    Ok(packets)
+}
+
+/// The source and destination of an IPv4 packet, None for the other packets and for too short captures
+fn ipv4_addresses(link_type: u32, frame: &[u8]) -> Option<(Ipv4Addr, Ipv4Addr)> {
+    let ip = match link_type {
+        LINKTYPE_ETHERNET => {
+            // skip the MAC addresses and any VLAN tags
+            let mut offset = 12;
+            while ETHERTYPE_VLAN.contains(&BigEndian::read_u16(frame.get(offset..offset + 2)?)) {
+                offset += 4;
+            }
+            if BigEndian::read_u16(frame.get(offset..offset + 2)?) != ETHERTYPE_IPV4 {
+                return None;
+            }
+            &frame[offset + 2..]
+        }
+        LINKTYPE_LINUX_SLL => {
+            if BigEndian::read_u16(frame.get(14..16)?) != ETHERTYPE_IPV4 {
+                return None;
+            }
+            &frame[16..]
+        }
+        _ => frame,
+    };
+    if ip.len() < 20 || ip[0] >> 4 != 4 {
+        return None;
+    }
+    let srcip = Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]);
+    let dstip = Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]);
+    Some((srcip, dstip))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use byteorder::{ByteOrder, LittleEndian};
+
+    fn record(seconds: u32, micros: u32, frame: &[u8]) -> Vec<u8> {
+        let mut header = [0_u8; 16];
+        LittleEndian::write_u32(&mut header, seconds);
+        LittleEndian::write_u32(&mut header[4..], micros);
+        LittleEndian::write_u32(&mut header[8..], frame.len() as u32);
+        LittleEndian::write_u32(&mut header[12..], frame.len() as u32);
+        [&header[..], frame].concat()
+    }
+
+    #[test]
+    fn test_read_pcap() {
+        let mut global = [0_u8; 24];
+        LittleEndian::write_u32(&mut global, super::MAGIC_MICROS);
+        LittleEndian::write_u32(&mut global[20..], super::LINKTYPE_ETHERNET);
+        let mut ip = [0_u8; 20];
+        ip[0] = 0x45;
+        ip[12..16].copy_from_slice(&[10, 0, 0, 1]);
+        ip[16..20].copy_from_slice(&[192, 168, 1, 1]);
+        let ethernet = [&[0_u8; 12][..], &[0x08, 0x00], &ip].concat();
+        let vlan = [&[0_u8; 12][..], &[0x81, 0x00, 0, 7, 0x08, 0x00], &ip].concat();
+        let arp = [&[0_u8; 12][..], &[0x08, 0x06], &[0_u8; 28]].concat();
+        let bytes = [&global[..], &record(5, 250_000, &ethernet), &record(6, 0, &arp), &record(7, 500_000, &vlan)].concat();
+        assert!(super::is_pcap(&bytes));
+        let packets = super::read_pcap(&bytes).unwrap();
+        let addresses = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(192, 168, 1, 1));
+        assert_eq!(packets, vec![(addresses.0, addresses.1, 5.25), (addresses.0, addresses.1, 7.5)]);
+        assert!(super::read_pcap(&bytes[..bytes.len() - 1]).is_err());
+    }
+}